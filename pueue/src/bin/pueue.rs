@@ -81,8 +81,10 @@ async fn main() -> Result<()> {
     // Only show version incompatibility warnings if we aren't supposed to output json.
     let show_version_warning = match subcommand {
         SubCommand::Status { json, .. } => !json,
-        SubCommand::Log { json, .. } => !json,
+        SubCommand::Log { json, ndjson, .. } => !json && !ndjson,
         SubCommand::Group { json, .. } => !json,
+        SubCommand::Grep { json, .. } => !json,
+        SubCommand::Export { .. } => false,
         _ => true,
     };
 
@@ -2,13 +2,18 @@ use std::process::Command;
 
 use clap::Parser;
 use color_eyre::Result;
-use pueue::daemon::{cli::CliArguments, run};
+use pueue::daemon::{cli::CliArguments, run, supervisor::supervise};
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
 async fn main() -> Result<()> {
     // Parse commandline options.
     let opt = CliArguments::parse();
 
+    // Supervisors write their output into the task's log, which is why they don't set up tracing.
+    if let Some(result_path) = opt.supervise {
+        return supervise(result_path, opt.command).await;
+    }
+
     // Set the verbosity level of the logger.
     pueue::tracing::install_tracing(opt.verbose)?;
     color_eyre::install()?;
@@ -1,9 +1,9 @@
-use std::path::PathBuf;
+use std::{ops::Range, path::PathBuf};
 
 use chrono::{TimeDelta, prelude::*};
-use clap::{ArgAction, Parser, ValueEnum, ValueHint};
+use clap::{ArgAction, ArgGroup, Parser, ValueEnum, ValueHint};
 use interim::*;
-use pueue_lib::message::Signal;
+use pueue_lib::message::{Signal, TaskStatusFilter};
 
 use crate::client::commands::WaitTargetStatus;
 
@@ -87,6 +87,29 @@ pub enum SubCommand {
         #[arg(short, long)]
         label: Option<String>,
 
+        /// Allow the task to be killed and requeued, if it's preempted by a task with a higher
+        /// priority. Tasks that aren't restartable are paused instead.
+        #[arg(long)]
+        restartable: bool,
+
+        /// Never run this task at the same time as any other task with the same key.
+        ///
+        /// Unlike groups, this works across groups and doesn't require any setup.
+        /// E.g. use `deploy-<service>` to prevent concurrent deploys of the same service.
+        #[arg(short = 'k', long)]
+        concurrency_key: Option<String>,
+
+        /// Cancel all queued and stashed tasks with the same concurrency key, so only this task is
+        /// kept.
+        #[arg(long, requires = "concurrency_key")]
+        supersede: bool,
+
+        /// Record the time at which each line of the task's output is printed.
+        ///
+        /// The timestamps can be shown via `pueue log --timestamps`.
+        #[arg(long)]
+        timestamps: bool,
+
         /// Only return the task id instead of a text.
         ///
         /// This is useful when working with dependencies in scripts.
@@ -110,6 +133,77 @@ pub enum SubCommand {
         /// The second task id.
         task_id_2: usize,
     },
+    /// Move queued or stashed tasks to another group or to the front/back of their group.
+    ///
+    /// Task ids and dependencies stay intact.
+    /// Tasks that are moved to the front or back are processed before or after all other queued
+    /// and stashed tasks of their group, regardless of priority. Their priority isn't changed.
+    #[command(group(ArgGroup::new("destination").required(true).args(["group", "front", "back"])))]
+    Move {
+        /// The task ids to be moved.
+        #[arg(required = true)]
+        task_ids: Vec<usize>,
+
+        /// Move the tasks to this group.
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Move the tasks to the front of their group.
+        #[arg(short, long)]
+        front: bool,
+
+        /// Move the tasks to the back of their group.
+        #[arg(short, long)]
+        back: bool,
+    },
+    /// Print tasks and groups as json, so they can be imported into another daemon.
+    ///
+    /// By default, only queued and stashed tasks are exported.
+    /// Use `pueue import` to import the exported tasks.
+    Export {
+        /// Only export tasks of this group.
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Also export running, paused and finished tasks.
+        /// Running and paused tasks are stashed on import.
+        #[arg(short, long)]
+        all: bool,
+
+        /// Include the log output of the exported tasks.
+        #[arg(short, long)]
+        logs: bool,
+    },
+    /// Import tasks and groups that have been exported via `pueue export`.
+    ///
+    /// All tasks get new ids and their dependencies are adjusted accordingly.
+    /// Missing groups are created, existing groups are left untouched.
+    /// The import is a single atomic operation.
+    Import {
+        /// The path of the exported file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+    },
+    /// Bundle the logs and metadata of tasks into a gzipped tar archive.
+    ///
+    /// The archive contains a `manifest.json` with the daemon's version and the metadata of all
+    /// selected tasks, as well as the log of each task in the `logs` directory.
+    /// The values of the tasks' environment variables are redacted.
+    Bundle {
+        /// Only bundle tasks of this group.
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Only bundle tasks with this status.
+        ///
+        /// One of `queued`, `stashed`, `paused`, `running`, `success` or `failed`.
+        #[arg(short, long, ignore_case(true))]
+        status: Option<TaskStatusFilter>,
+
+        /// The path of the archive that should be created, e.g. `run.tar.gz`.
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+    },
     /// Stash a task. Stashed tasks won't be automatically started.
     ///
     /// The enqueue an item, use the `pueue enqueue` subcommand.
@@ -413,9 +507,17 @@ https://github.com/Nukesor/pueue/issues/350#issue-1359083118"
         /// By default only the last lines will be returned unless --full is provided.
         /// Take care, as the json cannot be streamed!
         /// If your logs are really huge, using --full can use all of your machine's RAM.
+        /// Use --ndjson for such logs instead.
         #[arg(short, long)]
         json: bool,
 
+        /// Print each task and its output as a separate json object on its own line.
+        ///
+        /// The tasks are streamed one by one, so only a single task's output is held in memory
+        /// at any time.
+        #[arg(long, conflicts_with = "json")]
+        ndjson: bool,
+
         /// Only print the last X lines of each task's output.
         ///
         /// This is done by default if you're looking at multiple tasks.
@@ -425,21 +527,114 @@ https://github.com/Nukesor/pueue/issues/350#issue-1359083118"
         /// Show the whole output.
         #[arg(short, long)]
         full: bool,
+
+        /// Only show the stdout of the tasks.
+        ///
+        /// This only works for tasks whose output streams are captured separately.
+        /// Take a look at the `separate_stderr` option of the daemon.
+        #[arg(long, conflicts_with = "stderr")]
+        stdout: bool,
+
+        /// Only show the stderr of the tasks.
+        ///
+        /// This only works for tasks whose output streams are captured separately.
+        /// Take a look at the `separate_stderr` option of the daemon.
+        #[arg(long)]
+        stderr: bool,
+
+        /// Prefix each line with the time it has been printed.
+        ///
+        /// This only works for tasks whose output is timestamped.
+        /// Take a look at `pueue add --timestamps` and the `timestamp_output` option of the daemon.
+        #[arg(long)]
+        timestamps: bool,
+
+        /// Only show lines that have been printed since then.
+        ///
+        /// Either a duration like "30s", "10m", "2h" or "1d", or a date expression like
+        /// "today 12:00".
+        /// This only works for tasks whose output is timestamped.
+        #[arg(long, value_parser = parse_since)]
+        since: Option<DateTime<Local>>,
+
+        /// Only show this byte range of the output, e.g. "1000..2000" or "1000..".
+        ///
+        /// Offsets are counted from the start of the task's whole output, which allows to page
+        /// through huge logs. The output is always fetched from the daemon.
+        #[arg(
+            long,
+            value_parser = parse_byte_range,
+            conflicts_with_all = ["lines", "full", "timestamps", "since"]
+        )]
+        bytes: Option<Range<u64>>,
     },
 
     /// Follow the output of a currently running task.
     /// This command works like "tail -f".
+    ///
+    /// If several tasks are followed, their output is interleaved and each line is prefixed
+    /// with the id and label of its task. The output of multiple tasks is always streamed by
+    /// the daemon.
     #[command(alias("fo"))]
     Follow {
-        /// The id of the task you want to watch.
+        /// The ids of the tasks you want to watch.
         ///
         /// If no or multiple tasks are running, you have to specify the id.
         /// If only a single task is running, you can omit the id.
-        task_id: Option<usize>,
+        task_ids: Vec<usize>,
+
+        /// Follow all running tasks of this group, including tasks that are started later on.
+        #[arg(short, long, conflicts_with = "task_ids")]
+        group: Option<String>,
 
         /// Only print the last X lines of the output before following
         #[arg(short, long)]
         lines: Option<usize>,
+
+        /// Only follow the stdout of the task.
+        ///
+        /// This only works for tasks whose output streams are captured separately.
+        #[arg(long, conflicts_with = "stderr")]
+        stdout: bool,
+
+        /// Only follow the stderr of the task.
+        ///
+        /// This only works for tasks whose output streams are captured separately.
+        #[arg(long)]
+        stderr: bool,
+
+        /// Prefix each line with the time it has been printed.
+        ///
+        /// This only works for tasks whose output is timestamped.
+        #[arg(long)]
+        timestamps: bool,
+    },
+
+    /// Search the output of tasks for lines matching a regular expression.
+    ///
+    /// The logs of all tasks are searched by default.
+    /// Logs that have been rotated or compressed are searched as well.
+    Grep {
+        /// The regular expression to search for.
+        pattern: String,
+
+        /// Only search the logs of this group's tasks.
+        #[arg(short, long)]
+        group: Option<String>,
+
+        /// Only search the logs of tasks with this status.
+        ///
+        /// One of `queued`, `stashed`, `paused`, `running`, `success` or `failed`.
+        #[arg(short, long, ignore_case(true))]
+        status: Option<TaskStatusFilter>,
+
+        /// Show this many lines before and after each matching line, at most 10000.
+        #[arg(short = 'C', long, default_value_t = 0)]
+        context: usize,
+
+        /// Print the matching lines as json.
+        #[arg(short, long)]
+        json: bool,
     },
 
     /// Wait until tasks are finished.
@@ -496,6 +691,12 @@ https://github.com/Nukesor/pueue/issues/350#issue-1359083118"
     /// manager.
     Shutdown,
 
+    /// Manage the running daemon.
+    Daemon {
+        #[command(subcommand)]
+        cmd: DaemonCommand,
+    },
+
     /// Set the amount of allowed parallel tasks
     ///
     /// By default, adjusts the amount of the default group.
@@ -550,6 +751,16 @@ pub enum EnvCommand {
     },
 }
 
+#[derive(Parser, Debug, Clone)]
+pub enum DaemonCommand {
+    /// Re-read the daemon's configuration file, including the active profile, and apply all
+    /// changed settings. Sending SIGHUP to the daemon does the same.
+    ///
+    /// Settings that affect how the daemon is reached, such as the socket path, the host or the
+    /// pueue directory, only take effect after a restart of the daemon.
+    Reload,
+}
+
 #[derive(Parser, Debug, Clone)]
 pub enum GroupCommand {
     /// Add a group by name.
@@ -566,6 +777,37 @@ pub enum GroupCommand {
     /// Remove a group by name.
     /// This will move all tasks in this group to the default group!
     Remove { name: String },
+
+    /// Rename a group.
+    /// All tasks of this group, including running ones, are moved to the new name.
+    Rename { name: String, new_name: String },
+
+    /// Enable or disable priority preemption for a group.
+    ///
+    /// If all slots of the group are taken, a queued task whose priority exceeds the priority of
+    /// a running task by at least the given margin preempts that task.
+    /// Preempted tasks are paused and resumed once a slot is free again.
+    /// Tasks added with `--restartable` are killed and requeued instead.
+    ///
+    /// Omit the margin to disable preemption.
+    Preempt {
+        name: String,
+
+        /// The minimum priority difference that's required to preempt a running task.
+        #[arg(short, long)]
+        margin: Option<i32>,
+    },
+
+    /// Limit the amount of queued and stashed tasks of a group.
+    ///
+    /// New tasks are rejected once the limit is reached.
+    /// Omit the limit to allow an unlimited amount of queued tasks.
+    MaxQueued {
+        name: String,
+
+        /// The maximum amount of queued and stashed tasks.
+        limit: Option<usize>,
+    },
 }
 
 #[derive(Parser, ValueEnum, Debug, Clone, PartialEq, Eq)]
@@ -635,3 +877,44 @@ fn parse_delay_until(src: &str) -> Result<DateTime<Local>, String> {
         "could not parse as seconds or date expression",
     ))
 }
+
+/// Parse a point in the past, either as a duration like "10m" or as a date expression.
+fn parse_since(src: &str) -> Result<DateTime<Local>, String> {
+    let unit = src.chars().last().unwrap_or_default();
+    if let Ok(amount) = src[..src.len() - unit.len_utf8()].parse::<i64>() {
+        let delta = match unit {
+            's' => TimeDelta::try_seconds(amount),
+            'm' => TimeDelta::try_minutes(amount),
+            'h' => TimeDelta::try_hours(amount),
+            'd' => TimeDelta::try_days(amount),
+            _ => None,
+        };
+        if let Some(delta) = delta {
+            return Ok(Local::now() - delta);
+        }
+    }
+
+    if let Ok(date_time) = parse_date_string(src, Local::now(), Dialect::Us) {
+        return Ok(date_time);
+    }
+
+    Err(String::from(
+        "could not parse as duration (e.g. 10m) or date expression",
+    ))
+}
+
+/// Parse a byte range like "1000..2000". The end may be omitted to read until the end.
+fn parse_byte_range(src: &str) -> Result<Range<u64>, String> {
+    let Some((start, end)) = src.split_once("..") else {
+        return Err(String::from("expected a range like 1000..2000"));
+    };
+
+    let start = start.parse::<u64>().map_err(|err| err.to_string())?;
+    let end = if end.is_empty() {
+        u64::MAX
+    } else {
+        end.parse::<u64>().map_err(|err| err.to_string())?
+    };
+
+    Ok(start..end)
+}
@@ -29,16 +29,21 @@ pub async fn add_task(
     dependencies: Vec<usize>,
     priority: Option<i32>,
     label: Option<String>,
+    restartable: bool,
+    concurrency_key: Option<String>,
+    supersede: bool,
+    timestamps: bool,
     print_task_id: bool,
     follow: bool,
 ) -> Result<()> {
     // Either take the user-specified path or default to the current working directory.
+    // In the latter case, the daemon uses the working directory of the group instead, if the
+    // group declares one.
     // This will give errors if connecting over TCP/TLS to a remote host that doesn't
     // have the same directory structure as the client
-    let path = working_directory
-        .as_ref()
-        .map(|path| Ok(path.clone()))
-        .unwrap_or_else(current_dir)?;
+    let group = group_or_default(&group);
+    let default_path = working_directory.is_none();
+    let path = working_directory.map(Ok).unwrap_or_else(current_dir)?;
 
     // The user can request to escape any special shell characters in all parameter
     // strings before we concatenated them to a single string.
@@ -53,15 +58,20 @@ pub async fn add_task(
     let message = Request::Add(AddRequest {
         command: command.join(" "),
         path,
+        default_path,
         // Catch the current environment for later injection into the task's process.
         envs: HashMap::from_iter(vars()),
         start_immediately,
         stashed,
-        group: group_or_default(&group),
+        group,
         enqueue_at: delay_until,
         dependencies,
         priority,
         label,
+        restartable,
+        concurrency_key,
+        supersede,
+        timestamps,
     });
     client.send_request(message).await?;
 
@@ -103,7 +113,17 @@ pub async fn add_task(
     }
 
     if follow {
-        follow_cmd(client, settings, style, Some(task_id), None).await?;
+        follow_cmd(
+            client,
+            settings,
+            style,
+            vec![task_id],
+            None,
+            None,
+            None,
+            timestamps,
+        )
+        .await?;
     }
 
     Ok(())
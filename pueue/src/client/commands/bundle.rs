@@ -0,0 +1,205 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+use crossterm::style::Color;
+use flate2::{Compression, write::GzEncoder};
+use pueue_lib::{
+    Client, Task, TaskResult, TaskStatus,
+    message::{LogRequest, Response, TaskSelection, TaskStatusFilter},
+};
+use serde::Serialize;
+use snap::read::FrameDecoder;
+use tar::{Builder, Header};
+
+use super::{get_state, handle_response};
+use crate::{client::style::OutputStyle, internal_prelude::*};
+
+/// The manifest that's written to `manifest.json` at the root of the bundle.
+#[derive(Debug, Serialize)]
+struct Manifest {
+    daemon_version: String,
+    created_at: DateTime<Local>,
+    tasks: Vec<TaskManifest>,
+}
+
+/// The metadata of a single bundled task.
+#[derive(Debug, Serialize)]
+struct TaskManifest {
+    id: usize,
+    label: Option<String>,
+    group: String,
+    command: String,
+    path: PathBuf,
+    status: String,
+    result: Option<TaskResult>,
+    created_at: DateTime<Local>,
+    start: Option<DateTime<Local>>,
+    end: Option<DateTime<Local>>,
+    dependencies: Vec<usize>,
+    /// Only the names of the environment variables, their values are redacted.
+    envs: Vec<String>,
+    /// The paths of the task's logs inside the bundle.
+    logs: Vec<String>,
+}
+
+impl From<&Task> for TaskManifest {
+    fn from(task: &Task) -> Self {
+        let (start, end) = task.start_and_end();
+        let result = match &task.status {
+            TaskStatus::Done { result, .. } => Some(result.clone()),
+            _ => None,
+        };
+        let mut envs: Vec<String> = task.envs.iter().map(|(name, _)| name.clone()).collect();
+        envs.sort();
+
+        TaskManifest {
+            id: task.id,
+            label: task.label.clone(),
+            group: task.group.clone(),
+            command: task.command.clone(),
+            path: task.path.clone(),
+            status: task.status.to_string(),
+            result,
+            created_at: task.created_at,
+            start,
+            end,
+            dependencies: task.dependencies.clone(),
+            envs,
+            logs: Vec::new(),
+        }
+    }
+}
+
+/// Bundle the logs and metadata of the selected tasks into a gzipped tar archive.
+///
+/// Everything is fetched from the daemon, so this works the same for local and remote daemons.
+/// The logs are streamed by the daemon one task at a time and directly written to the archive,
+/// so only a single log has to be held in memory.
+pub async fn bundle(
+    client: &mut Client,
+    style: &OutputStyle,
+    group: Option<String>,
+    status: Option<TaskStatusFilter>,
+    output: PathBuf,
+) -> Result<()> {
+    let state = get_state(client).await?;
+    if let Some(group) = group
+        .as_ref()
+        .filter(|group| !state.groups.contains_key(*group))
+    {
+        bail!("Group {group} doesn't exist");
+    }
+
+    let mut tasks: Vec<TaskManifest> = state
+        .tasks
+        .values()
+        .filter(|task| group.as_ref().is_none_or(|group| &task.group == group))
+        .filter(|task| status.is_none_or(|status| status.matches(task)))
+        .map(TaskManifest::from)
+        .collect();
+
+    // The bundle is written to a temporary file next to the output first, which is only moved to
+    // the output once the bundle is complete. That way, failures don't leave a partial bundle.
+    let directory = output
+        .parent()
+        .filter(|directory| !directory.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file = tempfile::Builder::new()
+        .prefix(".pueue-bundle")
+        .tempfile_in(directory)
+        .context(format!("Failed to create bundle in {directory:?}"))?;
+    let mut archive = Builder::new(GzEncoder::new(file, Compression::default()));
+    let mtime = Local::now().timestamp().max(0) as u64;
+
+    if !tasks.is_empty() {
+        client
+            .send_request(LogRequest {
+                tasks: TaskSelection::TaskIds(tasks.iter().map(|task| task.id).collect()),
+                send_logs: true,
+                lines: None,
+                stream: None,
+                timestamps: false,
+                since: None,
+                bytes: None,
+                streamed: true,
+            })
+            .await?;
+
+        loop {
+            let task_logs = match client.receive_response().await? {
+                Response::Log(task_logs) => task_logs,
+                Response::Close => break,
+                response => return handle_response(style, response),
+            };
+
+            for (id, task_log) in task_logs {
+                let Some(task) = tasks.iter_mut().find(|task| task.id == id) else {
+                    continue;
+                };
+
+                // Tasks with separately captured output streams get one log per stream.
+                let logs = match task_log.stderr {
+                    Some(stderr) => vec![
+                        (format!("logs/{id}.stdout.log"), task_log.output),
+                        (format!("logs/{id}.stderr.log"), Some(stderr)),
+                    ],
+                    None => vec![(format!("logs/{id}.log"), task_log.output)],
+                };
+
+                for (path, bytes) in logs {
+                    let Some(bytes) = bytes else {
+                        continue;
+                    };
+                    let mut log = Vec::new();
+                    FrameDecoder::new(&bytes[..])
+                        .read_to_end(&mut log)
+                        .context(format!("Failed to decompress log of task {id}"))?;
+
+                    append_file(&mut archive, &path, &log, mtime)
+                        .context(format!("Failed to write log of task {id} to bundle"))?;
+                    task.logs.push(path);
+                }
+            }
+        }
+    }
+
+    let task_count = tasks.len();
+    let manifest = Manifest {
+        daemon_version: client.daemon_version().clone(),
+        created_at: Local::now(),
+        tasks,
+    };
+    let manifest = serde_json::to_string_pretty(&manifest)?;
+    append_file(&mut archive, "manifest.json", manifest.as_bytes(), mtime)
+        .context("Failed to write manifest to bundle")?;
+
+    archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .context("Failed to write bundle")?
+        .persist(&output)
+        .context(format!("Failed to write bundle to {output:?}"))?;
+
+    let text = format!("Bundled {task_count} tasks into {output:?}");
+    println!("{}", style.style_text(text, Some(Color::Green), None));
+
+    Ok(())
+}
+
+/// Append a regular file with the given path and content to the archive.
+fn append_file<W: Write>(
+    archive: &mut Builder<W>,
+    path: &str,
+    data: &[u8],
+    mtime: u64,
+) -> std::io::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+
+    archive.append_data(&mut header, path, data)
+}
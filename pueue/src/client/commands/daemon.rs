@@ -0,0 +1,18 @@
+use pueue_lib::{Client, message::*};
+
+use super::handle_response;
+use crate::{
+    client::{cli::DaemonCommand, style::OutputStyle},
+    internal_prelude::*,
+};
+
+/// Manage the running daemon.
+pub async fn daemon(client: &mut Client, style: &OutputStyle, cmd: DaemonCommand) -> Result<()> {
+    match cmd {
+        DaemonCommand::Reload => client.send_request(Request::DaemonReload).await?,
+    }
+
+    let response = client.receive_response().await?;
+
+    handle_response(style, response)
+}
@@ -0,0 +1,86 @@
+use std::{collections::BTreeMap, io::Read};
+
+use pueue_lib::{
+    Client, Task,
+    message::{ImportRequest, LogRequest, Response, TaskSelection},
+};
+use snap::read::FrameDecoder;
+
+use super::get_state;
+use crate::internal_prelude::*;
+
+/// Print tasks and groups as json, so they can be imported into another daemon via
+/// `pueue import`.
+///
+/// By default, only queued and stashed tasks are exported.
+pub async fn export(
+    client: &mut Client,
+    group: Option<String>,
+    all: bool,
+    logs: bool,
+) -> Result<()> {
+    let state = get_state(client).await?;
+
+    let tasks: BTreeMap<usize, Task> = state
+        .tasks
+        .into_iter()
+        .filter(|(_, task)| group.as_ref().is_none_or(|group| &task.group == group))
+        .filter(|(_, task)| all || task.is_queued() || task.is_stashed())
+        .collect();
+
+    // Export all groups of the exported tasks, as well as the selected group.
+    let groups = state
+        .groups
+        .into_iter()
+        .filter(|(name, _)| {
+            group.as_ref() == Some(name) || tasks.values().any(|task| &task.group == name)
+        })
+        .collect();
+
+    let mut export = ImportRequest {
+        tasks,
+        groups,
+        logs: BTreeMap::new(),
+    };
+
+    if logs && !export.tasks.is_empty() {
+        client
+            .send_request(LogRequest {
+                tasks: TaskSelection::TaskIds(export.tasks.keys().copied().collect()),
+                send_logs: true,
+                lines: None,
+                stream: None,
+                timestamps: false,
+                since: None,
+                bytes: None,
+                streamed: false,
+            })
+            .await?;
+
+        let Response::Log(task_logs) = client.receive_response().await? else {
+            bail!("Received unexpected response while fetching logs");
+        };
+
+        for (id, task_log) in task_logs {
+            let Some(bytes) = task_log.output else {
+                continue;
+            };
+
+            let mut output = String::new();
+            FrameDecoder::new(&bytes[..])
+                .read_to_string(&mut output)
+                .context(format!("Failed to decompress log of task {id}"))?;
+            // Separately captured stderr is appended, as imported tasks only have a single log.
+            if let Some(bytes) = task_log.stderr {
+                FrameDecoder::new(&bytes[..])
+                    .read_to_string(&mut output)
+                    .context(format!("Failed to decompress stderr of task {id}"))?;
+            }
+            export.logs.insert(id, output);
+        }
+    }
+
+    println!("{}", serde_json::to_string_pretty(&export)?);
+
+    Ok(())
+}
@@ -1,12 +1,17 @@
 use std::{
-    io::{self, Write},
+    collections::BTreeMap,
+    io::{self, Read, Write},
     time::Duration,
 };
 
+use crossterm::style::Color;
 use pueue_lib::{
     Client, Response, Settings,
-    log::{get_log_file_handle, get_log_path, seek_to_last_lines},
+    log::{LogReader, TimestampReader, get_log_paths, log_exists, seek_to_last_lines},
     message::{StreamRequest, TaskSelection},
+    network::socket::ConnectionSettings,
+    secret::read_shared_secret,
+    task::LogStream,
 };
 use tokio::time::sleep;
 
@@ -23,53 +28,124 @@ use crate::{
 ///
 /// Log files may be read directly on the local machine, but they may also be streamed via the
 /// daemon in case they're somewhere inaccessible or on a remote machine.
+#[allow(clippy::too_many_arguments)]
 pub async fn follow(
     client: &mut Client,
     settings: Settings,
     style: &OutputStyle,
-    task_id: Option<usize>,
+    task_ids: Vec<usize>,
+    group: Option<String>,
     lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
 ) -> Result<()> {
     // If we're supposed to read the log files from the local system, we don't have to
     // do any communication with the daemon.
     // Thereby we handle this in a separate function.
-    if settings.client.read_local_logs {
-        local_follow(client, settings, task_id, lines).await?;
+    //
+    // Multiple tasks are always streamed by the daemon, as it knows when tasks are started.
+    if settings.client.read_local_logs && task_ids.len() <= 1 && group.is_none() {
+        let task_id = task_ids.first().copied();
+        local_follow(client, settings, task_id, lines, stream, timestamps).await?;
         return Ok(());
     }
 
-    remote_follow(client, style, task_id, lines).await
+    let selection = match group {
+        Some(group) => TaskSelection::Group(group),
+        None => TaskSelection::TaskIds(task_ids),
+    };
+    remote_follow(
+        client, &settings, style, selection, lines, stream, timestamps,
+    )
+    .await
 }
 
 /// Request the daemon to stream log files for some tasks.
 ///
-/// This receives log output until the connection goes away or is explicitly closed by the daemon
-/// once the task finishes.
+/// This receives log output until the connection is explicitly closed by the daemon once the
+/// tasks finish. If the connection is lost in the meantime, the client reconnects and resumes
+/// the stream at the byte offsets it has received last.
+///
+/// If several tasks are followed, each line is prefixed with the id and label of its task.
 pub async fn remote_follow(
     client: &mut Client,
+    settings: &Settings,
     style: &OutputStyle,
-    task_id: Option<usize>,
+    selection: TaskSelection,
     lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
 ) -> Result<()> {
-    let task_ids = task_id.map(|id| vec![id]).unwrap_or_default();
+    let prefixed = !matches!(&selection, TaskSelection::TaskIds(task_ids) if task_ids.len() <= 1);
 
     // Request the log stream.
-    client
-        .send_request(StreamRequest {
-            tasks: TaskSelection::TaskIds(task_ids),
-            lines,
-        })
-        .await?;
-
-    // Receive the stream until the connection is closed, breaks or another failure appears.
+    let mut request = StreamRequest {
+        tasks: selection,
+        lines,
+        stream,
+        timestamps,
+        offsets: BTreeMap::new(),
+    };
+    client.send_request(request.clone()).await?;
+
+    // The incomplete last line of each task's output, which is printed once it's complete.
+    // Otherwise lines of different tasks could end up mixed into each other.
+    let mut partial_lines: BTreeMap<usize, String> = BTreeMap::new();
+    let mut labels: BTreeMap<usize, String> = BTreeMap::new();
+
+    // Receive the stream until the connection is closed or another failure appears.
+    let mut reconnects = 0;
     loop {
-        let response = client.receive_response().await?;
+        let response = match client.receive_response().await {
+            Ok(response) => response,
+            Err(err) => {
+                reconnects += 1;
+                if reconnects > MAX_RECONNECTS {
+                    return Err(err).context("Lost the connection to the daemon.");
+                }
+
+                eprintln!("Pueue: Lost the connection to the daemon, reconnecting...");
+                sleep(Duration::from_secs(1)).await;
+                if let Ok(new_client) = reconnect(settings).await {
+                    *client = new_client;
+                    // If this fails, the next attempt is made once receiving fails as well.
+                    let _ = client.send_request(request.clone()).await;
+                }
+                continue;
+            }
+        };
+
         match response {
             Response::Stream(response) => {
-                for (_, text) in response.logs {
-                    print!("{text}");
-                    io::stdout().flush().unwrap();
+                reconnects = 0;
+                // The daemon might've picked the followed task, so make sure to resume that one.
+                if request.tasks == TaskSelection::TaskIds(Vec::new()) {
+                    request.tasks = TaskSelection::TaskIds(response.logs.keys().copied().collect());
                 }
+                // Remember where the stream left off.
+                request.offsets.extend(response.offsets);
+                labels.extend(response.labels);
+
+                for (task_id, text) in response.logs {
+                    if !prefixed {
+                        print!("{text}");
+                        continue;
+                    }
+
+                    let partial = partial_lines.entry(task_id).or_default();
+                    partial.push_str(&text);
+                    // Only print complete lines and keep the rest for later.
+                    let Some(end) = partial.rfind('\n') else {
+                        continue;
+                    };
+                    let rest = partial.split_off(end + 1);
+                    let prefix = task_prefix(style, task_id, labels.get(&task_id));
+                    for line in partial.split_inclusive('\n') {
+                        print!("{prefix}{line}");
+                    }
+                    *partial = rest;
+                }
+                io::stdout().flush().unwrap();
                 continue;
             }
             Response::Close => break,
@@ -81,9 +157,49 @@ pub async fn remote_follow(
         }
     }
 
+    // Print the last lines of tasks, that didn't end with a newline.
+    for (task_id, partial) in partial_lines {
+        if !partial.is_empty() {
+            let prefix = task_prefix(style, task_id, labels.get(&task_id));
+            println!("{prefix}{partial}");
+        }
+    }
+
     Ok(())
 }
 
+/// The `[id|label]` prefix of a task's lines, if several tasks are followed.
+/// Each task gets its own color, so the interleaved output is easier to tell apart.
+fn task_prefix(style: &OutputStyle, task_id: usize, label: Option<&String>) -> String {
+    const COLORS: [Color; 6] = [
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Red,
+    ];
+
+    let prefix = match label {
+        Some(label) => format!("[{task_id}|{label}]"),
+        None => format!("[{task_id}]"),
+    };
+    let color = COLORS[task_id % COLORS.len()];
+
+    format!("{} ", style.style_text(prefix, Some(color), None))
+}
+
+/// How often the client tries to reconnect, after the connection to the daemon has been lost.
+const MAX_RECONNECTS: usize = 10;
+
+/// Establish a new connection to the daemon, e.g. after the previous one has been lost.
+async fn reconnect(settings: &Settings) -> Result<Client> {
+    let connection_settings = ConnectionSettings::try_from(settings.shared.clone())?;
+    let secret = read_shared_secret(&settings.shared.shared_secret_path())?;
+
+    Client::new(connection_settings, &secret, false).await
+}
+
 /// This function reads a log file from the filesystem and streams it to `stdout`.
 /// This is the default behavior of `pueue`'s log reading logic, which is only possible
 /// if `pueued` runs on the same environment.
@@ -96,6 +212,8 @@ pub async fn local_follow(
     settings: Settings,
     task_id: Option<usize>,
     lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
 ) -> Result<()> {
     let task_id = match task_id {
         Some(task_id) => task_id,
@@ -128,7 +246,7 @@ pub async fn local_follow(
         }
     };
 
-    follow_local_task_logs(client, settings, task_id, lines).await?;
+    follow_local_task_logs(client, settings, task_id, lines, stream, timestamps).await?;
 
     Ok(())
 }
@@ -145,6 +263,8 @@ pub async fn follow_local_task_logs(
     settings: Settings,
     task_id: usize,
     lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
 ) -> Result<()> {
     let pueue_directory = &settings.shared.pueue_directory();
     // It might be that the task is not yet running.
@@ -161,29 +281,43 @@ pub async fn follow_local_task_logs(
         sleep(Duration::from_millis(1000)).await;
     }
 
-    let mut handle = match get_log_file_handle(task_id, pueue_directory) {
-        Ok(stdout) => stdout,
+    // If the task's streams are captured separately, both files are followed by default.
+    let paths = match get_log_paths(task_id, pueue_directory, stream) {
+        Ok(paths) => paths,
         Err(err) => {
-            eprintln!("Failed to get log file handles: {err}");
-            return Ok(());
+            eprintln!("Pueue: {err}");
+            std::process::exit(1);
         }
     };
-    let path = get_log_path(task_id, pueue_directory);
 
-    // Stdout handle to directly stream log file output to `io::stdout`.
-    // This prevents us from allocating any large amounts of memory.
-    let mut stdout = io::stdout();
+    let mut handles = Vec::new();
+    for path in paths {
+        let mut handle = match LogReader::open(&path) {
+            Ok(handle) => handle,
+            Err(err) => {
+                eprintln!("Failed to get log file handles: {err}");
+                return Ok(());
+            }
+        };
 
-    // If `lines` is passed as an option, we only want to show the last `X` lines.
-    // To achieve this, we seek the file handle to the start of the `Xth` line
-    // from the end of the file.
-    // The loop following this section will then only copy those last lines to stdout.
-    if let Some(lines) = lines {
-        if let Err(err) = seek_to_last_lines(&mut handle, lines) {
-            eprintln!("Error seeking to last lines from log: {err}");
+        // If `lines` is passed as an option, we only want to show the last `X` lines.
+        // To achieve this, we seek the file handle to the start of the `Xth` line
+        // from the end of the file.
+        // The loop following this section will then only copy those last lines to stdout.
+        if let Some(lines) = lines {
+            if let Err(err) = seek_to_last_lines(&mut handle, lines) {
+                eprintln!("Error seeking to last lines from log: {err}");
+            }
         }
+
+        let timestamp_reader = TimestampReader::new(&path);
+        handles.push((path, handle, timestamp_reader));
     }
 
+    // Stdout handle to directly stream log file output to `io::stdout`.
+    // This prevents us from allocating any large amounts of memory.
+    let mut stdout = io::stdout();
+
     // The interval at which the task log is checked and streamed to stdout.
     let log_check_interval = 250;
 
@@ -193,16 +327,41 @@ pub async fn follow_local_task_logs(
     let task_check_interval = log_check_interval * 2;
     let mut last_check = 0;
     loop {
-        // Check whether the file still exists. Exit if it doesn't.
-        if !path.exists() {
-            eprintln!("Pueue: Log file has gone away. Has the task been removed?");
-            return Ok(());
+        for (path, handle, timestamp_reader) in handles.iter_mut() {
+            // Check whether the file still exists. Exit if it doesn't.
+            if !log_exists(path) {
+                eprintln!("Pueue: Log file has gone away. Has the task been removed?");
+                return Ok(());
+            }
+
+            // Continue in the rotated segment, if the log has been rotated in the meantime.
+            if let Err(err) = handle.refresh() {
+                eprintln!("Pueue: Error while reopening rotated log: {err}");
+                return Ok(());
+            }
+
+            // Timestamps have to be inserted, so the new output is read into memory first.
+            if timestamps {
+                let offset = handle.offset();
+                let mut buffer = Vec::new();
+                if let Err(err) = handle.read_to_end(&mut buffer) {
+                    eprintln!("Pueue: Error while reading file: {err}");
+                    return Ok(());
+                };
+                if let Err(err) = timestamp_reader.update() {
+                    eprintln!("Pueue: Error while reading timestamps: {err}");
+                    return Ok(());
+                };
+                print!("{}", timestamp_reader.render(&buffer, offset, true, None));
+                continue;
+            }
+
+            // Read the next chunk of text from the last position.
+            if let Err(err) = io::copy(handle, &mut stdout) {
+                eprintln!("Pueue: Error while reading file: {err}");
+                return Ok(());
+            };
         }
-        // Read the next chunk of text from the last position.
-        if let Err(err) = io::copy(&mut handle, &mut stdout) {
-            eprintln!("Pueue: Error while reading file: {err}");
-            return Ok(());
-        };
         // Flush the stdout buffer to actually print the output.
         if let Err(err) = stdout.flush() {
             eprintln!("Pueue: Error while flushing stdout: {err}");
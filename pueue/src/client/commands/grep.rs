@@ -0,0 +1,91 @@
+use crossterm::style::{Attribute, Color};
+use pueue_lib::{Client, message::*};
+
+use super::handle_response;
+use crate::{client::style::OutputStyle, internal_prelude::*};
+
+/// Search the logs of tasks for lines matching a regular expression.
+///
+/// The output is similar to that of `grep -n`.
+/// Matching lines are printed as `line_number:text`, context lines as `line_number-text` and
+/// non-consecutive blocks of lines are separated by `--`.
+pub async fn grep(
+    client: &mut Client,
+    style: &OutputStyle,
+    pattern: String,
+    group: Option<String>,
+    status: Option<TaskStatusFilter>,
+    context: usize,
+    json: bool,
+) -> Result<()> {
+    let tasks = match group {
+        Some(group) => TaskSelection::Group(group),
+        None => TaskSelection::All,
+    };
+    let request = GrepRequest {
+        pattern,
+        tasks,
+        status,
+        context,
+    };
+    client.send_request(request).await?;
+
+    let response = client.receive_response().await?;
+
+    let Response::Grep(task_responses) = response else {
+        return handle_response(style, response);
+    };
+
+    if json {
+        println!("{}", serde_json::to_string(&task_responses).unwrap());
+        return Ok(());
+    }
+
+    let mut first = true;
+    for task_response in task_responses {
+        if !first {
+            println!();
+        }
+        first = false;
+        print_task_matches(&task_response, style);
+    }
+
+    Ok(())
+}
+
+/// Print the matching lines of a single task, preceded by a header with its id and label.
+fn print_task_matches(task_response: &GrepTaskResponse, style: &OutputStyle) {
+    let mut header = format!("Task {}", task_response.task_id);
+    if let Some(label) = &task_response.label {
+        header.push_str(&format!(" ({label})"));
+    }
+    let header = style.style_text(
+        format!("{header}:"),
+        Some(Color::Green),
+        Some(Attribute::Bold),
+    );
+    println!("{header}");
+
+    let mut previous: Option<&GrepLine> = None;
+    for line in task_response.lines.iter() {
+        // Separate blocks of lines that aren't directly following each other.
+        let consecutive = previous.is_none_or(|previous| {
+            previous.stream == line.stream && previous.line_number + 1 == line.line_number
+        });
+        if !consecutive {
+            println!("--");
+        }
+        previous = Some(line);
+
+        let separator = if line.is_match { ':' } else { '-' };
+        let mut prefix = format!("{}{separator}", line.line_number);
+        if let Some(stream) = line.stream {
+            prefix = format!("{stream}{separator}{prefix}");
+        }
+
+        if line.is_match {
+            prefix = style.style_text(prefix, Some(Color::Green), None);
+        }
+        println!("{prefix}{}", line.text);
+    }
+}
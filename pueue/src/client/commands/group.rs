@@ -19,6 +19,12 @@ pub async fn group(
             parallel_tasks: parallel.to_owned(),
         },
         Some(GroupCommand::Remove { name }) => GroupRequest::Remove(name.to_owned()),
+        Some(GroupCommand::Rename { name, new_name }) => GroupRequest::Rename { name, new_name },
+        Some(GroupCommand::Preempt { name, margin }) => GroupRequest::Preemption { name, margin },
+        Some(GroupCommand::MaxQueued { name, limit }) => GroupRequest::MaxQueued {
+            name,
+            max_queued: limit,
+        },
         None => GroupRequest::List,
     };
 
@@ -45,7 +51,8 @@ pub fn format_groups(message: GroupResponse, style: &OutputStyle, json: bool) ->
     let mut text = String::new();
     let mut group_iter = message.groups.iter().peekable();
     while let Some((name, group)) = group_iter.next() {
-        let styled = get_group_headline(name, group, style);
+        let queued_tasks = message.queued_tasks.get(name).copied().unwrap_or_default();
+        let styled = get_group_headline(name, group, queued_tasks, style);
 
         text.push_str(&styled);
         if group_iter.peek().is_some() {
@@ -0,0 +1,20 @@
+use std::path::PathBuf;
+
+use pueue_lib::{Client, message::ImportRequest};
+
+use super::handle_response;
+use crate::{client::style::OutputStyle, internal_prelude::*};
+
+/// Import tasks and groups from a file that has been created by `pueue export`.
+pub async fn import(client: &mut Client, style: &OutputStyle, path: PathBuf) -> Result<()> {
+    let data = std::fs::read_to_string(&path)
+        .context(format!("Failed to read exported tasks from {path:?}"))?;
+    let message: ImportRequest =
+        serde_json::from_str(&data).context("Failed to deserialize exported tasks")?;
+
+    client.send_request(message).await?;
+
+    let response = client.receive_response().await?;
+
+    handle_response(style, response)
+}
@@ -1,13 +1,14 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    io::Read,
-};
+use std::{collections::BTreeMap, io::Read};
 
+use chrono::{DateTime, Local};
 use pueue_lib::{
-    log::{get_log_file_handle, read_last_lines},
+    log::{
+        get_stream_log_file_handle, has_separate_streams, read_last_lines,
+        read_timestamped_log_file,
+    },
     message::TaskLogResponse,
     settings::Settings,
-    task::Task,
+    task::{Envs, LogStream, Task},
 };
 use serde::{Deserialize, Serialize};
 use snap::read::FrameDecoder;
@@ -17,6 +18,10 @@ use snap::read::FrameDecoder;
 pub struct TaskLog {
     pub task: Task,
     pub output: String,
+    /// The task's stderr, if its output streams are captured separately.
+    /// `output` then only contains the task's stdout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
 }
 
 /// Print some log output in JSON serialized form.
@@ -28,36 +33,96 @@ pub fn print_log_json(
     task_log_messages: BTreeMap<usize, TaskLogResponse>,
     settings: &Settings,
     lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
+    since: Option<DateTime<Local>>,
 ) {
-    let mut tasks: BTreeMap<usize, Task> = BTreeMap::new();
-    let mut task_log: BTreeMap<usize, String> = BTreeMap::new();
+    let mut json = BTreeMap::new();
     for (id, message) in task_log_messages {
-        tasks.insert(id, message.task);
-
-        if settings.client.read_local_logs {
-            let output = get_local_log(settings, id, lines);
-            task_log.insert(id, output);
-        } else {
-            let output = get_remote_log(message.output);
-            task_log.insert(id, output);
-        }
+        let task_log = get_task_log(id, message, settings, lines, stream, timestamps, since);
+        json.insert(id, task_log);
     }
 
-    // Now assemble the final struct that will be returned
-    let mut json = BTreeMap::new();
-    for (id, mut task) in tasks {
-        let (id, output) = task_log.remove_entry(&id).unwrap();
+    println!("{}", serde_json::to_string(&json).unwrap());
+}
 
-        task.envs = HashMap::new();
-        json.insert(id, TaskLog { task, output });
+/// Print each task and its log output as a separate line of JSON. \
+/// This is used for newline-delimited JSON, for which the tasks are received one by one.
+pub fn print_log_ndjson(
+    task_log_messages: BTreeMap<usize, TaskLogResponse>,
+    settings: &Settings,
+    lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
+    since: Option<DateTime<Local>>,
+) {
+    for (id, message) in task_log_messages {
+        let task_log = get_task_log(id, message, settings, lines, stream, timestamps, since);
+        println!("{}", serde_json::to_string(&task_log).unwrap());
     }
+}
 
-    println!("{}", serde_json::to_string(&json).unwrap());
+/// Convert a received [TaskLogResponse] into a [TaskLog].
+/// The output is either read from the disk or decompressed from the response.
+fn get_task_log(
+    id: usize,
+    message: TaskLogResponse,
+    settings: &Settings,
+    lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
+    since: Option<DateTime<Local>>,
+) -> TaskLog {
+    let (output, stderr) = if settings.client.read_local_logs && message.output.is_none() {
+        let pueue_directory = settings.shared.pueue_directory();
+        // Add the separately captured stderr, unless a specific stream is requested.
+        let stderr = (stream.is_none() && has_separate_streams(id, &pueue_directory)).then(|| {
+            let stream = Some(LogStream::Stderr);
+            get_local_log(settings, id, lines, stream, timestamps, since)
+        });
+        let output = get_local_log(settings, id, lines, stream, timestamps, since);
+        (output, stderr)
+    } else {
+        let stderr = message.stderr.map(|bytes| get_remote_log(Some(bytes)));
+        let output = get_remote_log(message.output);
+        (output, stderr)
+    };
+
+    let mut task = message.task;
+    task.envs = Envs::default();
+
+    TaskLog {
+        task,
+        output,
+        stderr,
+    }
 }
 
 /// Read logs directly from local files for a specific task.
-fn get_local_log(settings: &Settings, id: usize, lines: Option<usize>) -> String {
-    let mut file = match get_log_file_handle(id, &settings.shared.pueue_directory()) {
+fn get_local_log(
+    settings: &Settings,
+    id: usize,
+    lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
+    since: Option<DateTime<Local>>,
+) -> String {
+    let pueue_directory = settings.shared.pueue_directory();
+    if timestamps || since.is_some() {
+        return match read_timestamped_log_file(
+            id,
+            &pueue_directory,
+            lines,
+            stream,
+            timestamps,
+            since,
+        ) {
+            Ok((output, _)) => output,
+            Err(err) => format!("(Pueue error) Failed to read local log output file: {err}"),
+        };
+    }
+
+    let mut file = match get_stream_log_file_handle(id, &pueue_directory, stream) {
         Ok(file) => file,
         Err(err) => {
             return format!("(Pueue error) Failed to get log file handle: {err}");
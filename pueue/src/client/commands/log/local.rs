@@ -1,47 +1,86 @@
-use std::{
-    fs::File,
-    io::{self, Stdout},
-};
+use std::io::{self, Stdout};
 
+use chrono::{DateTime, Local};
 use crossterm::style::{Attribute, Color};
 use pueue_lib::{
-    log::{get_log_file_handle, seek_to_last_lines},
+    log::{
+        LogReader, get_stream_log_file_handle, has_separate_streams, read_timestamped_log_file,
+        seek_to_last_lines,
+    },
     settings::Settings,
+    task::LogStream,
 };
 
 use crate::client::style::OutputStyle;
 
 /// The daemon didn't send any log output, thereby we didn't request any.
 /// If that's the case, read the log file from the local pueue directory.
+///
+/// If the task's output streams are captured separately and no specific stream is requested,
+/// both streams are printed one after another.
 pub fn print_local_log(
     task_id: usize,
     style: &OutputStyle,
     settings: &Settings,
     lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
+    since: Option<DateTime<Local>>,
 ) {
-    let mut file = match get_log_file_handle(task_id, &settings.shared.pueue_directory()) {
-        Ok(file) => file,
-        Err(err) => {
-            eprintln!("Failed to get log file handle: {err}");
-            return;
-        }
+    let pueue_directory = settings.shared.pueue_directory();
+    let streams = if stream.is_none() && has_separate_streams(task_id, &pueue_directory) {
+        vec![Some(LogStream::Stdout), Some(LogStream::Stderr)]
+    } else {
+        vec![stream]
     };
+
     // Stdout handler to directly write log file output to io::stdout
     // without having to load anything into memory.
     let mut stdout = io::stdout();
 
-    print_local_file(
-        &mut stdout,
-        &mut file,
-        &lines,
-        style.style_text("output:", Some(Color::Green), Some(Attribute::Bold)),
-    );
+    for stream in streams {
+        let header = stream.map_or("output:".to_string(), |stream| format!("{stream}:"));
+        let header = style.style_text(header, Some(Color::Green), Some(Attribute::Bold));
+
+        // Timestamps have to be inserted, so the output is read into memory first.
+        if timestamps || since.is_some() {
+            match read_timestamped_log_file(
+                task_id,
+                &pueue_directory,
+                lines,
+                stream,
+                timestamps,
+                since,
+            ) {
+                Ok((output, output_complete)) => {
+                    print_local_output(&output, output_complete, &lines, header)
+                }
+                Err(err) => eprintln!("Failed reading local log file: {err}"),
+            }
+            continue;
+        }
+
+        let mut file = match get_stream_log_file_handle(task_id, &pueue_directory, stream) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Failed to get log file handle: {err}");
+                return;
+            }
+        };
+
+        print_local_file(&mut stdout, &mut file, &lines, header);
+    }
 }
 
 /// Print a local log file of a task.
-fn print_local_file(stdout: &mut Stdout, file: &mut File, lines: &Option<usize>, header: String) {
-    if let Ok(metadata) = file.metadata() {
-        if metadata.len() != 0 {
+fn print_local_file(
+    stdout: &mut Stdout,
+    file: &mut LogReader,
+    lines: &Option<usize>,
+    header: String,
+) {
+    if let Ok(size) = file.size() {
+        if size != 0 {
             // Indicates whether the full log output is shown or just the last part of it.
             let mut output_complete = true;
 
@@ -56,15 +95,8 @@ fn print_local_file(stdout: &mut Stdout, file: &mut File, lines: &Option<usize>,
                 }
             }
 
-            // Add a hint if we should limit the output to X lines **and** there are actually more
-            // lines than that given limit.
-            let mut line_info = String::new();
-            if !output_complete {
-                line_info = lines.map_or(String::new(), |lines| format!(" (last {lines} lines)"));
-            }
-
             // Print a newline between the task information and the first output.
-            eprintln!("\n{header}{line_info}");
+            eprintln!("\n{header}{}", line_info(output_complete, lines));
 
             // Print everything
             if let Err(err) = io::copy(file, stdout) {
@@ -73,3 +105,22 @@ fn print_local_file(stdout: &mut Stdout, file: &mut File, lines: &Option<usize>,
         }
     }
 }
+
+/// Print the already rendered output of a local log file.
+fn print_local_output(output: &str, output_complete: bool, lines: &Option<usize>, header: String) {
+    if output.is_empty() {
+        return;
+    }
+
+    eprintln!("\n{header}{}", line_info(output_complete, lines));
+    print!("{output}");
+}
+
+/// Add a hint if we should limit the output to X lines **and** there are actually more
+/// lines than that given limit.
+fn line_info(output_complete: bool, lines: &Option<usize>) -> String {
+    if output_complete {
+        return String::new();
+    }
+    lines.map_or(String::new(), |lines| format!(" (last {lines} lines)"))
+}
@@ -1,10 +1,13 @@
+use std::ops::Range;
+
+use chrono::{DateTime, Local};
 use comfy_table::{Attribute as ComfyAttribute, Cell, CellAlignment, Table};
 use crossterm::style::Color;
 use pueue_lib::{
     Client,
     message::{TaskLogResponse, TaskSelection, *},
     settings::Settings,
-    task::{Task, TaskResult, TaskStatus},
+    task::{LogStream, Task, TaskResult, TaskStatus},
 };
 
 use super::{OutputStyle, handle_response, selection_from_params};
@@ -29,20 +32,49 @@ pub async fn print_logs(
     group: Option<String>,
     all: bool,
     json: bool,
+    ndjson: bool,
     lines: Option<usize>,
     full: bool,
+    stream: Option<LogStream>,
+    timestamps: bool,
+    since: Option<DateTime<Local>>,
+    bytes: Option<Range<u64>>,
 ) -> Result<()> {
-    let lines = determine_log_line_amount(full, &lines);
+    // Byte ranges aren't limited to any amount of lines.
+    let lines = if bytes.is_some() {
+        None
+    } else {
+        determine_log_line_amount(full, &lines)
+    };
     let selection = selection_from_params(all, group.clone(), task_ids.clone());
 
     client
         .send_request(LogRequest {
             tasks: selection.clone(),
-            send_logs: !settings.client.read_local_logs,
+            // Byte ranges are always read by the daemon.
+            send_logs: !settings.client.read_local_logs || bytes.is_some(),
             lines,
+            stream,
+            timestamps,
+            since,
+            bytes,
+            streamed: ndjson,
         })
         .await?;
 
+    // The tasks are received one by one, until the daemon closes the stream.
+    if ndjson {
+        loop {
+            match client.receive_response().await? {
+                Response::Log(task_logs) => {
+                    print_log_ndjson(task_logs, &settings, lines, stream, timestamps, since)
+                }
+                Response::Close => return Ok(()),
+                response => return handle_response(style, response),
+            }
+        }
+    }
+
     let response = client.receive_response().await?;
 
     let Response::Log(task_logs) = response else {
@@ -52,7 +84,7 @@ pub async fn print_logs(
 
     // Return the server response in json representation.
     if json {
-        print_log_json(task_logs, &settings, lines);
+        print_log_json(task_logs, &settings, lines, stream, timestamps, since);
         return Ok(());
     }
 
@@ -76,7 +108,7 @@ pub async fn print_logs(
     // Iterate over each task and print the respective log.
     let mut task_iter = task_logs.iter().peekable();
     while let Some((_, task_log)) = task_iter.next() {
-        print_log(task_log, style, &settings, lines);
+        print_log(task_log, style, &settings, lines, stream, timestamps, since);
 
         // Add a newline if there is another task that's going to be printed.
         if let Some((_, task_log)) = task_iter.peek() {
@@ -119,11 +151,17 @@ fn determine_log_line_amount(full: bool, lines: &Option<usize>) -> Option<usize>
 /// lines: Whether we should reduce the log output of each task to a specific number of lines.
 ///         `None` implicates that everything should be printed.
 ///         This is only important, if we read local lines.
+/// stream: Whether only a specific output stream should be printed.
+/// timestamps: Whether each line should be prefixed with the time it has been printed.
+/// since: Only print lines that have been printed after this point in time.
 fn print_log(
     message: &TaskLogResponse,
     style: &OutputStyle,
     settings: &Settings,
     lines: Option<usize>,
+    stream: Option<LogStream>,
+    timestamps: bool,
+    since: Option<DateTime<Local>>,
 ) {
     let task = &message.task;
     // We only show logs of finished or running tasks.
@@ -136,10 +174,19 @@ fn print_log(
 
     print_task_info(task, style);
 
-    if settings.client.read_local_logs {
-        print_local_log(message.task.id, style, settings, lines);
-    } else if message.output.is_some() {
-        print_remote_log(message, style, lines);
+    // The daemon only sends log output, if local logs aren't read or a byte range is requested.
+    if message.output.is_some() {
+        print_remote_log(message, style, lines, stream);
+    } else if settings.client.read_local_logs {
+        print_local_log(
+            message.task.id,
+            style,
+            settings,
+            lines,
+            stream,
+            timestamps,
+            since,
+        );
     } else {
         println!("Logs requested from pueue daemon, but none received. Please report this bug.");
     }
@@ -166,6 +213,8 @@ fn print_task_info(task: &Task, style: &OutputStyle) {
             TaskResult::Killed => ("killed by system or user".into(), Color::Red),
             TaskResult::Errored => ("some IO error.\n Check daemon log.".into(), Color::Red),
             TaskResult::DependencyFailed => ("dependency failed".into(), Color::Red),
+            TaskResult::Superseded => ("superseded by a newer task".into(), Color::Yellow),
+            TaskResult::Orphaned => ("orphaned by a crash of the daemon".into(), Color::Red),
         },
         _ => (task.status.to_string(), Color::White),
     };
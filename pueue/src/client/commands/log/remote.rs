@@ -1,31 +1,70 @@
 use std::io;
 
 use crossterm::style::{Attribute, Color};
-use pueue_lib::message::TaskLogResponse;
+use pueue_lib::{message::TaskLogResponse, task::LogStream};
 use snap::read::FrameDecoder;
 
 use super::OutputStyle;
 use crate::internal_prelude::*;
 
 /// Prints log output received from the daemon.
-pub fn print_remote_log(task_log: &TaskLogResponse, style: &OutputStyle, lines: Option<usize>) {
+///
+/// If the task's output streams are captured separately and no specific stream has been
+/// requested, the daemon sends both streams, which are then printed one after another.
+pub fn print_remote_log(
+    task_log: &TaskLogResponse,
+    style: &OutputStyle,
+    lines: Option<usize>,
+    stream: Option<LogStream>,
+) {
+    let mut header = if task_log.stderr.is_some() {
+        LogStream::Stdout.to_string()
+    } else {
+        stream.map_or("output".to_string(), |stream| stream.to_string())
+    };
+    // Show which part of the output has been fetched, if a byte range has been requested.
+    if let Some(bytes) = &task_log.bytes {
+        header = format!("{header} (bytes {}..{})", bytes.start, bytes.end);
+    }
+
     if let Some(bytes) = task_log.output.as_ref() {
-        if !bytes.is_empty() {
-            // Add a hint if we should limit the output to X lines **and** there are actually more
-            // lines than that given limit.
-            let mut line_info = String::new();
-            if !task_log.output_complete {
-                line_info = lines.map_or(String::new(), |lines| format!(" (last {lines} lines)"));
-            }
-
-            // Print a newline between the task information and the first output.
-            let header = style.style_text("output:", Some(Color::Green), Some(Attribute::Bold));
-            println!("\n{header}{line_info}");
-
-            if let Err(err) = decompress_and_print_remote_log(bytes) {
-                eprintln!("Error while parsing stdout: {err}");
-            }
-        }
+        print_remote_output(bytes, &header, task_log.output_complete, style, lines);
+    }
+    if let Some(bytes) = task_log.stderr.as_ref() {
+        let header = LogStream::Stderr.to_string();
+        print_remote_output(bytes, &header, task_log.output_complete, style, lines);
+    }
+}
+
+/// Print a single section of compressed log output.
+fn print_remote_output(
+    bytes: &[u8],
+    header: &str,
+    output_complete: bool,
+    style: &OutputStyle,
+    lines: Option<usize>,
+) {
+    if bytes.is_empty() {
+        return;
+    }
+
+    // Add a hint if we should limit the output to X lines **and** there are actually more
+    // lines than that given limit.
+    let mut line_info = String::new();
+    if !output_complete {
+        line_info = lines.map_or(String::new(), |lines| format!(" (last {lines} lines)"));
+    }
+
+    // Print a newline between the task information and the first output.
+    let header = style.style_text(
+        format!("{header}:"),
+        Some(Color::Green),
+        Some(Attribute::Bold),
+    );
+    println!("\n{header}{line_info}");
+
+    if let Err(err) = decompress_and_print_remote_log(bytes) {
+        eprintln!("Error while parsing stdout: {err}");
     }
 }
 
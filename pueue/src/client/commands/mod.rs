@@ -9,22 +9,28 @@ use std::io::{Write, stdin, stdout};
 
 use pueue_lib::{
     Client, Settings,
-    message::{Request, Response, TaskSelection},
+    message::{Request, Response, StatusResponse, TaskSelection},
     state::{PUEUE_DEFAULT_GROUP, State},
-    task::Task,
+    task::{LogStream, Task},
 };
 
 use crate::internal_prelude::*;
 
 mod add;
+mod bundle;
 mod clean;
+mod daemon;
 mod edit;
 mod enqueue;
 mod env;
+mod export;
 mod follow;
+mod grep;
 mod group;
+mod import;
 mod kill;
 mod log;
+mod move_tasks;
 mod parallel;
 mod pause;
 mod remove;
@@ -39,14 +45,20 @@ mod switch;
 mod wait;
 
 use add::add_task;
+use bundle::bundle;
 use clean::clean;
+use daemon::daemon;
 use edit::edit;
 use enqueue::enqueue;
 use env::env;
+use export::export;
 use follow::follow;
+use grep::grep;
 use group::group;
+use import::import;
 use kill::kill;
 use log::print_logs;
+use move_tasks::move_tasks;
 use parallel::parallel;
 use pause::pause;
 use remove::remove;
@@ -89,6 +101,18 @@ pub fn selection_from_params(
     }
 }
 
+/// Determine which output stream of tasks has been selected via the `--stdout` and `--stderr`
+/// flags. `None` selects all output.
+fn stream_from_params(stdout: bool, stderr: bool) -> Option<LogStream> {
+    if stdout {
+        Some(LogStream::Stdout)
+    } else if stderr {
+        Some(LogStream::Stderr)
+    } else {
+        None
+    }
+}
+
 /// This is a small helper which either returns a given group or the default group.
 fn group_or_default(group: &Option<String>) -> String {
     group
@@ -99,6 +123,11 @@ fn group_or_default(group: &Option<String>) -> String {
 // This is a helper function for easy retrieval of the current daemon state.
 // The current daemon state is often needed in more complex commands.
 pub async fn get_state(client: &mut Client) -> Result<State> {
+    Ok(get_status(client).await?.state)
+}
+
+// Retrieve the current daemon state together with the settings needed to display it.
+pub async fn get_status(client: &mut Client) -> Result<StatusResponse> {
     // Create the message payload and send it to the daemon.
     client.send_request(Request::Status).await?;
 
@@ -106,7 +135,7 @@ pub async fn get_state(client: &mut Client) -> Result<State> {
     let response = client.receive_response().await?;
 
     match response {
-        Response::Status(state) => Ok(*state),
+        Response::Status(status) => Ok(*status),
         _ => unreachable!(),
     }
 }
@@ -120,7 +149,7 @@ pub async fn get_task(client: &mut Client, task_id: usize) -> Result<Option<Task
     let response = client.receive_response().await?;
 
     let state = match response {
-        Response::Status(state) => state,
+        Response::Status(status) => status.state,
         _ => unreachable!(),
     };
 
@@ -170,6 +199,10 @@ pub async fn handle_command(
             dependencies,
             priority,
             label,
+            restartable,
+            concurrency_key,
+            supersede,
+            timestamps,
             print_task_id,
             follow,
         } => {
@@ -187,15 +220,25 @@ pub async fn handle_command(
                 dependencies,
                 priority,
                 label,
+                restartable,
+                concurrency_key,
+                supersede,
+                timestamps,
                 print_task_id,
                 follow,
             )
             .await
         }
+        SubCommand::Bundle {
+            group,
+            status,
+            output,
+        } => bundle(client, style, group, status, output).await,
         SubCommand::Clean {
             successful_only,
             group,
         } => clean(client, style, group, successful_only).await,
+        SubCommand::Daemon { cmd } => daemon(client, style, cmd).await,
         SubCommand::Edit { task_ids } => edit(client, settings, style, task_ids).await,
         SubCommand::Enqueue {
             task_ids,
@@ -204,10 +247,30 @@ pub async fn handle_command(
             delay_until,
         } => enqueue(client, style, task_ids, group, all, delay_until).await,
         SubCommand::Env { cmd } => env(client, style, cmd).await,
-        SubCommand::Follow { task_id, lines } => {
-            follow(client, settings, style, task_id, lines).await
+        SubCommand::Export { group, all, logs } => export(client, group, all, logs).await,
+        SubCommand::Follow {
+            task_ids,
+            group,
+            lines,
+            stdout,
+            stderr,
+            timestamps,
+        } => {
+            let stream = stream_from_params(stdout, stderr);
+            follow(
+                client, settings, style, task_ids, group, lines, stream, timestamps,
+            )
+            .await
         }
+        SubCommand::Grep {
+            pattern,
+            group,
+            status,
+            context,
+            json,
+        } => grep(client, style, pattern, group, status, context, json).await,
         SubCommand::Group { cmd, json } => group(client, style, cmd, json).await,
+        SubCommand::Import { path } => import(client, style, path).await,
         SubCommand::Kill {
             task_ids,
             group,
@@ -219,11 +282,19 @@ pub async fn handle_command(
             group,
             all,
             json,
+            ndjson,
             lines,
             full,
+            stdout,
+            stderr,
+            timestamps,
+            since,
+            bytes,
         } => {
+            let stream = stream_from_params(stdout, stderr);
             print_logs(
-                client, settings, style, task_ids, group, all, json, lines, full,
+                client, settings, style, task_ids, group, all, json, ndjson, lines, full, stream,
+                timestamps, since, bytes,
             )
             .await
         }
@@ -283,6 +354,12 @@ pub async fn handle_command(
             task_id_1,
             task_id_2,
         } => switch(client, style, task_id_1, task_id_2).await,
+        SubCommand::Move {
+            task_ids,
+            group,
+            front,
+            back: _,
+        } => move_tasks(client, style, task_ids, group, front).await,
         SubCommand::Wait {
             task_ids,
             group,
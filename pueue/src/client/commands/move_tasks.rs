@@ -0,0 +1,31 @@
+use pueue_lib::{Client, message::*};
+
+use super::handle_response;
+use crate::{client::style::OutputStyle, internal_prelude::*};
+
+/// Move queued or stashed tasks to another group or to the front/back of their group.
+pub async fn move_tasks(
+    client: &mut Client,
+    style: &OutputStyle,
+    task_ids: Vec<usize>,
+    group: Option<String>,
+    front: bool,
+) -> Result<()> {
+    // Clap ensures that exactly one destination is given.
+    let destination = match group {
+        Some(group) => MoveDestination::Group(group),
+        None if front => MoveDestination::Front,
+        None => MoveDestination::Back,
+    };
+
+    client
+        .send_request(MoveRequest {
+            task_ids,
+            destination,
+        })
+        .await?;
+
+    let response = client.receive_response().await?;
+
+    handle_response(style, response)
+}
@@ -146,7 +146,8 @@ pub async fn restart(
         let add_task_message = AddRequest {
             command: task.original_command,
             path: task.path,
-            envs: task.envs.clone(),
+            default_path: false,
+            envs: task.envs.to_map(),
             start_immediately,
             stashed,
             group: task.group.clone(),
@@ -154,6 +155,10 @@ pub async fn restart(
             dependencies: Vec::new(),
             priority: Some(task.priority),
             label: task.label,
+            restartable: task.restartable,
+            concurrency_key: task.concurrency_key,
+            supersede: false,
+            timestamps: task.timestamps,
         };
 
         // Send the cloned task to the daemon and abort on any failure messages.
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use chrono::{DateTime, Local, LocalResult};
+use chrono::{DateTime, Local, LocalResult, TimeDelta};
 use pueue_lib::{
     Client,
     settings::Settings,
@@ -9,7 +9,7 @@ use pueue_lib::{
 };
 
 use crate::{
-    client::{commands::get_state, display_helper::get_group_headline, style::OutputStyle},
+    client::{commands::get_status, display_helper::get_group_headline, style::OutputStyle},
     internal_prelude::*,
 };
 
@@ -28,10 +28,21 @@ pub async fn state(
     json: bool,
     group: Option<String>,
 ) -> Result<()> {
-    let state = get_state(client).await?;
+    let status = get_status(client).await?;
+    let aging = status.priority_aging_interval();
+    let state = status.state;
     let tasks = state.tasks.values().cloned().collect();
 
-    let output = print_state(state, tasks, style, &settings, json, group, Some(query))?;
+    let output = print_state(
+        state,
+        tasks,
+        aging,
+        style,
+        &settings,
+        json,
+        group,
+        Some(query),
+    )?;
     println!("{output}");
 
     Ok(())
@@ -42,9 +53,12 @@ pub async fn state(
 ///
 /// We pass the tasks as a separate parameter and as a list.
 /// This allows us to print the tasks in the order passed to the `format-status` subcommand.
+/// `priority_aging` is the daemon's priority aging interval, see [Task::effective_priority].
+#[allow(clippy::too_many_arguments)]
 fn print_state(
     mut state: State,
     mut tasks: Vec<Task>,
+    priority_aging: Option<TimeDelta>,
     style: &OutputStyle,
     settings: &Settings,
     json: bool,
@@ -53,7 +67,7 @@ fn print_state(
 ) -> Result<String> {
     let mut output = String::new();
 
-    let mut table_builder = TableBuilder::new(settings, style);
+    let mut table_builder = TableBuilder::new(settings, style, priority_aging);
 
     if let Some(query) = &query {
         let query_result = apply_query(&query.join(" "), &group)?;
@@ -103,7 +117,8 @@ fn print_single_group(
 
     // Only a single group is requested. Print that group and return.
     let tasks = sorted_tasks.entry(group_name.clone()).or_default();
-    let headline = get_group_headline(&group_name, group, style);
+    let queued_tasks = state.queued_tasks_in_group(&group_name);
+    let headline = get_group_headline(&group_name, group, queued_tasks, style);
     output.push_str(&headline);
 
     // Show a message if the requested group doesn't have any tasks.
@@ -135,6 +150,7 @@ fn print_all_groups(
         let headline = get_group_headline(
             PUEUE_DEFAULT_GROUP,
             state.groups.get(PUEUE_DEFAULT_GROUP).unwrap(),
+            state.queued_tasks_in_group(PUEUE_DEFAULT_GROUP),
             style,
         );
         output.push_str(&format!("{headline}\n"));
@@ -151,6 +167,7 @@ fn print_all_groups(
         let headline = get_group_headline(
             PUEUE_DEFAULT_GROUP,
             state.groups.get(PUEUE_DEFAULT_GROUP).unwrap(),
+            state.queued_tasks_in_group(PUEUE_DEFAULT_GROUP),
             style,
         );
         output.push_str(&headline);
@@ -172,7 +189,12 @@ fn print_all_groups(
             continue;
         }
 
-        let headline = get_group_headline(group, state.groups.get(group).unwrap(), style);
+        let headline = get_group_headline(
+            group,
+            state.groups.get(group).unwrap(),
+            state.queued_tasks_in_group(group),
+            style,
+        );
         if !output.is_empty() {
             output.push('\n');
         }
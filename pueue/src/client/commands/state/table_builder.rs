@@ -14,6 +14,8 @@ use super::{OutputStyle, formatted_start_end, query::Rule, start_of_today};
 pub struct TableBuilder<'a> {
     settings: &'a Settings,
     style: &'a OutputStyle,
+    /// The daemon's priority aging interval, which is used to display effective priorities.
+    priority_aging: Option<TimeDelta>,
 
     /// Whether the columns to be displayed are explicitly selected by the user.
     /// If that's the case, we won't do any automated checks whether columns should be displayed or
@@ -35,10 +37,15 @@ pub struct TableBuilder<'a> {
 }
 
 impl<'a> TableBuilder<'a> {
-    pub fn new(settings: &'a Settings, style: &'a OutputStyle) -> Self {
+    pub fn new(
+        settings: &'a Settings,
+        style: &'a OutputStyle,
+        priority_aging: Option<TimeDelta>,
+    ) -> Self {
         Self {
             settings,
             style,
+            priority_aging,
             selected_columns: false,
             id: true,
             status: true,
@@ -78,8 +85,12 @@ impl<'a> TableBuilder<'a> {
             return;
         }
 
-        // Check whether there are any tasks with a non-default priority
-        if tasks.iter().any(|task| task.priority != 0) {
+        // Check whether there are any tasks with a non-default (effective) priority
+        let aging = self.priority_aging;
+        if tasks
+            .iter()
+            .any(|task| task.priority != 0 || task.effective_priority(aging) != 0)
+        {
             self.priority = true;
         }
 
@@ -205,6 +216,10 @@ impl<'a> TableBuilder<'a> {
                 let status_string = task.status.to_string();
                 let (status_text, color) = match &task.status {
                     TaskStatus::Running { .. } => (status_string, Color::Green),
+                    TaskStatus::Paused { .. } if task.is_preempted() => {
+                        let preempted_by = task.preemptions.last().unwrap().preempted_by;
+                        (format!("Preempted by {preempted_by}"), Color::White)
+                    }
                     TaskStatus::Paused { .. } | TaskStatus::Locked { .. } => {
                         (status_string, Color::White)
                     }
@@ -215,6 +230,7 @@ impl<'a> TableBuilder<'a> {
                         }
                         TaskResult::FailedToSpawn(_) => ("Failed to spawn".to_string(), Color::Red),
                         TaskResult::Failed(code) => (format!("Failed ({code})"), Color::Red),
+                        TaskResult::Superseded => (result.to_string(), Color::Yellow),
                         _ => (result.to_string(), Color::Red),
                     },
                     _ => (status_string, Color::Yellow),
@@ -223,7 +239,16 @@ impl<'a> TableBuilder<'a> {
             }
 
             if self.priority {
-                row.add_cell(Cell::new(task.priority.to_string()));
+                // Show the effective priority next to the static one, if queued tasks are aging.
+                let effective_priority = task.effective_priority(self.priority_aging);
+                if effective_priority != task.priority {
+                    row.add_cell(Cell::new(format!(
+                        "{} ({effective_priority})",
+                        task.priority
+                    )));
+                } else {
+                    row.add_cell(Cell::new(task.priority.to_string()));
+                }
             }
 
             if self.enqueue_at {
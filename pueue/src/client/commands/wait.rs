@@ -248,6 +248,14 @@ fn log_status_change(previous_status: TaskStatus, task: &Task, style: &OutputSty
                 let status = style.style_text("killed", Some(Color::Red), None);
                 format!("Task {task_id} has been {status}")
             }
+            TaskResult::Superseded => {
+                let status = style.style_text("superseded", Some(Color::Yellow), None);
+                format!("Task {task_id} has been {status} by a newer task")
+            }
+            TaskResult::Orphaned => {
+                let status = style.style_text("orphaned", Some(Color::Red), None);
+                format!("Task {task_id} has been {status} by a crash of the daemon")
+            }
         };
         println!("{current_time} - {text}");
 
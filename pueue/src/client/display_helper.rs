@@ -18,7 +18,15 @@ pub fn print_error(style: &OutputStyle, message: &str) {
 
 /// Return some nicely formatted info about a given group.
 /// This is also used as a headline that's displayed above group's task tables.
-pub fn get_group_headline(name: &str, group: &Group, style: &OutputStyle) -> String {
+///
+/// `queued_tasks` is the amount of queued and stashed tasks of the group.
+/// It's shown next to the group's limit, if there is one.
+pub fn get_group_headline(
+    name: &str,
+    group: &Group,
+    queued_tasks: usize,
+    style: &OutputStyle,
+) -> String {
     // Style group name
     let name = style.style_text(format!("Group \"{name}\""), None, Some(Attribute::Bold));
 
@@ -29,5 +37,13 @@ pub fn get_group_headline(name: &str, group: &Group, style: &OutputStyle) -> Str
         GroupStatus::Reset => style.style_text("resetting", Some(Color::Red), None),
     };
 
-    format!("{} ({} parallel): {}", name, group.parallel_tasks, status)
+    let mut settings = format!("{} parallel", group.parallel_tasks);
+    if let Some(margin) = group.preemption_margin {
+        settings.push_str(&format!(", preemption margin {margin}"));
+    }
+    if let Some(max_queued) = group.max_queued {
+        settings.push_str(&format!(", {queued_tasks}/{max_queued} queued"));
+    }
+
+    format!("{name} ({settings}): {status}")
 }
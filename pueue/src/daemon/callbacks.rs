@@ -98,17 +98,24 @@ pub fn build_callback_command(
     parameters.insert("end", print_time(end));
 
     // Read the last lines of the process' output and make it available.
-    if let Ok(output) = read_last_log_file_lines(
+    // If only a specific stream is requested, but the task's streams haven't been captured
+    // separately, fall back to the whole output.
+    let pueue_directory = settings.shared.pueue_directory();
+    let lines = settings.daemon.callback_log_lines;
+    let output = read_last_log_file_lines(
         task.id,
-        &settings.shared.pueue_directory(),
-        settings.daemon.callback_log_lines,
-    ) {
+        &pueue_directory,
+        lines,
+        settings.daemon.callback_log_stream,
+    )
+    .or_else(|_| read_last_log_file_lines(task.id, &pueue_directory, lines, None));
+    if let Ok(output) = output {
         parameters.insert("output", output);
     } else {
         parameters.insert("output", "".to_string());
     }
 
-    let out_path = get_log_path(task.id, &settings.shared.pueue_directory());
+    let out_path = get_log_path(task.id, &pueue_directory);
     // Using Display impl of PathBuf which isn't necessarily a perfect
     // representation of the path but should work for most cases here
     parameters.insert("output_path", out_path.display().to_string());
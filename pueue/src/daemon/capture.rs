@@ -0,0 +1,223 @@
+//! The output of some tasks isn't written to their log files directly, but piped through the
+//! daemon instead. This allows to:
+//! - Record the time at which each line has been printed next to the log file.
+//! - Truncate or rotate the log files, without losing any output of the running task.
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use pueue_lib::{
+    log::{LineTimestamp, rotate_log_file, truncate_log_head},
+    settings::LogSizePolicy,
+};
+
+use crate::internal_prelude::*;
+
+/// The size limit of a log file, which is enforced while the output is recorded.
+#[derive(Clone, Copy, Debug)]
+pub struct LogLimit {
+    pub max_size: u64,
+    pub policy: LogSizePolicy,
+    pub max_segments: usize,
+}
+
+/// A log file that's written by the daemon.
+#[derive(Debug)]
+struct RecordedLog {
+    path: PathBuf,
+    log: File,
+    /// The file that records the timestamps of the log's lines, if the output is timestamped.
+    timestamps: Option<File>,
+    limit: Option<LogLimit>,
+    /// The amount of bytes that have been written to the log file.
+    position: u64,
+    /// Whether the next written byte starts a new line.
+    at_line_start: bool,
+}
+
+impl RecordedLog {
+    fn new(path: PathBuf, log: File) -> Self {
+        RecordedLog {
+            path,
+            log,
+            timestamps: None,
+            limit: None,
+            position: 0,
+            at_line_start: true,
+        }
+    }
+
+    /// Write a chunk of output and record the time of all lines that start in it.
+    fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        if let Some(timestamps) = &mut self.timestamps {
+            let time = Local::now();
+            let mut records = String::new();
+            for (index, byte) in chunk.iter().enumerate() {
+                if self.at_line_start {
+                    let offset = self.position + index as u64;
+                    records.push_str(&LineTimestamp { offset, time }.to_record());
+                }
+                self.at_line_start = *byte == b'\n';
+            }
+
+            // Timestamps are written first, so readers find the timestamp of every line they read.
+            timestamps.write_all(records.as_bytes())?;
+        }
+        self.log.write_all(chunk)?;
+        self.position += chunk.len() as u64;
+
+        Ok(())
+    }
+
+    /// Truncate or rotate the log file, once it exceeds its size limit.
+    ///
+    /// The task's output is only written while the log is locked, which is why nothing gets lost.
+    fn enforce_limit(&mut self) -> Result<()> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+        if self.log.metadata()?.len() <= limit.max_size {
+            return Ok(());
+        }
+
+        match limit.policy {
+            LogSizePolicy::Truncate => truncate_log_head(&self.path, limit.max_size / 2)?,
+            LogSizePolicy::Rotate => rotate_log_file(&self.path, limit.max_segments)?,
+            // Tasks are killed by the task handler.
+            LogSizePolicy::Kill => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// The log files of a task, whose output is about to be recorded.
+#[derive(Debug)]
+pub struct OutputRecorder {
+    stdout: Arc<Mutex<RecordedLog>>,
+    stderr: Arc<Mutex<RecordedLog>>,
+}
+
+impl OutputRecorder {
+    /// Take the paths and freshly created `(stdout, stderr)` log files of a task.
+    ///
+    /// If stderr isn't written to a separate file, both streams share the same log file.
+    pub fn new(paths: (PathBuf, PathBuf), logs: (File, File), separate_stderr: bool) -> Self {
+        let stdout = Arc::new(Mutex::new(RecordedLog::new(paths.0, logs.0)));
+        let stderr = if separate_stderr {
+            Arc::new(Mutex::new(RecordedLog::new(paths.1, logs.1)))
+        } else {
+            stdout.clone()
+        };
+
+        OutputRecorder { stdout, stderr }
+    }
+
+    /// Record the timestamps of all lines into the freshly created `(stdout, stderr)` timestamp
+    /// files. Streams that share the same log file also share the same timestamp file.
+    pub fn with_timestamps(self, timestamps: (File, Option<File>)) -> Self {
+        let (stdout_timestamps, stderr_timestamps) = timestamps;
+        self.stdout.lock().unwrap().timestamps = Some(stdout_timestamps);
+        if let Some(stderr_timestamps) = stderr_timestamps {
+            self.stderr.lock().unwrap().timestamps = Some(stderr_timestamps);
+        }
+
+        self
+    }
+
+    /// Enforce the size limit of the log files while recording.
+    pub fn with_limit(self, limit: LogLimit) -> Self {
+        self.stdout.lock().unwrap().limit = Some(limit);
+        self.stderr.lock().unwrap().limit = Some(limit);
+
+        self
+    }
+
+    /// Start recording the piped output of a task's process.
+    pub fn start(
+        self,
+        task_id: usize,
+        stdout: Option<impl Read + Send + 'static>,
+        stderr: Option<impl Read + Send + 'static>,
+    ) -> OutputCapture {
+        let mut threads = Vec::new();
+        if let Some(stdout) = stdout {
+            let log = self.stdout.clone();
+            threads.push(thread::spawn(move || record(task_id, stdout, log)));
+        }
+        if let Some(stderr) = stderr {
+            let log = self.stderr.clone();
+            threads.push(thread::spawn(move || record(task_id, stderr, log)));
+        }
+
+        OutputCapture {
+            threads,
+            exited_at: None,
+        }
+    }
+}
+
+/// Copy everything from a pipe into a log file, until the pipe is closed.
+fn record(task_id: usize, mut pipe: impl Read, log: Arc<Mutex<RecordedLog>>) {
+    let mut buffer = vec![0; 4096];
+    let mut failed = false;
+    loop {
+        let read = match pipe.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                error!("Failed to read output of task {task_id}: {err:?}");
+                break;
+            }
+        };
+
+        // Keep draining the pipe even if writing fails, otherwise the task would block.
+        if failed {
+            continue;
+        }
+        let mut log = log.lock().unwrap();
+        if let Err(err) = log.write(&buffer[..read]) {
+            error!("Failed to write output of task {task_id}: {err:?}");
+            failed = true;
+        }
+        if let Err(err) = log.enforce_limit() {
+            // Don't retry on every single chunk of output.
+            error!("Failed to limit the log size of task {task_id}: {err:?}");
+            log.limit = None;
+        }
+    }
+}
+
+/// The threads that record the output of a running task.
+#[derive(Debug)]
+pub struct OutputCapture {
+    threads: Vec<JoinHandle<()>>,
+    /// The time at which the task's process has been found to be exited.
+    exited_at: Option<Instant>,
+}
+
+impl OutputCapture {
+    /// Whether the task's process has exited and we're waiting for its remaining output.
+    pub fn has_exited(&self) -> bool {
+        self.exited_at.is_some()
+    }
+
+    /// Check whether all output of an exited task has been recorded.
+    /// This never blocks, so it can be polled while the state is locked.
+    ///
+    /// Processes that have been started by the task may keep the pipes open after the task
+    /// exited. That's why we only wait up to `timeout` after the task exited. The threads then
+    /// continue to record the remaining output in the background and stop once the pipes are
+    /// closed.
+    pub fn is_recorded(&mut self, timeout: Duration) -> bool {
+        let exited_at = *self.exited_at.get_or_insert_with(Instant::now);
+        self.threads.iter().all(JoinHandle::is_finished) || exited_at.elapsed() >= timeout
+    }
+}
@@ -29,6 +29,15 @@ pub struct CliArguments {
     #[arg(short, long)]
     pub profile: Option<String>,
 
+    /// Run as the supervisor of a single task and record its result in the given file.
+    /// This is used internally by the daemon, if `daemon.supervise_tasks` is enabled.
+    #[arg(long, hide = true, value_name = "RESULT_FILE", requires = "command")]
+    pub supervise: Option<PathBuf>,
+
+    /// The command that's run by the supervisor.
+    #[arg(last = true, hide = true)]
+    pub command: Vec<String>,
+
     #[cfg(target_os = "windows")]
     #[command(subcommand)]
     pub service: Option<ServiceSubcommandEntry>,
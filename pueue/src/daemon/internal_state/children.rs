@@ -2,13 +2,59 @@ use std::collections::BTreeMap;
 
 use command_group::GroupChild;
 
+use crate::{
+    daemon::supervisor::SupervisorProcess,
+    internal_prelude::*,
+    process_helper::{Signal, kill_child, send_signal_to_child, send_signal_to_process_group},
+};
+
+/// The handle of a task's process.
+#[derive(Debug)]
+pub enum TaskChild {
+    /// A process that has been spawned by this daemon.
+    Spawned(GroupChild),
+    /// The supervisor of a task that has been spawned by a previous daemon.
+    /// It isn't a child of this daemon, which is why only its recorded process is known.
+    Adopted(SupervisorProcess),
+}
+
+impl TaskChild {
+    /// Send a signal to the task's process group.
+    pub fn signal(&mut self, signal: Signal) -> Result<()> {
+        match self {
+            TaskChild::Spawned(child) => send_signal_to_child(child, signal),
+            TaskChild::Adopted(supervisor) => {
+                // Never signal a process group, whose id has been reused by another process.
+                if !supervisor.is_alive() {
+                    bail!("The supervisor {} is no longer running", supervisor.pid);
+                }
+                send_signal_to_process_group(supervisor.pid, signal)
+            }
+        }
+    }
+
+    /// Kill the task's whole process group.
+    pub fn kill(&mut self, task_id: usize) -> Result<()> {
+        match self {
+            TaskChild::Spawned(child) => kill_child(task_id, child).map_err(Into::into),
+            TaskChild::Adopted(supervisor) => {
+                if !supervisor.is_alive() {
+                    info!("Task {task_id} has already finished by itself.");
+                    return Ok(());
+                }
+                send_signal_to_process_group(supervisor.pid, Signal::SIGKILL)
+            }
+        }
+    }
+}
+
 /// This structure is needed to manage worker pools for groups.
 /// It's a newtype pattern around a nested BTreeMap, which implements some convenience functions.
 ///
 /// The datastructure represents the following data:
 /// BTreeMap<group_name, BTreeMap<group_worker_id, (task_id, subprocess_handle)>
 #[derive(Debug, Default)]
-pub struct Children(pub BTreeMap<String, BTreeMap<usize, (usize, GroupChild)>>);
+pub struct Children(pub BTreeMap<String, BTreeMap<usize, (usize, TaskChild)>>);
 
 impl Children {
     /// Returns whether there are any active tasks across all groups.
@@ -43,7 +89,7 @@ impl Children {
     /// A convenience function to get a mutable child by its respective task_id.
     /// We have to do a nested linear search over all children of all pools,
     /// beceause these datastructure aren't indexed via task_ids.
-    pub fn get_child_mut(&mut self, task_id: usize) -> Option<&mut GroupChild> {
+    pub fn get_child_mut(&mut self, task_id: usize) -> Option<&mut TaskChild> {
         for pool in self.0.values_mut() {
             for (child_task_id, child) in pool.values_mut() {
                 if child_task_id == &task_id {
@@ -104,7 +150,7 @@ impl Children {
     /// This function should only be called when spawning a new process.
     /// At this point, we're sure that the worker pool for the given group already exists, hence
     /// the expect call.
-    pub fn add_child(&mut self, group: &str, worker_id: usize, task_id: usize, child: GroupChild) {
+    pub fn add_child(&mut self, group: &str, worker_id: usize, task_id: usize, child: TaskChild) {
         let pool = self
             .0
             .get_mut(group)
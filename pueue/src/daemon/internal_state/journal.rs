@@ -0,0 +1,417 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use pueue_lib::{Group, Settings, State, Task, task::Envs};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    daemon::internal_state::{
+        migrations::{self, STATE_VERSION},
+        rows::{DirtyRows, PersistedRows},
+        state::write_state_file,
+        state_file::{EnvironmentInterner, TaskEnvironment},
+    },
+    internal_prelude::*,
+};
+
+/// Once the journal contains this many records, it's compacted into a new snapshot.
+const COMPACTION_THRESHOLD: usize = 1000;
+
+/// A single line in the journal.
+/// Records always contain the full task or group, which is why replaying a record twice is fine.
+///
+/// Just like in the `state.json`, the base environment of tasks is only written once per journal
+/// and referenced by the task records.
+///
+/// Every journal starts with the schema version of its tasks and groups, so journals of older
+/// Pueue versions can be migrated while replaying them.
+// Records are only short-lived, so the size of the task variant doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Deserialize, Serialize)]
+enum Record<'a> {
+    Task {
+        task: Cow<'a, Task>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        environment: Option<TaskEnvironment>,
+    },
+    RemoveTask(usize),
+    Group {
+        name: Cow<'a, str>,
+        group: Cow<'a, Group>,
+    },
+    RemoveGroup(String),
+    /// A base environment, which is referenced by the task records that follow it.
+    Environment {
+        key: String,
+        variables: Cow<'a, HashMap<String, String>>,
+    },
+    /// The schema version of all following records.
+    Version(u64),
+}
+
+/// The bookkeeping of the journal backend.
+#[derive(Debug, Default)]
+pub struct Journal {
+    rows: PersistedRows,
+    /// The amount of records that have been appended to the journal since the last compaction.
+    records: usize,
+    /// The base environments that have been written to the current journal by their key.
+    environments: HashMap<String, Arc<HashMap<String, String>>>,
+    /// Whether a snapshot is currently being written in the background.
+    compacting: Arc<AtomicBool>,
+}
+
+/// The path of the journal inside the pueue directory.
+pub fn journal_path(settings: &Settings) -> PathBuf {
+    settings.shared.pueue_directory().join("state.journal")
+}
+
+/// The path of the journal that's currently being compacted into a new snapshot.
+fn compacting_path(settings: &Settings) -> PathBuf {
+    settings
+        .shared
+        .pueue_directory()
+        .join("state.journal.compacting")
+}
+
+impl Journal {
+    /// Append a record for every task and group that changed since the last save.
+    ///
+    /// The very first save writes a full snapshot instead, as we don't know which records made
+    /// it to disk in a previous session.
+    pub fn save(&mut self, state: &State, settings: &Settings, dirty: &DirtyRows) -> Result<()> {
+        if !self.rows.synchronized {
+            write_state_file(state, settings)?;
+            for path in [compacting_path(settings), journal_path(settings)] {
+                if path.exists() {
+                    std::fs::remove_file(&path)
+                        .context(format!("Failed to remove old journal {path:?}"))?;
+                }
+            }
+
+            let changes = self.rows.changes(state, dirty);
+            self.rows.apply(changes);
+            self.records = 0;
+            self.environments.clear();
+            return Ok(());
+        }
+
+        let changes = self.rows.changes(state, dirty);
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let path = journal_path(settings);
+        let mut records = Vec::with_capacity(changes.len() + 1);
+        if !path.exists() {
+            records.push(Record::Version(STATE_VERSION));
+        }
+        for task in changes.tasks.iter() {
+            self.push_task_records(&mut records, task);
+        }
+        for id in changes.removed_tasks.iter() {
+            records.push(Record::RemoveTask(*id));
+        }
+        for (name, group) in changes.groups.iter() {
+            records.push(Record::Group {
+                name: Cow::Borrowed(name.as_str()),
+                group: Cow::Borrowed(*group),
+            });
+        }
+        for name in changes.removed_groups.iter() {
+            records.push(Record::RemoveGroup(name.clone()));
+        }
+
+        // Write all records at once, so a crash can at most leave a single broken line.
+        let mut data = String::new();
+        for record in records.iter() {
+            data.push_str(&serde_json::to_string(record).context("Failed to serialize record")?);
+            data.push('\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(format!("Failed to open state journal at {path:?}"))?;
+        file.write_all(data.as_bytes())
+            .context("Failed to append to state journal")?;
+
+        self.records += changes.len();
+        self.rows.apply(changes);
+        debug!("Appended {} records to {path:?}", records.len());
+
+        if self.records >= COMPACTION_THRESHOLD {
+            self.compact(state, settings)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add the record of a changed task.
+    /// If the task's base environment hasn't been written to the journal yet, a record for the
+    /// environment is added first.
+    fn push_task_records<'a>(&mut self, records: &mut Vec<Record<'a>>, task: &'a Task) {
+        let base = task.envs.base();
+        let key = EnvironmentInterner::key(&task.envs);
+        match self.environments.get(&key) {
+            Some(written) if Arc::ptr_eq(written, base) || written == base => (),
+            // Two different environments with the same hash. This is very unlikely, but in that
+            // case the environment is simply kept in the task itself.
+            Some(_) => {
+                records.push(Record::Task {
+                    task: Cow::Borrowed(task),
+                    environment: None,
+                });
+                return;
+            }
+            None => {
+                self.environments.insert(key.clone(), base.clone());
+                records.push(Record::Environment {
+                    key: key.clone(),
+                    variables: Cow::Borrowed(&**base),
+                });
+            }
+        }
+
+        let mut stripped = task.clone();
+        stripped.envs = Envs::default();
+        records.push(Record::Task {
+            task: Cow::Owned(stripped),
+            environment: Some(TaskEnvironment {
+                base: key,
+                changes: task.envs.changes().clone(),
+            }),
+        });
+    }
+
+    /// Rotate the journal and write a new snapshot of the given state in a background thread,
+    /// so the state doesn't stay locked while the snapshot is written.
+    ///
+    /// Records that are appended in the meantime end up in a new journal.
+    /// The rotated journal is only removed once the snapshot has been written, which is why
+    /// nothing gets lost if the daemon crashes in between.
+    fn compact(&mut self, state: &State, settings: &Settings) -> Result<()> {
+        if self.compacting.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        // If there's still a rotated journal, a previous snapshot couldn't be written.
+        // In that case, the current journal is kept as well, as the new snapshot covers both.
+        let compacting = compacting_path(settings);
+        if !compacting.exists() {
+            if let Err(err) = std::fs::rename(journal_path(settings), &compacting) {
+                self.compacting.store(false, Ordering::SeqCst);
+                return Err(err).context("Failed to rotate state journal");
+            }
+            self.environments.clear();
+        }
+        // The new snapshot covers all records so far, either way.
+        self.records = 0;
+
+        let state = state.clone();
+        let settings = settings.clone();
+        let running = self.compacting.clone();
+        std::thread::spawn(move || {
+            match write_state_file(&state, &settings) {
+                Ok(()) => {
+                    if let Err(err) = std::fs::remove_file(&compacting) {
+                        error!("Failed to remove compacted state journal: {err:?}");
+                    }
+                }
+                Err(err) => error!("Failed to compact state journal: {err:?}"),
+            }
+            running.store(false, Ordering::SeqCst);
+        });
+
+        Ok(())
+    }
+}
+
+/// Replay the journals on top of the last snapshot.
+///
+/// Returns `None` if neither a snapshot nor a journal exists.
+pub fn replay(snapshot: Option<State>, settings: &Settings) -> Result<Option<State>> {
+    let mut state = snapshot;
+    let mut environments = HashMap::new();
+
+    // A rotated journal always contains older records than the current one.
+    for path in [compacting_path(settings), journal_path(settings)] {
+        if !path.exists() {
+            continue;
+        }
+        info!("Replaying state journal {path:?}");
+        // Every journal starts with its schema version.
+        let mut version = None;
+
+        let file = File::open(&path).context(format!("Failed to open journal {path:?}"))?;
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.context(format!("Failed to read journal {path:?}"))?;
+            // A crash while appending to the journal might leave a partially written record.
+            let mut record: Value = match serde_json::from_str(&line) {
+                Ok(record) => record,
+                Err(err) => {
+                    warn!("Skipping broken record in line {index} of {path:?}: {err}");
+                    continue;
+                }
+            };
+            let Some(record_version) = version else {
+                let journal_version = record
+                    .get("Version")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| eyre!("Journal {path:?} doesn't start with its version."))?;
+                migrations::check_version(journal_version)
+                    .context(format!("Failed to replay journal {path:?}"))?;
+                version = Some(journal_version);
+                continue;
+            };
+            migrate_record(&mut record, record_version).context(format!(
+                "Failed to migrate line {index} of journal {path:?}"
+            ))?;
+            let record: Record = match serde_json::from_value(record) {
+                Ok(record) => record,
+                Err(err) => {
+                    warn!("Skipping broken record in line {index} of {path:?}: {err}");
+                    continue;
+                }
+            };
+
+            let state = state.get_or_insert_with(State::new);
+            match record {
+                Record::Task { task, environment } => {
+                    let mut task = task.into_owned();
+                    if let Some(environment) = environment {
+                        task.envs = environment.restore(&environments)?;
+                    }
+                    state.tasks.insert(task.id, task);
+                }
+                Record::RemoveTask(id) => {
+                    state.tasks.remove(&id);
+                }
+                Record::Group { name, group } => {
+                    state.groups.insert(name.into_owned(), group.into_owned());
+                }
+                Record::RemoveGroup(name) => {
+                    state.groups.remove(&name);
+                }
+                Record::Environment { key, variables } => {
+                    environments.insert(key, Arc::new(variables.into_owned()));
+                }
+                Record::Version(_) => {
+                    bail!("Unexpected version in line {index} of journal {path:?}.")
+                }
+            }
+        }
+    }
+
+    Ok(state)
+}
+
+/// Upgrade the task or group of a record that has been written with an older schema version.
+fn migrate_record(record: &mut Value, version: u64) -> Result<()> {
+    if let Some(task) = record.pointer_mut("/Task/task") {
+        migrations::migrate_task(task, version)?;
+    }
+    if let Some(group) = record.pointer_mut("/Group/group") {
+        migrations::migrate_group(group, version)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+    use crate::daemon::network::message_handler::fixtures::*;
+
+    #[test]
+    /// Changes are appended to the journal and replayed on top of the first snapshot.
+    /// A partially written record at the end of the journal is skipped.
+    fn save_and_replay() -> Result<()> {
+        let (_, settings, _tempdir) = get_state();
+
+        let mut state = State::new();
+        state.add_task(get_stub_task("0", StubStatus::Queued));
+        state.add_task(get_stub_task("1", StubStatus::Queued));
+        let snapshot = state.clone();
+
+        let mut journal = Journal::default();
+        journal.save(&state, &settings, &DirtyRows::default())?;
+        assert!(!journal_path(&settings).exists());
+
+        state.tasks.remove(&0);
+        state.tasks.get_mut(&1).unwrap().label = Some("changed".into());
+        state.add_task(get_stub_task("2", StubStatus::Queued));
+        let mut dirty = DirtyRows::default();
+        for id in 0..3 {
+            dirty.task(id);
+        }
+        journal.save(&state, &settings, &dirty)?;
+        assert_eq!(journal.records, 3);
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(journal_path(&settings))?;
+        file.write_all(b"{\"Task\":{\"id\"")?;
+
+        assert_eq!(replay(Some(snapshot), &settings)?, Some(state));
+
+        Ok(())
+    }
+
+    #[test]
+    /// Records of journals that have been written by an older version of Pueue are migrated.
+    fn replay_old_journal() -> Result<()> {
+        let (_, settings, _tempdir) = get_state();
+
+        let mut state = State::new();
+        state.add_task(get_stub_task("0", StubStatus::Queued));
+
+        // Tasks of version 0 didn't contain the timestamps flag yet.
+        let mut task = serde_json::to_value(&state.tasks[&0])?;
+        task.as_object_mut().unwrap().remove("timestamps");
+        let journal = format!(
+            "{}\n{}\n",
+            serde_json::to_string(&Record::Version(0))?,
+            json!({"Task": {"task": task}}),
+        );
+        std::fs::write(journal_path(&settings), journal)?;
+
+        assert_eq!(replay(None, &settings)?, Some(state));
+
+        Ok(())
+    }
+
+    #[test]
+    /// Journals that don't start with their version are rejected.
+    fn reject_unversioned_journal() -> Result<()> {
+        let (_, settings, _tempdir) = get_state();
+
+        let task = get_stub_task("0", StubStatus::Queued);
+        let record = Record::Task {
+            task: Cow::Owned(task),
+            environment: None,
+        };
+        std::fs::write(
+            journal_path(&settings),
+            format!("{}\n", serde_json::to_string(&record)?),
+        )?;
+
+        assert!(replay(None, &settings).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use serde_json::{Map, Value};
+
+use crate::internal_prelude::*;
+
+/// The current schema version of the state file.
+///
+/// Whenever the schema changes, bump this version and add a migration to [MIGRATIONS].
+pub const STATE_VERSION: u64 = 1;
+
+/// The changes of a single schema version.
+///
+/// Tasks and groups are migrated one by one, so the same migrations can be applied to the
+/// rows of the incremental state backends.
+struct Migration {
+    /// Upgrade a single task.
+    task: fn(&mut Map<String, Value>),
+    /// Upgrade a single group.
+    group: fn(&mut Map<String, Value>),
+    /// Upgrade the top-level fields of a state file, which don't belong to any task or group.
+    state: fn(&mut Map<String, Value>),
+}
+
+/// All migrations in order.
+/// The migration at index `n` upgrades a state from version `n` to version `n + 1`.
+const MIGRATIONS: [Migration; STATE_VERSION as usize] = [
+    // Version 0 is the state of Pueue v4.0.0, which didn't record its version yet.
+    // All fields that have been added since then have defaults.
+    Migration {
+        task: unchanged,
+        group: unchanged,
+        state: unchanged,
+    },
+];
+
+/// Make sure that a state of the given version can be upgraded by this version of Pueue.
+pub fn check_version(version: u64) -> Result<()> {
+    if version > STATE_VERSION {
+        bail!(
+            "The state has been written by a newer version of Pueue (state version {version}, \
+            supported up to {STATE_VERSION}). Please update Pueue."
+        );
+    }
+
+    Ok(())
+}
+
+/// Upgrade a state to the current [STATE_VERSION] step by step.
+///
+/// Returns the version the state had before the migration.
+pub fn migrate(state: &mut Value) -> Result<u64> {
+    let Some(state) = state.as_object_mut() else {
+        bail!("The state file doesn't contain a json object.");
+    };
+
+    // The state didn't have a version before the first migration.
+    let version = match state.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| eyre!("Invalid state version: {version}"))?,
+    };
+    check_version(version)?;
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let to = from as u64 + 1;
+        apply(state, migration).context(format!(
+            "Failed to migrate state from version {from} to {to}."
+        ))?;
+        state.insert("version".into(), Value::from(to));
+        info!("Migrated state from version {from} to {to}");
+    }
+
+    Ok(version)
+}
+
+/// Apply a single migration to all tasks and groups of a state file.
+fn apply(state: &mut Map<String, Value>, migration: &Migration) -> Result<()> {
+    let tasks = state
+        .get_mut("tasks")
+        .ok_or_else(|| eyre!("The state doesn't contain any tasks."))?;
+    for (id, task) in object_mut(tasks, "tasks")?.iter_mut() {
+        (migration.task)(object_mut(task, &format!("task {id}"))?);
+    }
+
+    let groups = state
+        .get_mut("groups")
+        .ok_or_else(|| eyre!("The state doesn't contain any groups."))?;
+    for (name, group) in object_mut(groups, "groups")?.iter_mut() {
+        (migration.group)(object_mut(group, &format!("group {name}"))?);
+    }
+
+    (migration.state)(state);
+
+    Ok(())
+}
+
+/// Upgrade a single task that has been stored with the given version to the current
+/// [STATE_VERSION].
+pub fn migrate_task(task: &mut Value, version: u64) -> Result<()> {
+    check_version(version)?;
+    let task = object_mut(task, "task")?;
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        (migration.task)(task);
+    }
+
+    Ok(())
+}
+
+/// Upgrade a single group that has been stored with the given version to the current
+/// [STATE_VERSION].
+pub fn migrate_group(group: &mut Value, version: u64) -> Result<()> {
+    check_version(version)?;
+    let group = object_mut(group, "group")?;
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        (migration.group)(group);
+    }
+
+    Ok(())
+}
+
+/// Get a mutable json object or fail with a helpful error message.
+fn object_mut<'a>(value: &'a mut Value, name: &str) -> Result<&'a mut Map<String, Value>> {
+    value
+        .as_object_mut()
+        .ok_or_else(|| eyre!("Expected {name} to be a json object."))
+}
+
+/// Used for the parts of a state that didn't change in a version.
+fn unchanged(_: &mut Map<String, Value>) {}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    /// A state without a version is upgraded to the current version.
+    fn migrate_unversioned_state() -> Result<()> {
+        let mut state = json!({
+            "tasks": {"0": {"id": 0}},
+            "groups": {"default": {"status": "Running", "parallel_tasks": 1}},
+        });
+
+        assert_eq!(migrate(&mut state)?, 0);
+        assert_eq!(state["version"], json!(STATE_VERSION));
+        assert_eq!(state["tasks"]["0"], json!({"id": 0}));
+
+        Ok(())
+    }
+
+    #[test]
+    /// States of newer versions and broken states are rejected.
+    fn reject_invalid_states() {
+        let mut state = json!({"version": STATE_VERSION + 1, "tasks": {}, "groups": {}});
+        assert!(migrate(&mut state).is_err());
+
+        let mut state = json!({"tasks": []});
+        assert!(migrate(&mut state).is_err());
+
+        let mut task = json!({"id": 0});
+        assert!(migrate_task(&mut task, STATE_VERSION + 1).is_err());
+    }
+
+    #[test]
+    /// Single tasks and groups of older versions keep their fields.
+    fn migrate_rows() -> Result<()> {
+        let mut task = json!({"id": 0, "process": {"pid": 10}});
+        migrate_task(&mut task, 0)?;
+        assert_eq!(task, json!({"id": 0, "process": {"pid": 10}}));
+
+        let mut group = json!({"status": "Running", "parallel_tasks": 1});
+        migrate_group(&mut group, 0)?;
+        assert_eq!(group, json!({"status": "Running", "parallel_tasks": 1}));
+
+        Ok(())
+    }
+}
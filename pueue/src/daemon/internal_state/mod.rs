@@ -5,7 +5,19 @@ use state::InternalState;
 /// A helper newtype struct, which implements convenience methods for our child process management
 /// datastructure.
 pub mod children;
+/// An alternative state backend, which appends every change to a journal next to the last
+/// snapshot of the state and compacts it from time to time.
+pub mod journal;
+/// The migrations that upgrade states of older Pueue versions to the current schema.
+pub mod migrations;
+/// Change detection for the incremental state backends.
+pub mod rows;
+/// An alternative state backend, which stores every task and group as a separate row in a
+/// SQLite database and only writes rows that changed.
+pub mod sqlite;
 /// The main struct used to represent the daemon's current state.
 pub mod state;
+/// The representation of the state in the `state.json` file.
+pub mod state_file;
 
 pub type SharedState = Arc<Mutex<InternalState>>;
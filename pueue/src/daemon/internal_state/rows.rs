@@ -0,0 +1,184 @@
+use std::collections::BTreeSet;
+
+use pueue_lib::{Group, State, Task};
+
+/// The tasks and groups that changed since the state has been saved the last time.
+///
+/// Changes are recorded at the mutation sites of the
+/// [InternalState](crate::daemon::internal_state::state::InternalState), so the incremental state
+/// backends only have to write these rows.
+#[derive(Debug, Default)]
+pub struct DirtyRows {
+    /// Tasks or groups have been changed in bulk, which is why all rows have to be written.
+    all: bool,
+    tasks: BTreeSet<usize>,
+    groups: BTreeSet<String>,
+}
+
+impl DirtyRows {
+    /// Mark a task as changed. This includes tasks that have been added or removed.
+    pub fn task(&mut self, id: usize) {
+        if !self.all {
+            self.tasks.insert(id);
+        }
+    }
+
+    /// Mark a group as changed. This includes groups that have been added or removed.
+    pub fn group(&mut self, name: &str) {
+        if !self.all && !self.groups.contains(name) {
+            self.groups.insert(name.to_string());
+        }
+    }
+
+    /// Mark all tasks and groups as changed.
+    pub fn all(&mut self) {
+        self.all = true;
+        self.tasks.clear();
+        self.groups.clear();
+    }
+
+    /// Add the rows of a save that failed, so they're written on the next save.
+    pub fn merge(&mut self, other: DirtyRows) {
+        if other.all {
+            self.all();
+        }
+        for id in other.tasks {
+            self.task(id);
+        }
+        for name in other.groups {
+            self.group(&name);
+        }
+    }
+}
+
+/// The ids of all tasks and groups that have been persisted by this daemon.
+/// This is used by the incremental state backends to determine which rows have to be removed.
+#[derive(Debug, Default)]
+pub struct PersistedRows {
+    /// Whether the persisted state has been fully synchronized with the in-memory state yet.
+    /// Until this happens, we don't know which rows have been persisted.
+    pub synchronized: bool,
+    tasks: BTreeSet<usize>,
+    groups: BTreeSet<String>,
+}
+
+/// All tasks and groups that changed or have been removed since the last save.
+#[derive(Debug, Default)]
+pub struct RowChanges<'a> {
+    pub tasks: Vec<&'a Task>,
+    pub removed_tasks: Vec<usize>,
+    pub groups: Vec<(&'a String, &'a Group)>,
+    pub removed_groups: Vec<String>,
+}
+
+impl RowChanges<'_> {
+    /// The total amount of changed and removed rows.
+    pub fn len(&self) -> usize {
+        self.tasks.len() + self.removed_tasks.len() + self.groups.len() + self.removed_groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl PersistedRows {
+    /// Determine the rows that have to be written for the given changes.
+    ///
+    /// If the rows haven't been synchronized yet, all rows are considered as changed.
+    pub fn changes<'a>(&self, state: &'a State, dirty: &DirtyRows) -> RowChanges<'a> {
+        let mut changes = RowChanges::default();
+
+        if !self.synchronized || dirty.all {
+            changes.tasks = state.tasks.values().collect();
+            changes.removed_tasks = self
+                .tasks
+                .iter()
+                .filter(|id| !state.tasks.contains_key(*id))
+                .copied()
+                .collect();
+            changes.groups = state.groups.iter().collect();
+            changes.removed_groups = self
+                .groups
+                .iter()
+                .filter(|name| !state.groups.contains_key(*name))
+                .cloned()
+                .collect();
+
+            return changes;
+        }
+
+        for id in dirty.tasks.iter() {
+            match state.tasks.get(id) {
+                Some(task) => changes.tasks.push(task),
+                None if self.tasks.contains(id) => changes.removed_tasks.push(*id),
+                None => (),
+            }
+        }
+        for name in dirty.groups.iter() {
+            match state.groups.get_key_value(name) {
+                Some(group) => changes.groups.push(group),
+                None if self.groups.contains(name) => changes.removed_groups.push(name.clone()),
+                None => (),
+            }
+        }
+
+        changes
+    }
+
+    /// Remember the rows of the given changes.
+    /// Only call this once the changes actually made it to disk.
+    pub fn apply(&mut self, changes: RowChanges) {
+        self.synchronized = true;
+        for task in changes.tasks {
+            self.tasks.insert(task.id);
+        }
+        for id in changes.removed_tasks.iter() {
+            self.tasks.remove(id);
+        }
+        for (name, _) in changes.groups {
+            self.groups.insert(name.clone());
+        }
+        for name in changes.removed_groups.iter() {
+            self.groups.remove(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::daemon::network::message_handler::fixtures::*;
+
+    #[test]
+    /// Only rows that have been marked as dirty are written once the rows are synchronized.
+    /// Dirty rows that no longer exist are removed, if they have been persisted before.
+    fn only_dirty_rows_change() {
+        let mut state = State::new();
+        for id in 0..3 {
+            state.add_task(get_stub_task(&id.to_string(), StubStatus::Queued));
+        }
+
+        let mut persisted = PersistedRows::default();
+        let changes = persisted.changes(&state, &DirtyRows::default());
+        assert_eq!(changes.tasks.len(), 3);
+        persisted.apply(changes);
+
+        state.tasks.remove(&0);
+        let mut dirty = DirtyRows::default();
+        dirty.task(0);
+        dirty.task(2);
+        // This task has never been persisted.
+        dirty.task(5);
+
+        let changes = persisted.changes(&state, &dirty);
+        assert_eq!(
+            changes.tasks.iter().map(|task| task.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(changes.removed_tasks, vec![0]);
+        assert!(changes.groups.is_empty());
+    }
+}
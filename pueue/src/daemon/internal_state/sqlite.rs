@@ -0,0 +1,464 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use pueue_lib::{Group, Settings, State, Task, task::Envs};
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use serde_json::Value;
+
+use crate::{
+    daemon::internal_state::{
+        migrations::{self, STATE_VERSION},
+        rows::{DirtyRows, PersistedRows},
+        state_file::{EnvironmentInterner, TaskEnvironment},
+    },
+    internal_prelude::*,
+};
+
+/// The path of the database file inside the pueue directory.
+pub fn database_path(settings: &Settings) -> PathBuf {
+    settings.shared.pueue_directory().join("state.sqlite")
+}
+
+/// The bookkeeping of the SQLite backend.
+#[derive(Debug, Default)]
+pub struct Database {
+    /// The connection is opened on the first save and kept open afterwards.
+    connection: Option<(PathBuf, Connection)>,
+    rows: PersistedRows,
+    /// The keys of all base environments that are known to be stored in the database.
+    environments: BTreeSet<String>,
+}
+
+/// Open the database and make sure that all tables exist.
+///
+/// The schema version of the stored tasks and groups is kept in the `user_version` pragma.
+/// New databases always start with the current [STATE_VERSION], rows of older databases are
+/// migrated to it.
+fn open(path: &Path) -> Result<Connection> {
+    let mut connection =
+        Connection::open(path).context(format!("Failed to open state database at {path:?}"))?;
+
+    let is_new: bool = connection
+        .query_row(
+            "SELECT NOT EXISTS \
+            (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'tasks')",
+            [],
+            |row| row.get(0),
+        )
+        .context("Failed to inspect state database")?;
+
+    connection
+        .execute_batch(
+            "PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            CREATE TABLE IF NOT EXISTS tasks (
+                id INTEGER PRIMARY KEY,
+                data TEXT NOT NULL,
+                environment TEXT
+            );
+            CREATE TABLE IF NOT EXISTS groups (name TEXT PRIMARY KEY, data TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS environments (key TEXT PRIMARY KEY, data TEXT NOT NULL);",
+        )
+        .context("Failed to initialize state database")?;
+
+    if is_new {
+        set_schema_version(&connection, STATE_VERSION)?;
+    }
+
+    let version = schema_version(&connection)?;
+    migrations::check_version(version)
+        .context(format!("Failed to open state database at {path:?}"))?;
+    if version < STATE_VERSION {
+        migrate_rows(&mut connection, version)
+            .context(format!("Failed to migrate state database at {path:?}"))?;
+    }
+
+    Ok(connection)
+}
+
+/// Upgrade all tasks and groups in the database from the given version to the current
+/// [STATE_VERSION].
+fn migrate_rows(connection: &mut Connection, version: u64) -> Result<()> {
+    let transaction = connection.transaction()?;
+
+    type MigrateRow = fn(&mut Value, u64) -> Result<()>;
+    let tables: [(&str, MigrateRow); 2] = [
+        ("tasks", migrations::migrate_task),
+        ("groups", migrations::migrate_group),
+    ];
+    for (table, migrate) in tables {
+        let rows = transaction
+            .prepare(&format!("SELECT rowid, data FROM {table}"))?
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for (rowid, data) in rows {
+            let mut value: Value = serde_json::from_str(&data)
+                .context(format!("Failed to deserialize row {rowid} of {table}"))?;
+            migrate(&mut value, version)?;
+            transaction.execute(
+                &format!("UPDATE {table} SET data = ?1 WHERE rowid = ?2"),
+                params![value.to_string(), rowid],
+            )?;
+        }
+    }
+
+    set_schema_version(&transaction, STATE_VERSION)?;
+    transaction.commit()?;
+    info!("Migrated state database from version {version} to {STATE_VERSION}");
+
+    Ok(())
+}
+
+/// The schema version of the tasks and groups in the database.
+fn schema_version(connection: &Connection) -> Result<u64> {
+    let version: i64 = connection
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .context("Failed to read version of state database")?;
+
+    Ok(version as u64)
+}
+
+fn set_schema_version(connection: &Connection, version: u64) -> Result<()> {
+    connection
+        .pragma_update(None, "user_version", version as i64)
+        .context("Failed to write version of state database")?;
+
+    Ok(())
+}
+
+impl Database {
+    /// Write all tasks and groups that changed since the last save to the database.
+    /// Rows of tasks and groups that no longer exist are removed.
+    ///
+    /// Just like in the `state.json`, the base environment of tasks is stored only once in the
+    /// `environments` table and referenced by the tasks.
+    pub fn save(&mut self, state: &State, path: &Path, dirty: &DirtyRows) -> Result<()> {
+        // Reopen the database, if it moved to another location in the meantime.
+        let connection = match &mut self.connection {
+            Some((current, connection)) if current == path => connection,
+            connection => {
+                self.rows = PersistedRows::default();
+                self.environments.clear();
+                &mut connection.insert((path.to_path_buf(), open(path)?)).1
+            }
+        };
+
+        let changes = self.rows.changes(state, dirty);
+        let transaction = connection.transaction()?;
+
+        // On the very first save, the database is brought in line with the in-memory state.
+        if !self.rows.synchronized {
+            transaction.execute_batch(
+                "DELETE FROM tasks; DELETE FROM groups; DELETE FROM environments;",
+            )?;
+            self.environments.clear();
+        }
+
+        let mut interner = EnvironmentInterner::default();
+        let references: Vec<Option<TaskEnvironment>> = changes
+            .tasks
+            .iter()
+            .map(|task| interner.reference(&task.envs))
+            .collect();
+        let (stored, conflicts) =
+            store_environments(&self.environments, &transaction, interner.environments)?;
+
+        for (task, environment) in changes.tasks.iter().zip(references) {
+            // The environment is kept in the task itself, if its base cannot be referenced.
+            let environment = environment.filter(|env| !conflicts.contains(&env.base));
+            let data = match environment {
+                Some(_) => {
+                    let mut task = (*task).clone();
+                    task.envs = Envs::default();
+                    serde_json::to_string(&task)
+                }
+                None => serde_json::to_string(task),
+            }
+            .context("Failed to serialize task")?;
+            let environment = environment
+                .map(|env| serde_json::to_string(&env))
+                .transpose()
+                .context("Failed to serialize task environment")?;
+
+            transaction.execute(
+                "INSERT OR REPLACE INTO tasks (id, data, environment) VALUES (?1, ?2, ?3)",
+                params![task.id as i64, data, environment],
+            )?;
+        }
+        for id in changes.removed_tasks.iter() {
+            transaction.execute("DELETE FROM tasks WHERE id = ?1", params![*id as i64])?;
+        }
+        // Remove base environments that are no longer used by any task.
+        if !changes.removed_tasks.is_empty() {
+            transaction.execute(
+                "DELETE FROM environments WHERE key NOT IN \
+                (SELECT json_extract(environment, '$.base') FROM tasks \
+                WHERE environment IS NOT NULL)",
+                [],
+            )?;
+        }
+
+        for (name, group) in changes.groups.iter() {
+            let data = serde_json::to_string(group).context("Failed to serialize group")?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO groups (name, data) VALUES (?1, ?2)",
+                params![name, data],
+            )?;
+        }
+        for name in changes.removed_groups.iter() {
+            transaction.execute("DELETE FROM groups WHERE name = ?1", params![name])?;
+        }
+
+        transaction
+            .commit()
+            .context("Failed to commit state to database")?;
+
+        // Only remember the written rows, once they actually made it to disk.
+        // Unused environments might have been removed, so we no longer know which are stored.
+        if changes.removed_tasks.is_empty() {
+            self.environments.extend(stored);
+        } else {
+            self.environments.clear();
+        }
+        self.rows.apply(changes);
+        debug!("State saved at: {path:?}");
+
+        Ok(())
+    }
+}
+
+/// Store all base environments that aren't `known` to be in the database yet.
+///
+/// Returns the keys of all environments that are now stored in the database and the keys of
+/// environments that collide with a different environment in the database. Tasks keep the
+/// latter in their own row instead.
+fn store_environments(
+    known: &BTreeSet<String>,
+    transaction: &Transaction,
+    environments: BTreeMap<String, HashMap<String, String>>,
+) -> Result<(Vec<String>, BTreeSet<String>)> {
+    let mut stored = Vec::new();
+    let mut conflicts = BTreeSet::new();
+    for (key, environment) in environments {
+        if known.contains(&key) {
+            continue;
+        }
+
+        let existing: Option<String> = transaction
+            .query_row(
+                "SELECT data FROM environments WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match existing {
+            None => {
+                let data = serde_json::to_string(&environment)
+                    .context("Failed to serialize environment")?;
+                transaction.execute(
+                    "INSERT INTO environments (key, data) VALUES (?1, ?2)",
+                    params![key, data],
+                )?;
+            }
+            Some(existing) => {
+                let existing: HashMap<String, String> = serde_json::from_str(&existing)
+                    .context("Failed to deserialize environment from database")?;
+                if existing != environment {
+                    conflicts.insert(key);
+                    continue;
+                }
+            }
+        }
+        stored.push(key);
+    }
+
+    Ok((stored, conflicts))
+}
+
+/// Load the state from the database.
+/// Returns `None`, if there's no database yet.
+pub fn load(path: &Path) -> Result<Option<State>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let connection = open(path)?;
+
+    let mut environments = HashMap::new();
+    let mut statement = connection.prepare("SELECT key, data FROM environments")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let key: String = row.get(0)?;
+        let data: String = row.get(1)?;
+        let environment: HashMap<String, String> = serde_json::from_str(&data).context(format!(
+            "Failed to deserialize environment {key} from database"
+        ))?;
+        environments.insert(key, Arc::new(environment));
+    }
+
+    let mut tasks = BTreeMap::new();
+    let mut statement = connection.prepare("SELECT id, data, environment FROM tasks")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let data: String = row.get(1)?;
+        let environment: Option<String> = row.get(2)?;
+        let mut task: Task = serde_json::from_str(&data)
+            .context(format!("Failed to deserialize task {id} from database"))?;
+        if let Some(environment) = environment {
+            let environment: TaskEnvironment = serde_json::from_str(&environment).context(
+                format!("Failed to deserialize environment of task {id} from database"),
+            )?;
+            task.envs = environment.restore(&environments)?;
+        }
+        tasks.insert(id as usize, task);
+    }
+
+    let mut groups = BTreeMap::new();
+    let mut statement = connection.prepare("SELECT name, data FROM groups")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let data: String = row.get(1)?;
+        let group: Group = serde_json::from_str(&data)
+            .context(format!("Failed to deserialize group {name} from database"))?;
+        groups.insert(name, group);
+    }
+
+    Ok(Some(State { tasks, groups }))
+}
+
+/// Move a state from a previous `state.json` or `state.json.gz` into a new database.
+///
+/// The old file is renamed to `*.migrated` afterwards, so it's kept around just in case,
+/// but won't be picked up again.
+pub fn migrate(state: &State, path: &Path, old_file: &Path) -> Result<()> {
+    info!("Migrating state from {old_file:?} to {path:?}");
+    Database::default().save(state, path, &DirtyRows::default())?;
+
+    let mut migrated = old_file.as_os_str().to_owned();
+    migrated.push(".migrated");
+    std::fs::rename(old_file, &migrated)
+        .context(format!("Failed to rename migrated state file {old_file:?}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::daemon::network::message_handler::fixtures::*;
+
+    #[test]
+    /// Tasks and groups survive a round trip through the database.
+    /// Removed tasks are also removed from the database.
+    fn save_and_load() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let path = tempdir.path().join("state.sqlite");
+
+        let mut state = State::new();
+        state.groups.insert(
+            PUEUE_DEFAULT_GROUP.into(),
+            Group {
+                status: pueue_lib::GroupStatus::Running,
+                parallel_tasks: 1,
+                preemption_margin: None,
+                max_queued: None,
+            },
+        );
+        state.add_task(get_stub_task("0", StubStatus::Queued));
+        state.add_task(get_stub_task("1", StubStatus::Queued));
+
+        let mut database = Database::default();
+        database.save(&state, &path, &DirtyRows::default())?;
+        assert_eq!(load(&path)?.as_ref(), Some(&state));
+
+        state.tasks.remove(&0);
+        state.tasks.get_mut(&1).unwrap().label = Some("changed".into());
+        let mut dirty = DirtyRows::default();
+        dirty.task(0);
+        dirty.task(1);
+        database.save(&state, &path, &dirty)?;
+        assert_eq!(load(&path)?.as_ref(), Some(&state));
+        assert_eq!(schema_version(&open(&path)?)?, STATE_VERSION);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Rows of databases that have been written by an older version of Pueue are migrated.
+    fn migrate_old_database() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let path = tempdir.path().join("state.sqlite");
+
+        let mut state = State::new();
+        state.add_task(get_stub_task("0", StubStatus::Queued));
+        Database::default().save(&state, &path, &DirtyRows::default())?;
+
+        // Rows of version 0 didn't contain the timestamps flag yet.
+        let connection = Connection::open(&path)?;
+        connection.execute(
+            "UPDATE tasks SET data = json_remove(data, '$.timestamps')",
+            [],
+        )?;
+        set_schema_version(&connection, 0)?;
+        drop(connection);
+
+        assert_eq!(load(&path)?, Some(state));
+        assert_eq!(schema_version(&open(&path)?)?, STATE_VERSION);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Identical base environments are only stored once and are shared again after loading.
+    /// Environments that are no longer used by any task are removed.
+    fn deduplicate_environments() -> Result<()> {
+        let tempdir = TempDir::new()?;
+        let path = tempdir.path().join("state.sqlite");
+
+        let mut state = State::new();
+        let envs = HashMap::from([("PATH".to_string(), "/bin".to_string())]);
+        for id in 0..3 {
+            let mut task = get_stub_task(&id.to_string(), StubStatus::Queued);
+            task.envs = state.intern_envs(envs.clone());
+            state.add_task(task);
+        }
+        state.tasks.get_mut(&2).unwrap().envs.remove("PATH");
+
+        let mut database = Database::default();
+        database.save(&state, &path, &DirtyRows::default())?;
+        let count_environments =
+            || -> Result<i64> {
+                Ok(open(&path)?
+                    .query_row("SELECT COUNT(*) FROM environments", [], |row| row.get(0))?)
+            };
+        assert_eq!(count_environments()?, 1);
+
+        let restored = load(&path)?.unwrap();
+        assert_eq!(restored, state);
+        assert!(Arc::ptr_eq(
+            restored.tasks[&0].envs.base(),
+            restored.tasks[&2].envs.base()
+        ));
+
+        let mut dirty = DirtyRows::default();
+        for id in 0..3 {
+            state.tasks.remove(&id);
+            dirty.task(id);
+        }
+        database.save(&state, &path, &dirty)?;
+        assert_eq!(count_environments()?, 0);
+
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap},
     fs::{File, read_to_string},
     io::{Read, Write},
+    path::Path,
     process::Child,
     sync::{Arc, Mutex, MutexGuard},
 };
@@ -9,15 +10,32 @@ use std::{
 use chrono::Local;
 use flate2::Compression;
 use pueue_lib::{
-    Group, GroupStatus, Settings, State, TaskResult,
+    Group, GroupStatus, Settings, State,
     error::Error,
     message::request::ShutdownRequest,
+    settings::StateBackend,
     state::{FilteredTasks, PUEUE_DEFAULT_GROUP},
-    task::{Task, TaskStatus},
+    task::{Envs, Task, TaskStatus},
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{daemon::internal_state::children::Children, internal_prelude::*};
+use crate::{
+    daemon::{
+        capture::OutputCapture,
+        internal_state::{
+            children::Children,
+            journal::{self, Journal},
+            migrations::{self, STATE_VERSION},
+            rows::DirtyRows,
+            sqlite::{self, Database},
+            state_file::StateFile,
+        },
+        orphans,
+        process_handler::compress::LogCompressions,
+        supervisor,
+    },
+    internal_prelude::*,
+};
 
 pub type SharedState = Arc<Mutex<InternalState>>;
 pub type LockedState<'a> = MutexGuard<'a, InternalState>;
@@ -63,6 +81,28 @@ pub struct InternalState {
     /// These are the currently running callbacks. They're usually very short-lived.
     #[serde(default, skip)]
     pub callbacks: Vec<Child>,
+    /// The threads that record the output of running tasks, whose output is piped through the
+    /// daemon.
+    /// This is runtime state and won't be serialised to disk.
+    #[serde(default, skip)]
+    pub captures: BTreeMap<usize, OutputCapture>,
+    /// The compression of finished tasks' logs, which happens in the background.
+    /// This is runtime state and won't be serialised to disk.
+    #[serde(default, skip)]
+    pub log_compressions: LogCompressions,
+    /// The tasks and groups that changed since the last save.
+    /// This is runtime state and won't be serialised to disk.
+    #[serde(default, skip)]
+    pub dirty_rows: Mutex<DirtyRows>,
+    /// Bookkeeping of the SQLite backend, the open database and which rows have already been
+    /// written to it.
+    /// This is runtime state and won't be serialised to disk.
+    #[serde(default, skip)]
+    pub database: Mutex<Database>,
+    /// Bookkeeping of the journal backend.
+    /// This is runtime state and won't be serialised to disk.
+    #[serde(default, skip)]
+    pub journal: Mutex<Journal>,
 }
 
 // Implement a custom Clone, as the child processes don't implement Clone.
@@ -96,18 +136,38 @@ impl InternalState {
         &self.inner.tasks
     }
 
+    /// Get mutable access to all tasks. \
+    /// As we cannot know which tasks are changed, all of them are written on the next save.
+    /// Prefer [InternalState::task_mut] whenever possible.
     pub fn tasks_mut(&mut self) -> &mut BTreeMap<usize, Task> {
+        self.dirty_rows.get_mut().unwrap().all();
         &mut self.inner.tasks
     }
 
+    /// Get mutable access to a single task.
+    pub fn task_mut(&mut self, id: usize) -> Option<&mut Task> {
+        self.dirty_rows.get_mut().unwrap().task(id);
+        self.inner.tasks.get_mut(&id)
+    }
+
     pub fn groups(&self) -> &BTreeMap<String, Group> {
         &self.inner.groups
     }
 
+    /// Get mutable access to all groups. \
+    /// As we cannot know which groups are changed, all of them are written on the next save.
+    /// Prefer [InternalState::group_mut] whenever possible.
     pub fn groups_mut(&mut self) -> &mut BTreeMap<String, Group> {
+        self.dirty_rows.get_mut().unwrap().all();
         &mut self.inner.groups
     }
 
+    /// Get mutable access to a single group.
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut Group> {
+        self.dirty_rows.get_mut().unwrap().group(name);
+        self.inner.groups.get_mut(name)
+    }
+
     /// Add a new task
     pub fn add_task(&mut self, mut task: Task) -> usize {
         let next_id = match self.tasks().last_key_value() {
@@ -115,14 +175,21 @@ impl InternalState {
             Some((id, _)) => id + 1,
         };
         task.id = next_id;
-        self.tasks_mut().insert(next_id, task);
+        self.dirty_rows.get_mut().unwrap().task(next_id);
+        self.inner.tasks.insert(next_id, task);
 
         next_id
     }
 
+    /// Remove a task and return it, if it existed.
+    pub fn remove_task(&mut self, id: usize) -> Option<Task> {
+        self.dirty_rows.get_mut().unwrap().task(id);
+        self.inner.tasks.remove(&id)
+    }
+
     /// A small helper to change the status of a specific task.
     pub fn change_status(&mut self, id: usize, new_status: TaskStatus) {
-        if let Some(ref mut task) = self.tasks_mut().get_mut(&id) {
+        if let Some(task) = self.task_mut(id) {
             task.status = new_status;
         };
     }
@@ -131,12 +198,31 @@ impl InternalState {
     /// This also check if the given group already exists.
     /// Create a state.group entry and a settings.group entry, if it doesn't.
     pub fn create_group(&mut self, name: &str) -> &mut Group {
-        self.groups_mut().entry(name.into()).or_insert(Group {
+        self.dirty_rows.get_mut().unwrap().group(name);
+        self.inner.groups.entry(name.into()).or_insert(Group {
             status: GroupStatus::Running,
             parallel_tasks: 1,
+            preemption_margin: None,
+            max_queued: None,
         })
     }
 
+    /// Make sure that all groups declared in the `[daemon.groups]` section of the configuration
+    /// file exist and apply their configured amount of parallel tasks.
+    /// Groups that aren't declared are left untouched.
+    pub fn reconcile_groups(&mut self, settings: &Settings) {
+        for (name, declared) in settings.daemon.groups.iter() {
+            if !self.groups().contains_key(name) {
+                info!("Creating declared group \"{name}\"");
+            }
+
+            let group = self.create_group(name);
+            if let Some(parallel_tasks) = declared.parallel_tasks {
+                group.parallel_tasks = parallel_tasks;
+            }
+        }
+    }
+
     /// Remove a group.
     /// This also iterates through all tasks and sets any tasks' group
     /// to the `default` group if it matches the deleted group.
@@ -147,14 +233,17 @@ impl InternalState {
             ));
         }
 
-        self.groups_mut().remove(group);
+        self.dirty_rows.get_mut().unwrap().group(group);
+        self.inner.groups.remove(group);
 
         Ok(())
     }
 
     /// Set the group status (running/paused) for all groups including the default queue.
     pub fn set_status_for_all_groups(&mut self, status: GroupStatus) {
-        for (_, group) in self.groups_mut().iter_mut() {
+        let dirty = self.dirty_rows.get_mut().unwrap();
+        for (name, group) in self.inner.groups.iter_mut() {
+            dirty.group(name);
             group.status = status;
         }
     }
@@ -169,6 +258,17 @@ impl InternalState {
             .collect()
     }
 
+    /// Get the amount of tasks in a specific group that haven't been started yet.
+    /// This includes queued and stashed tasks.
+    pub fn queued_tasks_in_group(&self, group: &str) -> usize {
+        self.inner.queued_tasks_in_group(group)
+    }
+
+    /// Create the environment of a new task. See [State::intern_envs].
+    pub fn intern_envs(&self, envs: HashMap<String, String>) -> Envs {
+        self.inner.intern_envs(envs)
+    }
+
     /// This checks, whether some tasks match the expected filter criteria. \
     /// The first result is the list of task_ids that match these statuses. \
     /// The second result is the list of task_ids that don't match these statuses. \
@@ -231,8 +331,16 @@ impl InternalState {
     ///
     /// `group` should be the name of the failed task.
     pub fn pause_on_failure(&mut self, settings: &Settings, group: &str) {
-        if settings.daemon.pause_group_on_failure {
-            if let Some(group) = self.groups_mut().get_mut(group) {
+        // The group's own configuration takes precedence over the global one.
+        let pause_group = settings
+            .daemon
+            .groups
+            .get(group)
+            .and_then(|declared| declared.pause_on_failure)
+            .unwrap_or(settings.daemon.pause_group_on_failure);
+
+        if pause_group {
+            if let Some(group) = self.group_mut(group) {
                 group.status = GroupStatus::Paused;
             }
         } else if settings.daemon.pause_all_on_failure {
@@ -247,74 +355,47 @@ impl InternalState {
     /// In comparison to the daemon -> client communication, the state is saved
     /// as JSON for readability and debugging purposes.
     pub fn save(&self, settings: &Settings) -> Result<()> {
-        let serialized =
-            serde_json::to_string(&self.inner).context("Failed to serialize state:")?;
-
-        let path = settings.shared.pueue_directory();
-        let mut temp = path.join("state.json.partial");
-        let mut real = path.join("state.json");
-
-        if settings.daemon.compress_state_file {
-            temp = path.join("state.json.gz.partial");
-            real = path.join("state.json.gz");
-
-            let file = if temp.exists() {
-                File::open(&temp)?
-            } else {
-                File::create(&temp)?
-            };
+        let dirty = std::mem::take(&mut *self.dirty_rows.lock().unwrap());
+        let result = match settings.daemon.state_backend {
+            StateBackend::Json => write_state_file(&self.inner, settings),
+            StateBackend::Sqlite => self.database.lock().unwrap().save(
+                &self.inner,
+                &sqlite::database_path(settings),
+                &dirty,
+            ),
+            StateBackend::Journal => {
+                self.journal
+                    .lock()
+                    .unwrap()
+                    .save(&self.inner, settings, &dirty)
+            }
+        };
 
-            let mut encoder = flate2::write::GzEncoder::new(file, Compression::default());
-            encoder.write_all(serialized.as_bytes())?;
-        } else {
-            // Write to temporary log file first, to prevent loss due to crashes.
-            std::fs::write(&temp, serialized)
-                .context("Failed to write temp file while saving state.")?;
+        // The changed rows didn't make it to disk, so they have to be written on the next save.
+        if result.is_err() {
+            self.dirty_rows.lock().unwrap().merge(dirty);
         }
 
-        // Overwrite the original with the temp file, if everything went fine.
-        std::fs::rename(&temp, &real)
-            .context("Failed to overwrite old state while saving state")?;
-
-        debug!("State saved at: {real:?}");
-
-        Ok(())
+        result
     }
 
     /// Restore the last state from a previous session. \
-    /// The state is stored as json in the `pueue_directory`.
+    /// Depending on the configured [StateBackend], the state is stored as json, as json with
+    /// a journal of later changes or in a SQLite database in the `pueue_directory`.
     ///
-    /// If the state cannot be deserialized, an empty default state will be used instead. \
+    /// States of older versions are migrated to the current schema. If that fails or the state
+    /// cannot be deserialized, an error is returned. \
     /// All groups with queued tasks will be automatically paused to prevent unwanted execution.
     pub fn restore_state(settings: &Settings) -> Result<Option<InternalState>> {
-        let pueue_directory = settings.shared.pueue_directory();
-        let mut path = pueue_directory.join("state.json");
-
-        if settings.daemon.compress_state_file {
-            path = pueue_directory.join("state.json.gz");
-        }
-
-        // Ignore if the file doesn't exist. It doesn't have to.
-        if !path.exists() {
-            info!("Couldn't find state from previous session at location: {path:?}");
+        let state = match settings.daemon.state_backend {
+            StateBackend::Json => Self::read_state_file(settings)?,
+            StateBackend::Sqlite => Self::read_database(settings)?,
+            StateBackend::Journal => journal::replay(Self::read_state_file(settings)?, settings)?,
+        };
+        let Some(mut state) = state else {
             return Ok(None);
-        }
-        info!("Restoring state");
-
-        // Try to load the file.
-        let data = if settings.daemon.compress_state_file {
-            let file = File::open(path)?;
-            let mut decoder = flate2::read::GzDecoder::new(file);
-            let mut data = String::new();
-            decoder.read_to_string(&mut data)?;
-
-            data
-        } else {
-            read_to_string(&path).context("State restore: Failed to read file:\n\n{}")?
         };
-
-        // Try to deserialize the state file.
-        let state: State = serde_json::from_str(&data).context("Failed to deserialize state.")?;
+        state.deduplicate_envs();
 
         let mut state = InternalState {
             inner: state,
@@ -323,23 +404,33 @@ impl InternalState {
 
         // Restore all tasks.
         // While restoring the tasks, check for any invalid/broken stati.
+        let pueue_directory = settings.shared.pueue_directory();
         for (_, task) in state.inner.tasks.iter_mut() {
             // Handle ungraceful shutdowns while executing tasks.
+            // Tasks that run under a supervisor are re-adopted by the task handler instead.
             if let TaskStatus::Running { start, enqueued_at }
             | TaskStatus::Paused { start, enqueued_at } = task.status
             {
-                info!(
-                    "Setting task {} with previous status {:?} to new status {:?}",
-                    task.id,
-                    task.status,
-                    TaskResult::Killed
-                );
-                task.status = TaskStatus::Done {
-                    start,
-                    end: Local::now(),
-                    enqueued_at,
-                    result: TaskResult::Killed,
-                };
+                if supervisor::find_supervisor(task.id, &pueue_directory).is_some() {
+                    info!(
+                        "Task {} runs under a supervisor and will be re-adopted",
+                        task.id
+                    );
+                } else {
+                    // Processes of the task might have survived a crash of the daemon.
+                    let result = orphans::handle_orphan(settings, task);
+                    info!(
+                        "Setting task {} with previous status {:?} to new status {:?}",
+                        task.id, task.status, result
+                    );
+                    task.status = TaskStatus::Done {
+                        start,
+                        end: Local::now(),
+                        enqueued_at,
+                        result,
+                    };
+                    task.process = None;
+                }
             }
 
             // Handle crash during editing of the task command.
@@ -360,6 +451,8 @@ impl InternalState {
                         .or_insert(Group {
                             status: GroupStatus::Running,
                             parallel_tasks: 1,
+                            preemption_margin: None,
+                            max_queued: None,
                         })
                 }
             };
@@ -377,4 +470,123 @@ impl InternalState {
 
         Ok(Some(state))
     }
+
+    /// Read the state from the `state.json` or `state.json.gz` file.
+    fn read_state_file(settings: &Settings) -> Result<Option<State>> {
+        let pueue_directory = settings.shared.pueue_directory();
+        let mut path = pueue_directory.join("state.json");
+
+        if settings.daemon.compress_state_file {
+            path = pueue_directory.join("state.json.gz");
+        }
+
+        // Ignore if the file doesn't exist. It doesn't have to.
+        if !path.exists() {
+            info!("Couldn't find state from previous session at location: {path:?}");
+            return Ok(None);
+        }
+        info!("Restoring state");
+
+        read_json_state(&path, settings.daemon.compress_state_file).map(Some)
+    }
+
+    /// Read the state from the SQLite database.
+    /// If there's no database yet, the state of a previous `state.json` or `state.json.gz` file
+    /// is migrated into a new database.
+    fn read_database(settings: &Settings) -> Result<Option<State>> {
+        let database = sqlite::database_path(settings);
+        if let Some(state) = sqlite::load(&database)? {
+            info!("Restoring state from {database:?}");
+            return Ok(Some(state));
+        }
+
+        // Prefer the file that matches the current compression setting.
+        let pueue_directory = settings.shared.pueue_directory();
+        let mut candidates = [("state.json", false), ("state.json.gz", true)];
+        if settings.daemon.compress_state_file {
+            candidates.reverse();
+        }
+
+        for (file, compressed) in candidates {
+            let path = pueue_directory.join(file);
+            if path.exists() {
+                let state = read_json_state(&path, compressed)?;
+                sqlite::migrate(&state, &database, &path)?;
+                return Ok(Some(state));
+            }
+        }
+
+        info!("Couldn't find state from previous session at location: {database:?}");
+        Ok(None)
+    }
+}
+
+/// Write the whole state to `state.json` or `state.json.gz`.
+/// The file is written to a temporary file first, to prevent loss due to crashes.
+pub fn write_state_file(state: &State, settings: &Settings) -> Result<()> {
+    let serialized =
+        serde_json::to_string(&StateFile::from(state)).context("Failed to serialize state:")?;
+
+    let path = settings.shared.pueue_directory();
+    let mut temp = path.join("state.json.partial");
+    let mut real = path.join("state.json");
+
+    if settings.daemon.compress_state_file {
+        temp = path.join("state.json.gz.partial");
+        real = path.join("state.json.gz");
+
+        let file = if temp.exists() {
+            File::open(&temp)?
+        } else {
+            File::create(&temp)?
+        };
+
+        let mut encoder = flate2::write::GzEncoder::new(file, Compression::default());
+        encoder.write_all(serialized.as_bytes())?;
+    } else {
+        // Write to temporary log file first, to prevent loss due to crashes.
+        std::fs::write(&temp, serialized)
+            .context("Failed to write temp file while saving state.")?;
+    }
+
+    // Overwrite the original with the temp file, if everything went fine.
+    std::fs::rename(&temp, &real).context("Failed to overwrite old state while saving state")?;
+
+    debug!("State saved at: {real:?}");
+
+    Ok(())
+}
+
+/// Read and deserialize a json state file, which might be gzip compressed.
+///
+/// States of older versions are migrated to the current schema. In that case, the original file
+/// is backed up first, as it will be overwritten by the next save.
+fn read_json_state(path: &Path, compressed: bool) -> Result<State> {
+    let data = if compressed {
+        let file = File::open(path)?;
+        let mut decoder = flate2::read::GzDecoder::new(file);
+        let mut data = String::new();
+        decoder.read_to_string(&mut data)?;
+
+        data
+    } else {
+        read_to_string(path).context("State restore: Failed to read file:\n\n{}")?
+    };
+
+    let mut value: serde_json::Value =
+        serde_json::from_str(&data).context("Failed to deserialize state.")?;
+    let version = migrations::migrate(&mut value)
+        .context(format!("Failed to migrate state file {path:?}"))?;
+
+    if version != STATE_VERSION {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".v{version}.backup"));
+        std::fs::copy(path, &backup).context(format!("Failed to back up state to {backup:?}"))?;
+        info!("Backed up state of version {version} to {backup:?}");
+    }
+
+    let state_file: StateFile =
+        serde_json::from_value(value).context("Failed to deserialize state.")?;
+
+    state_file.try_into()
 }
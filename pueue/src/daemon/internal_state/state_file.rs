@@ -0,0 +1,206 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use pueue_lib::{Group, State, Task, task::Envs};
+use serde::{Deserialize, Serialize};
+
+use crate::{daemon::internal_state::migrations::STATE_VERSION, internal_prelude::*};
+
+/// The representation of the state in `state.json`.
+///
+/// The base environment of tasks is stored only once in `environments` and is referenced by its
+/// hash from `task_environments`. States of older versions contain the full environment of each
+/// task in the task itself, which is why tasks without such a reference keep their environment.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StateFile {
+    /// The schema version of this file. Older states are upgraded by the migrations in
+    /// [crate::daemon::internal_state::migrations].
+    #[serde(default)]
+    pub version: u64,
+    pub tasks: BTreeMap<usize, Task>,
+    pub groups: BTreeMap<String, Group>,
+    #[serde(default)]
+    pub environments: BTreeMap<String, HashMap<String, String>>,
+    #[serde(default)]
+    pub task_environments: BTreeMap<usize, TaskEnvironment>,
+}
+
+/// The reference to the base environment of a task and the task specific changes on top of it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TaskEnvironment {
+    pub base: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub changes: BTreeMap<String, Option<String>>,
+}
+
+/// Splits the environments of tasks into shared base environments and task specific changes.
+/// This is used by all state backends, so the base environment of tasks is only stored once.
+#[derive(Debug, Default)]
+pub struct EnvironmentInterner {
+    /// All base environments that have been referenced so far by their key.
+    pub environments: BTreeMap<String, HashMap<String, String>>,
+    /// Most tasks share the same base, so only hash each base once.
+    known_bases: HashMap<*const HashMap<String, String>, String>,
+}
+
+impl EnvironmentInterner {
+    /// The key under which a base environment is stored.
+    pub fn key(envs: &Envs) -> String {
+        format!("{:016x}", envs.base_hash())
+    }
+
+    /// Get the reference to the base environment of a task, which is added to the
+    /// `environments` if it isn't known yet.
+    ///
+    /// `None` is returned, if the environment has to be kept in the task itself.
+    pub fn reference(&mut self, envs: &Envs) -> Option<TaskEnvironment> {
+        let base = envs.base();
+        let pointer = Arc::as_ptr(base);
+
+        let key = match self.known_bases.get(&pointer) {
+            Some(key) => key.clone(),
+            None => {
+                let key = Self::key(envs);
+                match self.environments.get(&key) {
+                    // Two different environments with the same hash. This is very unlikely,
+                    // but in that case the environment is simply kept in the task itself.
+                    Some(existing) if existing != &**base => return None,
+                    Some(_) => (),
+                    None => {
+                        self.environments.insert(key.clone(), HashMap::clone(base));
+                    }
+                }
+                self.known_bases.insert(pointer, key.clone());
+                key
+            }
+        };
+
+        Some(TaskEnvironment {
+            base: key,
+            changes: envs.changes().clone(),
+        })
+    }
+}
+
+impl TaskEnvironment {
+    /// Restore the environment of a task from the shared base environments.
+    ///
+    /// Fails if the referenced base environment doesn't exist, as the task would otherwise
+    /// silently lose its environment.
+    pub fn restore(
+        self,
+        environments: &HashMap<String, Arc<HashMap<String, String>>>,
+    ) -> Result<Envs> {
+        let base = environments
+            .get(&self.base)
+            .cloned()
+            .ok_or_else(|| eyre!("Missing base environment {}", self.base))?;
+        Ok(Envs::new(base, self.changes))
+    }
+}
+
+impl From<&State> for StateFile {
+    fn from(state: &State) -> Self {
+        let mut tasks = BTreeMap::new();
+        let mut task_environments = BTreeMap::new();
+        let mut interner = EnvironmentInterner::default();
+
+        for (id, task) in state.tasks.iter() {
+            let mut task = task.clone();
+            if let Some(environment) = interner.reference(&task.envs) {
+                task_environments.insert(*id, environment);
+                task.envs = Envs::default();
+            }
+
+            tasks.insert(*id, task);
+        }
+
+        StateFile {
+            version: STATE_VERSION,
+            tasks,
+            groups: state.groups.clone(),
+            environments: interner.environments,
+            task_environments,
+        }
+    }
+}
+
+impl TryFrom<StateFile> for State {
+    type Error = color_eyre::Report;
+
+    fn try_from(file: StateFile) -> Result<Self> {
+        let environments: HashMap<String, Arc<HashMap<String, String>>> = file
+            .environments
+            .into_iter()
+            .map(|(key, environment)| (key, Arc::new(environment)))
+            .collect();
+
+        let mut tasks = file.tasks;
+        for (id, environment) in file.task_environments {
+            let Some(task) = tasks.get_mut(&id) else {
+                continue;
+            };
+            task.envs = environment
+                .restore(&environments)
+                .context(format!("Failed to restore environment of task {id}"))?;
+        }
+
+        Ok(State {
+            tasks,
+            groups: file.groups,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::daemon::network::message_handler::fixtures::*;
+
+    #[test]
+    /// Identical environments are only stored once and are shared again after deserialization.
+    /// Task specific changes are kept.
+    fn deduplicate_environments() -> Result<()> {
+        let mut state = State::new();
+        let envs = HashMap::from([("PATH".to_string(), "/bin".to_string())]);
+        for id in 0..3 {
+            let mut task = get_stub_task(&id.to_string(), StubStatus::Queued);
+            task.envs = state.intern_envs(envs.clone());
+            state.add_task(task);
+        }
+        let task = state.tasks.get_mut(&2).unwrap();
+        task.envs.insert("PUEUE_WORKER_ID".into(), "1".into());
+        task.envs.remove("PATH");
+
+        let serialized = serde_json::to_string(&StateFile::from(&state))?;
+        let file: StateFile = serde_json::from_str(&serialized)?;
+        assert_eq!(file.environments.len(), 1);
+
+        let restored = State::try_from(file)?;
+        assert_eq!(restored, state);
+        assert!(Arc::ptr_eq(
+            restored.tasks[&0].envs.base(),
+            restored.tasks[&2].envs.base()
+        ));
+        assert_eq!(restored.tasks[&2].envs.get("PATH"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tasks that reference an unknown base environment are rejected.
+    fn reject_missing_environment() -> Result<()> {
+        let mut state = State::new();
+        state.add_task(get_stub_task("0", StubStatus::Queued));
+
+        let mut file = StateFile::from(&state);
+        file.environments.clear();
+        assert!(State::try_from(file).is_err());
+
+        Ok(())
+    }
+}
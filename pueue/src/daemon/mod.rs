@@ -15,19 +15,24 @@ use crate::{
             certificate::create_certificates,
             socket::{accept_incoming, socket_cleanup},
         },
+        reload::SharedSettings,
     },
     internal_prelude::*,
 };
 
 mod callbacks;
+mod capture;
 pub mod cli;
 /// The daemon's state representation that contains process related data not exposed to clients.
 pub mod internal_state;
 pub mod network;
+mod orphans;
 mod pid;
 mod process_handler;
+pub mod reload;
 #[cfg(target_os = "windows")]
 pub mod service;
+pub mod supervisor;
 pub mod task_handler;
 
 /// The main entry point for the daemon logic.
@@ -67,31 +72,38 @@ pub async fn run(config_path: Option<PathBuf>, profile: Option<String>, test: bo
 
     // Restore the previous state and save any changes that might have happened during this
     // process. If no previous state exists, just create a new one.
-    // Create a new empty state if any errors occur, but print the error message.
-    let state = match InternalState::restore_state(&settings) {
+    // Refuse to start if the previous state couldn't be restored, as starting with a clean state
+    // would overwrite the previous state for good.
+    let mut state = match InternalState::restore_state(&settings) {
         Ok(Some(state)) => state,
         Ok(None) => InternalState::new(),
         Err(error) => {
-            warn!("Failed to restore previous state:\n {error:?}");
-            warn!("Using clean state instead.");
-            InternalState::new()
+            pid::cleanup_pid_file(&settings.shared.pid_path())?;
+            return Err(error.wrap_err(
+                "Failed to restore the previous state. Fix or move the state file in the pueue \
+                directory, to start with a clean state.",
+            ));
         }
     };
 
+    // Create all groups that are declared in the configuration file.
+    state.reconcile_groups(&settings);
+
     // Save the state once at the very beginning.
     state
         .save(&settings)
         .context("Failed to save state on startup.")?;
     let state = Arc::new(Mutex::new(state));
+    let shared_settings = SharedSettings::new(settings.clone(), config_path, profile);
 
     // Don't set ctrlc and panic handlers during testing.
     // This is necessary for multithreaded integration testing, since multiple listener per process
     // aren't allowed. On top of this, ctrlc also somehow breaks test error output.
     if !test {
-        setup_signal_panic_handling(&settings, state.clone())?;
+        setup_signal_panic_handling(&shared_settings, state.clone())?;
     }
 
-    // connect with NATS
+    // Connect with NATS. The connection keeps using the settings the daemon has been started with.
     let state_nats = state.clone();
     let settings_nats = settings.clone();
     tokio::task::spawn({
@@ -103,8 +115,8 @@ pub async fn run(config_path: Option<PathBuf>, profile: Option<String>, test: bo
 
     // Run both the task handler and the message handler in the same tokio task.
     // If any of them fails, return an error immediately.
-    let task_handler = task_handler::run(state.clone(), settings.clone());
-    let message_handler = accept_incoming(settings.clone(), state.clone());
+    let task_handler = task_handler::run(state.clone(), shared_settings.clone());
+    let message_handler = accept_incoming(shared_settings, state.clone());
     try_join!(task_handler, message_handler).map(|_| ())
 }
 
@@ -146,21 +158,29 @@ fn init_directories(pueue_dir: &Path) -> Result<()> {
 /// On SIGINT and SIGTERM, we exit gracefully by sending a DaemonShutdown message to the
 /// TaskHandler. This is to prevent dangling processes and other weird edge-cases.
 ///
+/// On SIGHUP, the configuration file is reloaded.
+///
 /// On panic, we want to cleanup existing unix sockets and the PID file.
-fn setup_signal_panic_handling(settings: &Settings, state: SharedState) -> Result<()> {
+fn setup_signal_panic_handling(settings: &SharedSettings, state: SharedState) -> Result<()> {
     let state_clone = state.clone();
     let settings_clone = settings.clone();
 
-    // This section handles Shutdown via SigTerm/SigInt process signals
+    // Shut down on SIGINT, as well as Ctrl-C and Ctrl-Break on Windows.
+    // ctrlc's `termination` feature isn't used, as it would also shut the daemon down on SIGHUP.
+    // SIGTERM is handled together with SIGHUP in `setup_unix_signal_handling` instead.
     // Notify the TaskHandler, so it can shutdown gracefully.
     // The actual program exit will be done via the TaskHandler.
     ctrlc::set_handler(move || {
+        let settings = settings_clone.get();
         let mut state = state_clone.lock().unwrap();
-        initiate_shutdown(&settings_clone, &mut state, ShutdownRequest::Graceful);
+        initiate_shutdown(&settings, &mut state, ShutdownRequest::Graceful);
     })?;
 
+    #[cfg(unix)]
+    setup_unix_signal_handling(settings, state)?;
+
     // Try to do some final cleanup, even if we panic.
-    let settings_clone = settings.clone();
+    let settings_clone = settings.get();
     let orig_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
         // invoke the default handler and exit the process
@@ -183,3 +203,33 @@ fn setup_signal_panic_handling(settings: &Settings, state: SharedState) -> Resul
 
     Ok(())
 }
+
+/// Shut down gracefully on SIGTERM and reload the configuration file on SIGHUP.
+#[cfg(unix)]
+fn setup_unix_signal_handling(settings: &SharedSettings, state: SharedState) -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let settings_clone = settings.clone();
+    let state_clone = state.clone();
+    tokio::spawn(async move {
+        while terminate.recv().await.is_some() {
+            let settings = settings_clone.get();
+            let mut state = state_clone.lock().unwrap();
+            initiate_shutdown(&settings, &mut state, ShutdownRequest::Graceful);
+        }
+    });
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    let settings = settings.clone();
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading settings");
+            if let Err(err) = settings.reload(&state) {
+                error!("Failed to reload settings: {err:#}");
+            }
+        }
+    });
+
+    Ok(())
+}
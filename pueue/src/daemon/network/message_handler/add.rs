@@ -1,11 +1,13 @@
+use std::collections::HashMap;
+
 use chrono::Local;
-use pueue_lib::{GroupStatus, Settings, Task, TaskStatus, failure_msg, message::*};
+use pueue_lib::{GroupStatus, Settings, Task, TaskResult, TaskStatus, failure_msg, message::*};
 
 use crate::{
     aliasing::insert_alias,
     daemon::{
-        internal_state::SharedState,
-        network::{message_handler::ok_or_failure_message, response_helper::ensure_group_exists},
+        internal_state::{SharedState, state::LockedState},
+        network::{message_handler::ok_or_failure_message, response_helper::*},
         process_handler,
     },
     ok_or_save_state_failure,
@@ -23,6 +25,23 @@ pub fn add_task(settings: &Settings, state: &SharedState, message: AddRequest) -
         return response;
     }
 
+    // Only keep the newest pending task for this concurrency key, if requested.
+    let superseded = match &message.concurrency_key {
+        Some(key) if message.supersede => superseded_tasks(&state, key),
+        _ => Vec::new(),
+    };
+
+    // Reject the task, if the group's queue is already full.
+    // Superseded tasks of the same group make room for the new task.
+    let replaces_pending_task = superseded
+        .iter()
+        .any(|task_id| state.tasks()[task_id].group == message.group);
+    if !replaces_pending_task {
+        if let Err(response) = ensure_queue_capacity(&state, &message.group, 1) {
+            return response;
+        }
+    }
+
     // Ensure that specified dependencies actually exist.
     let not_found: Vec<_> = message
         .dependencies
@@ -33,19 +52,36 @@ pub fn add_task(settings: &Settings, state: &SharedState, message: AddRequest) -
         return failure_msg!("Unable to setup dependencies : task(s) {not_found:?} not found",);
     }
 
+    // Tasks without an explicit priority get the default priority of their group.
+    let declared = settings.daemon.groups.get(&message.group);
+    let priority = message
+        .priority
+        .or_else(|| declared.and_then(|declared| declared.default_priority));
+
+    // Tasks without an explicit path are executed in the working directory of their group.
+    let path = match declared.and_then(|declared| declared.working_directory.clone()) {
+        Some(working_directory) if message.default_path => working_directory,
+        _ => message.path,
+    };
+
     // Create a new task and add it to the state.
     let mut task = Task::new(
         message.command,
-        message.path,
-        message.envs,
+        path,
+        HashMap::new(),
         message.group,
         TaskStatus::Queued {
             enqueued_at: Local::now(),
         },
         message.dependencies,
-        message.priority.unwrap_or(0),
+        priority.unwrap_or(0),
         message.label,
     );
+    // Share the environment with other tasks that have been added from the same shell.
+    task.envs = state.intern_envs(message.envs);
+    task.restartable = message.restartable;
+    task.concurrency_key = message.concurrency_key;
+    task.timestamps = message.timestamps;
 
     // Handle if the command is to be stashed and/or automatically enqueued later.
     if message.stashed || message.enqueue_at.is_some() {
@@ -74,6 +110,8 @@ pub fn add_task(settings: &Settings, state: &SharedState, message: AddRequest) -
         .status;
     let group_is_paused = matches!(group_status, GroupStatus::Paused);
 
+    supersede_tasks(&mut state, superseded);
+
     // Add the task and persist the state.
     let task_id = state.add_task(task);
     ok_or_save_state_failure!(state.save(settings));
@@ -90,3 +128,219 @@ pub fn add_task(settings: &Settings, state: &SharedState, message: AddRequest) -
     }
     .into()
 }
+
+/// Get all queued and stashed tasks with the given concurrency key.
+/// Those are superseded by a newer task with the same key.
+fn superseded_tasks(state: &LockedState, key: &str) -> Vec<usize> {
+    state
+        .tasks()
+        .values()
+        .filter(|task| task.concurrency_key.as_deref() == Some(key) && task.is_pending())
+        .map(|task| task.id)
+        .collect()
+}
+
+/// Cancel the given pending tasks.
+/// They're marked as [TaskResult::Superseded], which lets their dependants fail as well.
+fn supersede_tasks(state: &mut LockedState, task_ids: Vec<usize>) {
+    let now = Local::now();
+    for task_id in task_ids {
+        // Stashed tasks have never been enqueued.
+        let enqueued_at = match state.tasks()[&task_id].status {
+            TaskStatus::Queued { enqueued_at } => enqueued_at,
+            _ => now,
+        };
+        state.change_status(
+            task_id,
+            TaskStatus::Done {
+                enqueued_at,
+                start: now,
+                end: now,
+                result: TaskResult::Superseded,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+    use pueue_lib::settings::GroupSettings;
+
+    use super::{super::fixtures::*, *};
+
+    fn get_message(key: &str, supersede: bool) -> AddRequest {
+        AddRequest {
+            command: "deploy".into(),
+            group: PUEUE_DEFAULT_GROUP.into(),
+            concurrency_key: Some(key.into()),
+            supersede,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    /// Only the newest queued task of a concurrency key is kept, if requested.
+    fn supersede_older_tasks() {
+        let (state, settings, _tempdir) = get_state();
+        {
+            let mut state = state.lock().unwrap();
+            for (id, key) in ["service-a", "service-a", "service-b"].iter().enumerate() {
+                let mut task = get_stub_task(&id.to_string(), StubStatus::Queued);
+                task.concurrency_key = Some(key.to_string());
+                state.add_task(task);
+            }
+            // Running tasks are never cancelled.
+            let mut task = get_stub_task("3", StubStatus::Running);
+            task.concurrency_key = Some("service-a".into());
+            state.add_task(task);
+            // Stashed tasks would run later on, so they're cancelled as well.
+            let mut task = get_stub_task("4", StubStatus::Stashed { enqueue_at: None });
+            task.concurrency_key = Some("service-a".into());
+            state.add_task(task);
+        }
+
+        let response = add_task(&settings, &state, get_message("service-a", true));
+        assert!(matches!(response, Response::AddedTask(_)));
+
+        let state = state.lock().unwrap();
+        let result = |id: usize| match &state.tasks().get(&id).unwrap().status {
+            TaskStatus::Done { result, .. } => Some(result.clone()),
+            _ => None,
+        };
+        assert_eq!(result(0), Some(TaskResult::Superseded));
+        assert_eq!(result(1), Some(TaskResult::Superseded));
+        assert_eq!(result(2), None);
+        assert_eq!(result(3), None);
+        assert_eq!(result(4), Some(TaskResult::Superseded));
+        assert_eq!(result(5), None);
+    }
+
+    #[test]
+    /// Tasks that supersede a pending task of their group are accepted, even if the queue is full.
+    fn supersede_in_full_queue() {
+        let (state, settings, _tempdir) = get_state();
+        {
+            let mut state = state.lock().unwrap();
+            state.create_group("deploy").max_queued = Some(1);
+            let mut task = get_stub_task_in_group("0", "deploy", StubStatus::Queued);
+            task.concurrency_key = Some("service-a".into());
+            state.add_task(task);
+        }
+        let mut message = get_message("service-a", true);
+        message.group = "deploy".into();
+
+        let response = add_task(&settings, &state, message.clone());
+        assert!(matches!(response, Response::AddedTask(_)));
+
+        // Without superseding, the queue is still full.
+        message.supersede = false;
+        let response = add_task(&settings, &state, message);
+        assert!(matches!(response, Response::Failure(_)));
+    }
+
+    #[test]
+    /// Queued tasks with the same key are kept by default.
+    fn keep_queued_tasks() {
+        let (state, settings, _tempdir) = get_state();
+        {
+            let mut state = state.lock().unwrap();
+            let mut task = get_stub_task("0", StubStatus::Queued);
+            task.concurrency_key = Some("service-a".into());
+            state.add_task(task);
+        }
+
+        add_task(&settings, &state, get_message("service-a", false));
+
+        let state = state.lock().unwrap();
+        assert!(state.tasks().values().all(Task::is_queued));
+    }
+
+    #[test]
+    /// Tasks are rejected, once the group's queue is full.
+    fn reject_full_queue() {
+        let (state, settings, _tempdir) = get_state();
+        {
+            let mut state = state.lock().unwrap();
+            state.create_group("build").max_queued = Some(2);
+            state.add_task(get_stub_task_in_group("0", "build", StubStatus::Queued));
+            state.add_task(get_stub_task_in_group("1", "build", StubStatus::Running));
+        }
+        let message = AddRequest {
+            command: "make".into(),
+            group: "build".into(),
+            ..Default::default()
+        };
+
+        let response = add_task(&settings, &state, message.clone());
+        assert!(matches!(response, Response::AddedTask(_)));
+
+        let response = add_task(&settings, &state, message);
+        assert_eq!(
+            response,
+            Response::Failure("Group \"build\" already has the maximum of 2 queued tasks".into())
+        );
+    }
+
+    #[test]
+    /// Tasks without explicit priority get the default priority of their declared group.
+    fn group_default_priority() {
+        let (state, mut settings, _tempdir) = get_state();
+        settings.daemon.groups.insert(
+            PUEUE_DEFAULT_GROUP.into(),
+            GroupSettings {
+                default_priority: Some(3),
+                ..Default::default()
+            },
+        );
+        let mut message = AddRequest {
+            command: "ls".into(),
+            group: PUEUE_DEFAULT_GROUP.into(),
+            ..Default::default()
+        };
+
+        add_task(&settings, &state, message.clone());
+        message.priority = Some(-1);
+        add_task(&settings, &state, message);
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.tasks().get(&0).unwrap().priority, 3);
+        assert_eq!(state.tasks().get(&1).unwrap().priority, -1);
+    }
+
+    #[test]
+    /// Tasks without an explicit path run in the working directory of their declared group.
+    fn group_working_directory() {
+        let (state, mut settings, _tempdir) = get_state();
+        settings.daemon.groups.insert(
+            PUEUE_DEFAULT_GROUP.into(),
+            GroupSettings {
+                working_directory: Some("/srv/builds".into()),
+                ..Default::default()
+            },
+        );
+        let mut message = AddRequest {
+            command: "ls".into(),
+            path: "/home/user".into(),
+            default_path: true,
+            group: PUEUE_DEFAULT_GROUP.into(),
+            ..Default::default()
+        };
+
+        add_task(&settings, &state, message.clone());
+        message.default_path = false;
+        add_task(&settings, &state, message);
+
+        let state = state.lock().unwrap();
+        assert_eq!(
+            state.tasks().get(&0).unwrap().path,
+            PathBuf::from("/srv/builds")
+        );
+        assert_eq!(
+            state.tasks().get(&1).unwrap().path,
+            PathBuf::from("/home/user")
+        );
+    }
+}
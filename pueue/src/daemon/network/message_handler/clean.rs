@@ -1,7 +1,10 @@
 use pueue_lib::{TaskResult, TaskStatus, log::clean_log_handles, message::*};
 
 use super::*;
-use crate::{daemon::internal_state::SharedState, ok_or_save_state_failure};
+use crate::{
+    daemon::internal_state::{SharedState, state::LockedState},
+    ok_or_save_state_failure,
+};
 
 fn construct_success_clean_message(message: CleanRequest) -> String {
     let successful_only_fix = if message.successful_only {
@@ -23,44 +26,67 @@ fn construct_success_clean_message(message: CleanRequest) -> String {
 pub fn clean(settings: &Settings, state: &SharedState, message: CleanRequest) -> Response {
     let mut state = state.lock().unwrap();
 
-    let filtered_tasks =
-        state.filter_tasks(|task| matches!(task.status, TaskStatus::Done { .. }), None);
+    let task_ids: Vec<usize> = state
+        .tasks()
+        .iter()
+        .filter(|(_, task)| {
+            // Check if we should ignore this task, if only successful tasks should be removed.
+            if message.successful_only
+                && !matches!(
+                    task.status,
+                    TaskStatus::Done {
+                        result: TaskResult::Success,
+                        ..
+                    }
+                )
+            {
+                return false;
+            }
+
+            // User's can specify a specific group to be cleaned.
+            // Skip the task if that's the case and the task's group doesn't match.
+            message.group.is_none() || message.group.as_deref() == Some(&task.group)
+        })
+        .map(|(task_id, _)| *task_id)
+        .collect();
+    remove_finished_tasks(settings, &mut state, &task_ids);
+
+    ok_or_save_state_failure!(state.save(settings));
+
+    create_success_response(construct_success_clean_message(message))
+}
+
+/// Remove the given tasks and their logs, as long as they're finished.
+/// Tasks that other tasks depend on are kept.
+///
+/// This is also used by the TaskHandler to apply the retention policies.
+/// Returns the ids of all tasks that have been removed.
+pub fn remove_finished_tasks(
+    settings: &Settings,
+    state: &mut LockedState,
+    task_ids: &[usize],
+) -> Vec<usize> {
+    let mut removed = Vec::new();
+    for task_id in task_ids {
+        if !state
+            .tasks()
+            .get(task_id)
+            .is_some_and(|task| task.is_done())
+        {
+            continue;
+        }
 
-    for task_id in &filtered_tasks.matching_ids {
         // Ensure the task is removable, i.e. there are no dependant tasks.
         if !state.is_task_removable(task_id, &[]) {
             continue;
         }
 
-        if message.successful_only || message.group.is_some() {
-            if let Some(task) = state.tasks().get(task_id) {
-                // Check if we should ignore this task, if only successful tasks should be removed.
-                if message.successful_only
-                    && !matches!(
-                        task.status,
-                        TaskStatus::Done {
-                            result: TaskResult::Success,
-                            ..
-                        }
-                    )
-                {
-                    continue;
-                }
-
-                // User's can specify a specific group to be cleaned.
-                // Skip the task if that's the case and the task's group doesn't match.
-                if message.group.is_some() && message.group.as_deref() != Some(&task.group) {
-                    continue;
-                }
-            }
-        }
-        let _ = state.tasks_mut().remove(task_id).unwrap();
+        let _ = state.remove_task(*task_id).unwrap();
         clean_log_handles(*task_id, &settings.shared.pueue_directory());
+        removed.push(*task_id);
     }
 
-    ok_or_save_state_failure!(state.save(settings));
-
-    create_success_response(construct_success_clean_message(message))
+    removed
 }
 
 #[cfg(test)]
@@ -11,48 +11,35 @@ use crate::{
 pub fn enqueue(settings: &Settings, state: &SharedState, message: EnqueueRequest) -> Response {
     let mut state = state.lock().unwrap();
     // Get the affected task ids, based on the task selection.
-    let selected_tasks = match message.tasks {
-        TaskSelection::TaskIds(ref task_ids) => state
-            .tasks_mut()
-            .iter_mut()
-            .filter(|(task_id, task)| {
-                if !task_ids.contains(task_id) {
-                    return false;
-                }
-
-                matches!(
-                    task.status,
-                    TaskStatus::Stashed { .. } | TaskStatus::Locked { .. }
-                )
-            })
-            .collect::<Vec<(&usize, &mut Task)>>(),
-        TaskSelection::Group(ref group) => state
-            .tasks_mut()
-            .iter_mut()
-            .filter(|(_, task)| {
-                if task.group != *group {
-                    return false;
-                }
+    let selected_ids: Vec<usize> = state
+        .tasks()
+        .values()
+        .filter(|task| match &message.tasks {
+            TaskSelection::TaskIds(task_ids) => task_ids.contains(&task.id),
+            TaskSelection::Group(group) => task.group == *group,
+            TaskSelection::All => true,
+        })
+        .filter(|task| {
+            matches!(
+                task.status,
+                TaskStatus::Stashed { .. } | TaskStatus::Locked { .. }
+            )
+        })
+        .map(|task| task.id)
+        .collect();
 
-                matches!(
-                    task.status,
-                    TaskStatus::Stashed { .. } | TaskStatus::Locked { .. }
-                )
-            })
-            .collect::<Vec<(&usize, &mut Task)>>(),
-        TaskSelection::All => state
-            .tasks_mut()
-            .iter_mut()
-            .filter(|(_, task)| {
-                matches!(
-                    task.status,
-                    TaskStatus::Stashed { .. } | TaskStatus::Locked { .. }
-                )
-            })
-            .collect::<Vec<(&usize, &mut Task)>>(),
-    };
+    // Stashed tasks already count towards the queue limit of their group, locked tasks don't.
+    let newly_pending = selected_ids
+        .iter()
+        .map(|task_id| &state.tasks()[task_id])
+        .filter(|task| !task.is_pending())
+        .map(|task| task.group.as_str());
+    if let Err(response) = ensure_queue_capacities(&state, newly_pending) {
+        return response;
+    }
 
-    for (_, task) in selected_tasks {
+    for task_id in selected_ids {
+        let task = state.task_mut(task_id).unwrap();
         // Either specify the point of time the task should be enqueued or enqueue the task
         // immediately.
         if message.enqueue_at.is_some() {
@@ -0,0 +1,223 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use pueue_lib::{Error, Settings, failure_msg, log::*, message::*, task::LogStream};
+use regex::Regex;
+
+use crate::daemon::internal_state::SharedState;
+
+/// The maximum amount of context lines before and after each match.
+/// The lines before a match are buffered, so the client mustn't be able to request arbitrary
+/// amounts of them.
+const MAX_CONTEXT: usize = 10_000;
+
+/// Invoked when calling `pueue grep`.
+/// Search the logs of the selected tasks and return all matching lines.
+///
+/// Rotated and compressed logs are searched as well, as they're read via [LogReader].
+pub fn grep(settings: &Settings, state: &SharedState, message: GrepRequest) -> Response {
+    if message.context > MAX_CONTEXT {
+        return failure_msg!("At most {MAX_CONTEXT} lines of context are supported.");
+    }
+    let regex = match Regex::new(&message.pattern) {
+        Ok(regex) => regex,
+        Err(err) => return failure_msg!("Invalid pattern: {err}"),
+    };
+
+    // Only hold the lock while selecting the tasks, as searching the logs might take a while.
+    let tasks: Vec<(usize, Option<String>)> = {
+        let state = state.lock().unwrap();
+        let task_ids = match &message.tasks {
+            TaskSelection::All => state.tasks().keys().cloned().collect(),
+            TaskSelection::TaskIds(task_ids) => task_ids.clone(),
+            TaskSelection::Group(group) => state.task_ids_in_group(group),
+        };
+
+        task_ids
+            .iter()
+            .filter_map(|task_id| state.tasks().get(task_id))
+            .filter(|task| message.status.is_none_or(|status| status.matches(task)))
+            .map(|task| (task.id, task.label.clone()))
+            .collect()
+    };
+
+    let pueue_directory = settings.shared.pueue_directory();
+    let mut responses = Vec::new();
+    for (task_id, label) in tasks {
+        match grep_task_log(task_id, &pueue_directory, &regex, message.context) {
+            Ok(lines) if lines.is_empty() => continue,
+            Ok(lines) => responses.push(GrepTaskResponse {
+                task_id,
+                label,
+                lines,
+            }),
+            Err(err) => return failure_msg!("Failed searching the log of task {task_id}: {err}"),
+        }
+    }
+
+    Response::Grep(responses)
+}
+
+/// Search all output streams of a single task.
+/// Tasks that didn't produce any output yet simply don't have any matching lines.
+fn grep_task_log(
+    task_id: usize,
+    pueue_directory: &Path,
+    regex: &Regex,
+    context: usize,
+) -> Result<Vec<GrepLine>, Error> {
+    let streams = if has_separate_streams(task_id, pueue_directory) {
+        vec![Some(LogStream::Stdout), Some(LogStream::Stderr)]
+    } else {
+        vec![None]
+    };
+
+    let mut lines = Vec::new();
+    let paths = get_log_paths(task_id, pueue_directory, None)?;
+    for (path, stream) in paths.iter().zip(streams) {
+        if !log_exists(path) {
+            continue;
+        }
+        lines.extend(grep_log(path, regex, context, stream)?);
+    }
+
+    Ok(lines)
+}
+
+/// Return all lines of a log that match the regex, together with `context` lines before and
+/// after each match.
+///
+/// Line numbers are relative to the start of the log that's still around, lines that have
+/// already been dropped due to the log size limit aren't counted.
+fn grep_log(
+    path: &Path,
+    regex: &Regex,
+    context: usize,
+    stream: Option<LogStream>,
+) -> Result<Vec<GrepLine>, Error> {
+    let reader = BufReader::new(LogReader::open(path)?);
+
+    let mut lines = Vec::new();
+    // The last few lines, that might be needed as context for the next match.
+    let mut before: VecDeque<GrepLine> = VecDeque::new();
+    // The amount of lines that still need to be added as context of the last match.
+    let mut after = 0;
+    for (index, line) in reader.split(b'\n').enumerate() {
+        let line =
+            line.map_err(|err| Error::IoPathError(path.to_path_buf(), "reading log", err))?;
+        let text = String::from_utf8_lossy(&line)
+            .trim_end_matches('\r')
+            .to_string();
+        let is_match = regex.is_match(&text);
+        let line = GrepLine {
+            line_number: index + 1,
+            text,
+            is_match,
+            stream,
+        };
+
+        if is_match {
+            lines.extend(before.drain(..));
+            lines.push(line);
+            after = context;
+        } else if after > 0 {
+            lines.push(line);
+            after -= 1;
+        } else if context > 0 {
+            if before.len() == context {
+                before.pop_front();
+            }
+            before.push_back(line);
+        }
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+
+    use pretty_assertions::assert_eq;
+
+    use super::{super::fixtures::*, *};
+
+    fn get_message(pattern: &str, status: Option<TaskStatusFilter>, context: usize) -> GrepRequest {
+        GrepRequest {
+            pattern: pattern.to_string(),
+            tasks: TaskSelection::All,
+            status,
+            context,
+        }
+    }
+
+    fn write_log(settings: &Settings, task_id: usize, content: &str) {
+        let path = get_log_path(task_id, &settings.shared.pueue_directory());
+        create_dir_all(path.parent().unwrap()).expect("Failed to create log directory");
+        write(path, content).expect("Failed to write log");
+    }
+
+    #[test]
+    fn grep_with_context() {
+        let (state, settings, _tempdir) = get_stub_state();
+        write_log(
+            &settings,
+            1,
+            "one\ntwo\nerror\nthree\nfour\nfive\nsix\nerror\n",
+        );
+
+        let response = grep(&settings, &state, get_message("err", None, 1));
+        let Response::Grep(tasks) = response else {
+            panic!("Expected grep response, got {response:?}");
+        };
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].task_id, 1);
+
+        let lines: Vec<(usize, bool)> = tasks[0]
+            .lines
+            .iter()
+            .map(|line| (line.line_number, line.is_match))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![(2, false), (3, true), (4, false), (7, false), (8, true)]
+        );
+    }
+
+    #[test]
+    fn grep_rejects_huge_context() {
+        let (state, settings, _tempdir) = get_stub_state();
+
+        let response = grep(&settings, &state, get_message("err", None, usize::MAX));
+        assert!(matches!(response, Response::Failure(_)));
+    }
+
+    #[test]
+    fn grep_status_filter() {
+        let (state, settings, _tempdir) = get_stub_state();
+        write_log(&settings, 1, "error\n");
+        write_log(&settings, 3, "error\n");
+
+        let response = grep(
+            &settings,
+            &state,
+            get_message("error", Some(TaskStatusFilter::Running), 0),
+        );
+        let Response::Grep(tasks) = response else {
+            panic!("Expected grep response, got {response:?}");
+        };
+        let task_ids: Vec<usize> = tasks.iter().map(|task| task.task_id).collect();
+        assert_eq!(task_ids, vec![3]);
+    }
+
+    #[test]
+    fn grep_invalid_pattern() {
+        let (state, settings, _tempdir) = get_stub_state();
+
+        let response = grep(&settings, &state, get_message("(", None, 0));
+        assert!(matches!(response, Response::Failure(_)));
+    }
+}
@@ -18,14 +18,24 @@ use crate::{
 /// - Show groups
 /// - Add group
 /// - Remove group
+/// - Rename group
+/// - Configure preemption of a group
+/// - Limit the amount of queued tasks of a group
 pub fn group(settings: &Settings, state: &SharedState, message: GroupRequest) -> Response {
     let mut state = state.lock().unwrap();
 
     match message {
         GroupRequest::List => {
             // Return information about all groups to the client.
+            let queued_tasks = state
+                .groups()
+                .keys()
+                .map(|name| (name.clone(), state.queued_tasks_in_group(name)))
+                .collect();
+
             GroupResponse {
                 groups: state.groups().clone(),
+                queued_tasks,
             }
             .into()
         }
@@ -93,5 +103,68 @@ pub fn group(settings: &Settings, state: &SharedState, message: GroupRequest) ->
 
             success_msg!("Group \"{group}\" has been removed")
         }
+        GroupRequest::Rename { name, new_name } => {
+            if let Err(message) = ensure_group_exists(&mut state, &name) {
+                return message;
+            }
+
+            if name == PUEUE_DEFAULT_GROUP {
+                return failure_msg!("You cannot rename the default group");
+            }
+
+            if state.groups().contains_key(&new_name) {
+                return failure_msg!("Group \"{new_name}\" already exists");
+            }
+
+            // Move the group itself and its worker pool, which includes all running processes.
+            let group = state.groups_mut().remove(&name).unwrap();
+            state.groups_mut().insert(new_name.clone(), group);
+            let pool = state.children.0.remove(&name).unwrap_or_default();
+            state.children.0.insert(new_name.clone(), pool);
+
+            // Move all tasks. Their ids don't change, which keeps all dependencies intact.
+            for task in state.tasks_mut().values_mut() {
+                if task.group == name {
+                    task.group = new_name.clone();
+                }
+            }
+
+            // Persist the state.
+            ok_or_save_state_failure!(state.save(settings));
+
+            success_msg!("Group \"{name}\" has been renamed to \"{new_name}\"")
+        }
+        GroupRequest::Preemption { name, margin } => {
+            match ensure_group_exists(&mut state, &name) {
+                Ok(group) => group.preemption_margin = margin,
+                Err(message) => return message,
+            }
+
+            // Persist the state.
+            ok_or_save_state_failure!(state.save(settings));
+
+            match margin {
+                Some(margin) => success_msg!(
+                    "Group \"{name}\" now preempts tasks with a priority margin of {margin}"
+                ),
+                None => success_msg!("Preemption has been disabled for group \"{name}\""),
+            }
+        }
+        GroupRequest::MaxQueued { name, max_queued } => {
+            match ensure_group_exists(&mut state, &name) {
+                Ok(group) => group.max_queued = max_queued,
+                Err(message) => return message,
+            }
+
+            // Persist the state.
+            ok_or_save_state_failure!(state.save(settings));
+
+            match max_queued {
+                Some(max_queued) => {
+                    success_msg!("Group \"{name}\" now accepts up to {max_queued} queued tasks")
+                }
+                None => success_msg!("The queue of group \"{name}\" is no longer limited"),
+            }
+        }
     }
 }
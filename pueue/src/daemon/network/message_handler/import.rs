@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+
+use chrono::Local;
+use pueue_lib::{Settings, TaskStatus, failure_msg, log::get_log_path, message::*};
+
+use super::ok_or_failure_message;
+use crate::{
+    daemon::{internal_state::SharedState, network::response_helper::*},
+    internal_prelude::*,
+    ok_or_save_state_failure,
+};
+
+/// Invoked when calling `pueue import`.
+/// Add all tasks of an export to the state and create all missing groups.
+///
+/// Imported tasks get new ids and their dependencies are remapped accordingly.
+/// Dependencies on tasks that aren't part of the export are dropped.
+///
+/// The state stays locked during the whole import, which makes it a single atomic operation.
+pub fn import(settings: &Settings, state: &SharedState, message: ImportRequest) -> Response {
+    let mut state = state.lock().unwrap();
+
+    // Make sure that every task ends up in a group, before anything is changed.
+    let missing: Vec<&String> = message
+        .tasks
+        .values()
+        .map(|task| &task.group)
+        .filter(|group| {
+            !state.groups().contains_key(*group) && !message.groups.contains_key(*group)
+        })
+        .collect();
+    if let Some(group) = missing.first() {
+        return failure_msg!("Group \"{group}\" of an imported task doesn't exist");
+    }
+
+    // Imported tasks that aren't done yet count towards the queue limit of their group.
+    // Groups that don't exist yet are created with the limit of the export.
+    let mut additional: BTreeMap<&str, usize> = BTreeMap::new();
+    for task in message.tasks.values().filter(|task| !task.is_done()) {
+        *additional.entry(task.group.as_str()).or_default() += 1;
+    }
+    for (group, additional) in additional {
+        let result = match message.groups.get(group) {
+            Some(exported) if !state.groups().contains_key(group) => {
+                check_queue_capacity(group, 0, exported.max_queued, additional)
+            }
+            _ => ensure_queue_capacity(&state, group, additional),
+        };
+        if let Err(response) = result {
+            return response;
+        }
+    }
+
+    // Create all missing groups. Existing groups keep their settings.
+    for (name, group) in message.groups.iter() {
+        if state.groups().contains_key(name) {
+            continue;
+        }
+
+        let created = state.create_group(name);
+        created.parallel_tasks = group.parallel_tasks;
+        created.preemption_margin = group.preemption_margin;
+        created.max_queued = group.max_queued;
+        // Create the worker pool.
+        state.children.0.insert(name.clone(), BTreeMap::new());
+    }
+
+    // Tasks are added in the order of their old ids, so their order in the queue is kept.
+    let mut id_map = BTreeMap::new();
+    for (old_id, mut task) in message.tasks {
+        task.status = imported_status(&task.status);
+        task.envs = state.intern_envs(task.envs.to_map());
+        // Preemptions and processes reference the ids of the other daemon and its system.
+        task.preemptions.clear();
+        task.process = None;
+
+        let new_id = state.add_task(task);
+        id_map.insert(old_id, new_id);
+    }
+
+    let mut dropped_dependencies = 0;
+    for new_id in id_map.values() {
+        let task = state.task_mut(*new_id).unwrap();
+        let dependencies = task.dependencies.len();
+        task.dependencies = task
+            .dependencies
+            .iter()
+            .filter_map(|id| id_map.get(id).copied())
+            .collect();
+        dropped_dependencies += dependencies - task.dependencies.len();
+    }
+
+    let pueue_directory = settings.shared.pueue_directory();
+    for (old_id, output) in message.logs {
+        let Some(new_id) = id_map.get(&old_id) else {
+            continue;
+        };
+        if let Err(err) = std::fs::write(get_log_path(*new_id, &pueue_directory), output) {
+            error!("Failed to write log of imported task {new_id}: {err:?}");
+        }
+    }
+
+    ok_or_save_state_failure!(state.save(settings));
+
+    let mapping = id_map
+        .iter()
+        .map(|(old_id, new_id)| format!("{old_id} -> {new_id}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut text = format!("Imported {} tasks ({mapping})", id_map.len());
+    if dropped_dependencies > 0 {
+        text.push_str(&format!(
+            "\nDropped {dropped_dependencies} dependencies on tasks that haven't been exported"
+        ));
+    }
+
+    create_success_response(text)
+}
+
+/// Tasks that were running on the other daemon cannot be resumed, so they're stashed instead.
+/// Queued tasks are queued again and finished tasks keep their result.
+fn imported_status(status: &TaskStatus) -> TaskStatus {
+    match status {
+        TaskStatus::Queued { .. } => TaskStatus::Queued {
+            enqueued_at: Local::now(),
+        },
+        TaskStatus::Stashed { enqueue_at } => TaskStatus::Stashed {
+            enqueue_at: *enqueue_at,
+        },
+        TaskStatus::Done { .. } => status.clone(),
+        TaskStatus::Running { .. } | TaskStatus::Paused { .. } | TaskStatus::Locked { .. } => {
+            TaskStatus::Stashed { enqueue_at: None }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use pueue_lib::{Group, task::TaskProcess};
+
+    use super::{super::fixtures::*, *};
+
+    #[test]
+    /// Tasks get new ids, while their dependencies and groups are kept intact.
+    fn import_tasks() {
+        let (state, settings, _tempdir) = get_state();
+        {
+            let mut state = state.lock().unwrap();
+            state.add_task(get_stub_task("0", StubStatus::Done(TaskResult::Success)));
+        }
+
+        let mut message = ImportRequest::default();
+        let mut task = get_stub_task_in_group("5", "remote", StubStatus::Queued);
+        task.id = 5;
+        message.tasks.insert(5, task);
+        let mut task = get_stub_task_in_group("7", "remote", StubStatus::Running);
+        task.id = 7;
+        task.dependencies = vec![5, 3];
+        task.process = Some(TaskProcess {
+            pid: 1000,
+            process_group: 1000,
+            start_time: None,
+            boot_id: None,
+        });
+        message.tasks.insert(7, task);
+        message.groups.insert(
+            "remote".into(),
+            Group {
+                status: pueue_lib::GroupStatus::Running,
+                parallel_tasks: 3,
+                preemption_margin: None,
+                max_queued: None,
+            },
+        );
+
+        let response = import(&settings, &state, message);
+        assert!(matches!(response, Response::Success(_)));
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.groups().get("remote").unwrap().parallel_tasks, 3);
+        let task = state.tasks().get(&2).unwrap();
+        assert_eq!(task.group, "remote");
+        assert_eq!(task.dependencies, vec![1]);
+        assert!(task.is_stashed());
+        // The process of the other daemon must never be adopted or killed.
+        assert_eq!(task.process, None);
+    }
+
+    #[test]
+    /// Nothing is imported, if a task's group neither exists nor has been exported.
+    fn reject_missing_group() {
+        let (state, settings, _tempdir) = get_state();
+
+        let mut message = ImportRequest::default();
+        let task = get_stub_task_in_group("0", "remote", StubStatus::Queued);
+        message.tasks.insert(0, task);
+
+        let response = import(&settings, &state, message);
+        assert!(matches!(response, Response::Failure(_)));
+        assert!(state.lock().unwrap().tasks().is_empty());
+    }
+
+    #[test]
+    /// Nothing is imported, if the tasks would exceed the queue limit of their group.
+    fn reject_full_queue() {
+        let (state, settings, _tempdir) = get_state();
+        {
+            let mut state = state.lock().unwrap();
+            state.group_mut(PUEUE_DEFAULT_GROUP).unwrap().max_queued = Some(1);
+        }
+
+        let mut message = ImportRequest::default();
+        message
+            .tasks
+            .insert(0, get_stub_task("0", StubStatus::Queued));
+        message
+            .tasks
+            .insert(1, get_stub_task("1", StubStatus::Queued));
+
+        let response = import(&settings, &state, message);
+        assert!(matches!(response, Response::Failure(_)));
+        assert!(state.lock().unwrap().tasks().is_empty());
+    }
+}
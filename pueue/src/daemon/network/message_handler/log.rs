@@ -1,85 +1,206 @@
-use std::{collections::BTreeMap, io::Read, path::Path, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use pueue_lib::{
-    Settings, failure_msg,
+    Error, GroupStatus, Settings, failure_msg,
     log::*,
     message::*,
     network::protocol::{GenericStream, send_response},
+    task::{Envs, LogStream, Task},
 };
 
-use crate::{daemon::internal_state::SharedState, internal_prelude::*};
+use crate::{
+    daemon::{
+        internal_state::{SharedState, state::LockedState},
+        network::response_helper::ensure_group_exists,
+    },
+    internal_prelude::*,
+};
 
 /// Invoked when calling `pueue log`.
 /// Return tasks and their output to the client.
 pub fn get_log(settings: &Settings, state: &SharedState, message: LogRequest) -> Response {
-    let state = { state.lock().unwrap().clone() };
+    let pueue_directory = settings.shared.pueue_directory();
+    let mut tasks = BTreeMap::new();
+    for task in selected_tasks(state, &message.tasks) {
+        let task_id = task.id;
+        match get_task_log(&pueue_directory, task, &message) {
+            Ok(task_log) => {
+                tasks.insert(task_id, task_log);
+            }
+            Err(response) => return response,
+        }
+    }
+    Response::Log(tasks)
+}
 
-    let task_ids = match message.tasks {
+/// Invoked when calling `pueue log --ndjson`.
+/// Send the tasks and their output one by one, each in its own [Response::Log] message.
+/// That way, the logs of all tasks never have to be held in memory at the same time.
+///
+/// The stream is ended with a [Response::Close].
+pub async fn stream_logs(
+    settings: &Settings,
+    stream: &mut GenericStream,
+    state: &SharedState,
+    message: LogRequest,
+) -> Result<Response> {
+    let pueue_directory = settings.shared.pueue_directory();
+    for task in selected_tasks(state, &message.tasks) {
+        let task_id = task.id;
+        let task_log = match get_task_log(&pueue_directory, task, &message) {
+            Ok(task_log) => task_log,
+            Err(response) => return Ok(response),
+        };
+
+        let response = Response::Log(BTreeMap::from([(task_id, task_log)]));
+        send_response(response, stream).await?;
+    }
+
+    Ok(Response::Close)
+}
+
+/// Return copies of the selected tasks.
+/// Only those tasks are cloned, so the state is locked as briefly as possible and no logs are
+/// read while holding the lock.
+fn selected_tasks(state: &SharedState, selection: &TaskSelection) -> Vec<Task> {
+    let state = state.lock().unwrap();
+    let task_ids = match selection {
         TaskSelection::All => state.tasks().keys().cloned().collect(),
-        TaskSelection::TaskIds(task_ids) => task_ids,
-        TaskSelection::Group(group) => state.task_ids_in_group(&group),
+        TaskSelection::TaskIds(task_ids) => task_ids.clone(),
+        TaskSelection::Group(group) => state.task_ids_in_group(group),
     };
 
-    let mut tasks = BTreeMap::new();
-    for task_id in task_ids.iter() {
-        if let Some(task) = state.tasks().get(task_id) {
-            // We send log output and the task at the same time.
-            // This isn't as efficient as sending the raw compressed data directly,
-            // but it's a lot more convenient for now.
-            let (output, output_complete) = if message.send_logs {
-                match read_and_compress_log_file(
-                    *task_id,
-                    &settings.shared.pueue_directory(),
-                    message.lines,
-                ) {
-                    Ok((output, output_complete)) => (Some(output), output_complete),
-                    Err(err) => {
-                        // Fail early if there's some problem with getting the log output
-                        return failure_msg!("Failed reading process output file: {err:?}");
-                    }
-                }
-            } else {
-                (None, true)
-            };
+    task_ids
+        .iter()
+        .filter_map(|task_id| state.tasks().get(task_id))
+        .map(|task| {
+            // The environment isn't needed to display logs and is by far the largest part of
+            // a task, so it isn't sent to the client.
+            let mut task = task.clone();
+            task.envs = Envs::default();
+            task
+        })
+        .collect()
+}
 
-            let task_log = TaskLogResponse {
-                task: task.clone(),
-                output,
-                output_complete,
-            };
-            tasks.insert(*task_id, task_log);
+/// Read the requested log output of a single task.
+/// A failure response is returned, if the log output cannot be read.
+fn get_task_log(
+    pueue_directory: &Path,
+    task: Task,
+    message: &LogRequest,
+) -> Result<TaskLogResponse, Response> {
+    let task_id = task.id;
+    // We send log output and the task at the same time.
+    // This isn't as efficient as sending the raw compressed data directly,
+    // but it's a lot more convenient for now.
+    let (output, output_complete, bytes) = if message.send_logs {
+        match read_log(task_id, pueue_directory, message, message.stream) {
+            Ok((output, output_complete, bytes)) => (Some(output), output_complete, bytes),
+            Err(err) => {
+                // Fail early if there's some problem with getting the log output
+                return Err(failure_msg!("Failed reading process output file: {err:?}"));
+            }
+        }
+    } else {
+        (None, true, None)
+    };
+
+    // Send the separately captured stderr as well, unless a specific stream or a byte
+    // range was requested.
+    let mut stderr = None;
+    let mut stderr_complete = true;
+    if message.send_logs
+        && message.stream.is_none()
+        && message.bytes.is_none()
+        && has_separate_streams(task_id, pueue_directory)
+    {
+        match read_log(task_id, pueue_directory, message, Some(LogStream::Stderr)) {
+            Ok((output, complete, _)) => {
+                stderr = Some(output);
+                stderr_complete = complete;
+            }
+            Err(err) => {
+                return Err(failure_msg!("Failed reading process stderr file: {err:?}"));
+            }
         }
     }
-    Response::Log(tasks)
+
+    Ok(TaskLogResponse {
+        task,
+        output,
+        output_complete: output_complete && stderr_complete,
+        stderr,
+        bytes,
+    })
+}
+
+/// The compressed output of a log, whether it's complete and the byte range it covers.
+type ReadLog = (Vec<u8>, bool, Option<Range<u64>>);
+
+/// Read and compress a stream of a task's log.
+/// Timestamps are only rendered or filtered on, if the request asks for it.
+///
+/// The byte range of the output is returned as well, if a byte range has been requested.
+fn read_log(
+    task_id: usize,
+    pueue_directory: &Path,
+    message: &LogRequest,
+    stream: Option<LogStream>,
+) -> Result<ReadLog, Error> {
+    if let Some(bytes) = &message.bytes {
+        let (output, bytes, output_complete) =
+            read_and_compress_log_range(task_id, pueue_directory, stream, bytes.clone())?;
+        return Ok((output, output_complete, Some(bytes)));
+    }
+
+    let (output, output_complete) = if message.timestamps || message.since.is_some() {
+        read_and_compress_timestamped_log_file(
+            task_id,
+            pueue_directory,
+            message.lines,
+            stream,
+            message.timestamps,
+            message.since,
+        )?
+    } else {
+        read_and_compress_log_file(task_id, pueue_directory, message.lines, stream)?
+    };
+
+    Ok((output, output_complete, None))
 }
 
+/// The log files of a followed task, together with the recorded timestamps of their lines.
+type TaskLogHandles = Vec<(PathBuf, LogReader, TimestampReader)>;
+
 /// Handle the continuous stream of a some log output.
 ///
 /// It's not actually a stream in the sense of a low-level network stream, but rather a series of
 /// [Response::Stream] messages, that each send a portion of new log output.
 ///
 /// It's basically our own chunked stream implementation on top of the protocol we established.
+///
+/// Several tasks can be followed at the same time. Selected tasks are followed as soon as they're
+/// started. For groups, all running tasks are followed, including those that are started later
+/// on. The stream is closed once all followed tasks finished and none of the group's tasks can
+/// be started anymore without user interaction.
 pub async fn follow_log(
     pueue_directory: &Path,
     stream: &mut GenericStream,
     state: &SharedState,
     message: StreamRequest,
 ) -> Result<Response> {
-    // The user can specify the id of the task they want to follow
-    // If the id isn't specified and there's only a single running task, this task will be used.
+    // The user can specify the ids of the tasks they want to follow
+    // If no id is specified and there's only a single running task, this task will be used.
     // However, if there are multiple running tasks, the user will have to specify an id.
-    //
-    // NOTE: Read the docs of [StreamRequest] why we're not yet supporting selection of multiple
-    // tasks yet.
-    let task_id = if let TaskSelection::TaskIds(task_ids) = message.tasks {
-        task_ids.first().cloned()
-    } else {
-        None
-    };
-
-    let task_id = match task_id {
-        Some(id) => id,
-        None => {
+    let selection = match &message.tasks {
+        TaskSelection::TaskIds(task_ids) if task_ids.is_empty() => {
             // Get all ids of running tasks
             let state = state.lock().unwrap();
             let running_ids: Vec<_> = state
@@ -93,7 +214,7 @@ pub async fn follow_log(
                 0 => {
                     return Ok(create_failure_response("There are no running tasks."));
                 }
-                1 => running_ids[0],
+                1 => TaskSelection::TaskIds(running_ids),
                 _ => {
                     let running_ids = running_ids
                         .iter()
@@ -106,94 +227,229 @@ pub async fn follow_log(
                 }
             }
         }
+        TaskSelection::Group(group) => {
+            let mut state = state.lock().unwrap();
+            if let Err(response) = ensure_group_exists(&mut state, group) {
+                return Ok(response);
+            }
+            message.tasks.clone()
+        }
+        selection => selection.clone(),
     };
+    // Explicitly selected tasks are followed, even if they already finished.
+    // Tasks of groups are only followed while they're running.
+    let explicit = matches!(selection, TaskSelection::TaskIds(_));
+    let single_task = matches!(&selection, TaskSelection::TaskIds(task_ids) if task_ids.len() == 1);
 
-    // It might be that the task is not yet running.
-    // Ensure that it exists and is started.
+    let mut followed: BTreeMap<usize, TaskLogHandles> = BTreeMap::new();
+    // Tasks that have been followed until they finished.
+    let mut finished: BTreeSet<usize> = BTreeSet::new();
     loop {
+        // Check which tasks should be followed by now and which of them are no longer running.
+        // Tasks that aren't running anymore are read one last time, before they're dropped.
+        let mut to_follow = Vec::new();
+        let mut to_finish = Vec::new();
+        let mut labels = BTreeMap::new();
+        // Whether there are selected tasks that might still be started.
+        let mut waiting = false;
         {
             let state = state.lock().unwrap();
-            let Some(task) = state.tasks().get(&task_id) else {
-                return Ok(create_failure_response(
-                    "Pueue: The task to be followed doesn't exist.",
-                ));
+            let task_ids = match &selection {
+                TaskSelection::TaskIds(task_ids) => task_ids.clone(),
+                TaskSelection::Group(group) => state.task_ids_in_group(group),
+                TaskSelection::All => state.tasks().keys().cloned().collect(),
             };
-            // The task is running or finished, we can start to follow.
-            if task.is_running() || task.is_done() {
-                break;
+
+            for task_id in task_ids {
+                if finished.contains(&task_id) || followed.contains_key(&task_id) {
+                    continue;
+                }
+                let Some(task) = state.tasks().get(&task_id) else {
+                    return Ok(create_failure_response(
+                        "Pueue: The task to be followed doesn't exist.",
+                    ));
+                };
+
+                if task.is_running() || (explicit && task.is_done()) {
+                    to_follow.push(task_id);
+                } else if explicit || can_start(&state, task) {
+                    waiting = true;
+                }
+            }
+
+            for &task_id in followed.keys().chain(to_follow.iter()) {
+                let Some(task) = state.tasks().get(&task_id) else {
+                    if single_task {
+                        return Ok(create_failure_response(
+                            "Pueue: The followed task has been removed.",
+                        ));
+                    }
+                    to_finish.push(task_id);
+                    continue;
+                };
+                if !task.is_running() {
+                    to_finish.push(task_id);
+                }
+                if let Some(label) = &task.label {
+                    labels.insert(task_id, label.clone());
+                }
+            }
+        }
+
+        for task_id in to_follow {
+            match open_task_logs(pueue_directory, task_id, &message) {
+                Ok(handles) => {
+                    followed.insert(task_id, handles);
+                }
+                Err(response) => return Ok(response),
             }
         }
+
+        let mut logs = BTreeMap::new();
+        let mut offsets = BTreeMap::new();
+        for (task_id, handles) in followed.iter_mut() {
+            match read_task_logs(handles, message.timestamps) {
+                Ok(Some((text, task_offsets))) => {
+                    // Only send output of tasks with actual new content.
+                    if !text.is_empty() {
+                        logs.insert(*task_id, text);
+                        offsets.insert(*task_id, task_offsets);
+                    }
+                }
+                Ok(None) if single_task => {
+                    return Ok(create_success_response(
+                        "Pueue: Log file has gone away. Has the task been removed?",
+                    ));
+                }
+                // The log of this task went away, so it's been removed in the meantime.
+                Ok(None) => to_finish.push(*task_id),
+                Err(response) => return Ok(response),
+            }
+        }
+
+        // Only send a message, if there's actual new content.
+        if !logs.is_empty() {
+            labels.retain(|task_id, _| logs.contains_key(task_id));
+            // Send the next chunk.
+            let response = Response::Stream(StreamResponse {
+                logs,
+                offsets,
+                labels,
+            });
+            send_response(response, stream).await?;
+        }
+
+        for task_id in to_finish {
+            followed.remove(&task_id);
+            finished.insert(task_id);
+        }
+
+        // All followed tasks finished and there's nothing left to wait for, close the stream.
+        if followed.is_empty() && !waiting {
+            return Ok(Response::Close);
+        }
+
+        // Wait for 1 second before sending the next chunk.
         tokio::time::sleep(Duration::from_millis(1000)).await;
     }
+}
 
-    let mut handle = match get_log_file_handle(task_id, pueue_directory) {
-        Err(_) => {
-            return Ok(create_failure_response(
+/// Whether a task is going to be started without any user interaction.
+/// That's queued tasks and tasks that are enqueued later on, as long as their group isn't paused.
+fn can_start(state: &LockedState, task: &Task) -> bool {
+    task.is_queued()
+        && state
+            .groups()
+            .get(&task.group)
+            .is_some_and(|group| group.status == GroupStatus::Running)
+}
+
+/// Open the log files of a task that's about to be followed.
+///
+/// The logs are positioned at the offsets of a previous stream, if it's resumed.
+/// Otherwise only the last `lines` of the output are sent, if requested.
+fn open_task_logs(
+    pueue_directory: &Path,
+    task_id: usize,
+    message: &StreamRequest,
+) -> Result<TaskLogHandles, Response> {
+    // Get the output paths.
+    // If the task's streams are captured separately, both files are followed by default.
+    let paths = match get_log_paths(task_id, pueue_directory, message.stream) {
+        Ok(paths) => paths,
+        Err(err) => return Err(create_failure_response(format!("Pueue: {err}"))),
+    };
+
+    // The offsets at which a previous stream of this task has left off, if it's resumed.
+    let offsets = message.offsets.get(&task_id);
+
+    let mut handles = Vec::new();
+    for (index, path) in paths.into_iter().enumerate() {
+        let Ok(mut handle) = LogReader::open(&path) else {
+            return Err(create_failure_response(
                 "Couldn't find output files for task. Maybe it finished? Try `log`",
             ));
-        }
-        Ok(handle) => handle,
-    };
+        };
 
-    // Get the output path.
-    // We need to check continuously, whether the file still exists,
-    // since the file can go away (e.g. due to finishing a task).
-    let path = get_log_path(task_id, pueue_directory);
-
-    // If `lines` is passed as an option, we only want to show the last `X` lines.
-    // To achieve this, we seek the file handle to the start of the `Xth` line
-    // from the end of the file.
-    // The loop following this section will then only copy those last lines to stdout.
-    if let Some(lines) = message.lines {
-        if let Err(err) = seek_to_last_lines(&mut handle, lines) {
-            eprintln!("Error seeking to last lines from log: {err}");
+        // If `lines` is passed as an option, we only want to show the last `X` lines.
+        // To achieve this, we seek the file handle to the start of the `Xth` line
+        // from the end of the file.
+        // The loop following this section will then only copy those last lines to stdout.
+        // A resumed stream continues exactly where it left off instead.
+        if let Some(offset) = offsets.and_then(|offsets| offsets.get(index)) {
+            handle.seek_to_offset(*offset);
+        } else if let Some(lines) = message.lines {
+            if let Err(err) = seek_to_last_lines(&mut handle, lines) {
+                eprintln!("Error seeking to last lines from log: {err}");
+            }
         }
+
+        let timestamps = TimestampReader::new(&path);
+        handles.push((path, handle, timestamps));
     }
 
-    loop {
-        // Check whether the file still exists. Exit if it doesn't.
-        if !path.exists() {
-            return Ok(create_success_response(
-                "Pueue: Log file has gone away. Has the task been removed?",
-            ));
+    Ok(handles)
+}
+
+/// Read the new output of a followed task since the last call.
+///
+/// Returns the output together with the offsets, at which the next chunk starts.
+/// `None` is returned, if the log has gone away in the meantime.
+fn read_task_logs(
+    handles: &mut TaskLogHandles,
+    timestamps: bool,
+) -> Result<Option<(String, Vec<u64>)>, Response> {
+    let mut text = String::new();
+    let mut offsets = Vec::new();
+    for (path, handle, timestamp_reader) in handles.iter_mut() {
+        // We need to check continuously, whether the file still exists,
+        // since the file can go away (e.g. due to removing the task).
+        if !log_exists(path) {
+            return Ok(None);
+        }
+        // Continue in the rotated segment, if the log has been rotated in the meantime.
+        if let Err(err) = handle.refresh() {
+            return Err(create_failure_response(format!("Pueue Error: {err}")));
         }
+
         // Read the next chunk of text from the last position.
+        let offset = handle.offset();
         let mut buffer = Vec::new();
 
         if let Err(err) = handle.read_to_end(&mut buffer) {
-            return Ok(create_failure_response(format!("Pueue Error: {err}")));
+            return Err(create_failure_response(format!("Pueue Error: {err}")));
         };
-        let text = String::from_utf8_lossy(&buffer).to_string();
-
-        // Only send a message, if there's actual new content.
-        if !text.is_empty() {
-            let mut logs = BTreeMap::new();
-            logs.insert(task_id, text);
-            // Send the next chunk.
-            let response = Response::Stream(StreamResponse { logs });
-            send_response(response, stream).await?;
-        }
-
-        // Check if the task in question does:
-        // 1. Still exist
-        // 2. Is still running
-        //
-        // In case it's not, close the stream.
-        {
-            let state = state.lock().unwrap();
-            let Some(task) = state.tasks().get(&task_id) else {
-                return Ok(create_failure_response(
-                    "Pueue: The followed task has been removed.",
-                ));
-            };
 
-            // The task is done, just close the stream.
-            if !task.is_running() {
-                return Ok(Response::Close);
+        if timestamps {
+            if let Err(err) = timestamp_reader.update() {
+                return Err(create_failure_response(format!("Pueue Error: {err}")));
             }
+            text.push_str(&timestamp_reader.render(&buffer, offset, true, None));
+        } else {
+            text.push_str(&String::from_utf8_lossy(&buffer));
         }
-
-        // Wait for 1 second before sending the next chunk.
-        tokio::time::sleep(Duration::from_millis(1000)).await;
+        offsets.push(handle.offset());
     }
+
+    Ok(Some((text, offsets)))
 }
@@ -6,7 +6,9 @@ use pueue_lib::{
 use std::fmt::Display;
 
 use crate::{
-    daemon::{internal_state::SharedState, process_handler::initiate_shutdown},
+    daemon::{
+        internal_state::SharedState, process_handler::initiate_shutdown, reload::SharedSettings,
+    },
     internal_prelude::*,
 };
 
@@ -15,9 +17,12 @@ mod clean;
 mod edit;
 mod enqueue;
 mod env;
+mod grep;
 mod group;
+mod import;
 mod kill;
 mod log;
+mod move_tasks;
 mod parallel;
 mod pause;
 mod remove;
@@ -28,14 +33,17 @@ mod start;
 mod stash;
 mod switch;
 
+pub use clean::remove_finished_tasks;
 pub use log::follow_log;
 
 pub async fn handle_request(
     stream: &mut GenericStream,
     request: Request,
     state: &SharedState,
-    settings: &Settings,
+    shared_settings: &SharedSettings,
 ) -> Result<()> {
+    // Take a snapshot, so a reload doesn't change the settings while the request is handled.
+    let settings = &shared_settings.get();
     let response = match request {
         // The client requested the output of a task.
         // Since this involves streaming content, we have to do some special handling.
@@ -43,6 +51,15 @@ pub async fn handle_request(
             let pueue_directory = settings.shared.pueue_directory();
             follow_log(&pueue_directory, stream, state, payload).await?
         }
+        // The logs of tasks are sent one by one, if they're requested as a stream.
+        Request::Log(message) if message.streamed => {
+            log::stream_logs(settings, stream, state, message).await?
+        }
+        // Searching the logs might take a while, which mustn't block the daemon's runtime.
+        Request::Grep(message) => {
+            let (settings, state) = (settings.clone(), state.clone());
+            tokio::task::spawn_blocking(move || grep::grep(&settings, &state, message)).await?
+        }
         // To initiated a shutdown, a flag in Pueue's state is set that informs the TaskHandler
         // to perform a graceful shutdown.
         //
@@ -58,6 +75,10 @@ pub async fn handle_request(
 
             return Ok(());
         }
+        Request::DaemonReload => match shared_settings.reload(state) {
+            Ok(summary) => create_success_response(summary),
+            Err(err) => failure_msg!("Failed to reload settings: {err:#}"),
+        },
         Request::Add(message) => add::add_task(settings, state, message),
         Request::Clean(message) => clean::clean(settings, state, message),
         Request::EditedTasks(editable_tasks) => edit::edit(settings, state, editable_tasks),
@@ -66,8 +87,10 @@ pub async fn handle_request(
         Request::Env(message) => env::env(settings, state, message),
         Request::Enqueue(message) => enqueue::enqueue(settings, state, message),
         Request::Group(message) => group::group(settings, state, message),
+        Request::Import(message) => import::import(settings, state, message),
         Request::Kill(message) => kill::kill(settings, state, message),
         Request::Log(message) => log::get_log(settings, state, message),
+        Request::Move(message) => move_tasks::move_tasks(settings, state, message),
         Request::Parallel(message) => parallel::set_parallel_tasks(message, state),
         Request::Pause(message) => pause::pause(settings, state, message),
         Request::Remove(task_ids) => remove::remove(settings, state, task_ids),
@@ -77,7 +100,7 @@ pub async fn handle_request(
         Request::Start(message) => start::start(settings, state, message),
         Request::Stash(message) => stash::stash(settings, state, message),
         Request::Switch(message) => switch::switch(settings, state, message),
-        Request::Status => get_status(state),
+        Request::Status => get_status(settings, state),
     };
 
     send_response(response, stream).await?;
@@ -85,12 +108,19 @@ pub async fn handle_request(
     Ok(())
 }
 
-pub fn handle_income_request(
+pub async fn handle_income_request(
     request: Request,
     state: &SharedState,
     settings: &Settings,
 ) -> Response {
     match request {
+        // Searching the logs might take a while, which mustn't block the daemon's runtime.
+        Request::Grep(message) => {
+            let (settings, state) = (settings.clone(), state.clone());
+            tokio::task::spawn_blocking(move || grep::grep(&settings, &state, message))
+                .await
+                .unwrap_or_else(|err| failure_msg!("Failed to search logs: {err}"))
+        }
         Request::Add(message) => add::add_task(settings, state, message),
         Request::Clean(message) => clean::clean(settings, state, message),
         Request::EditRequest(task_ids) => edit::edit_request(state, task_ids),
@@ -98,8 +128,10 @@ pub fn handle_income_request(
         Request::Env(message) => env::env(settings, state, message),
         Request::Enqueue(message) => enqueue::enqueue(settings, state, message),
         Request::Group(message) => group::group(settings, state, message),
+        Request::Import(message) => import::import(settings, state, message),
         Request::Kill(message) => kill::kill(settings, state, message),
         Request::Log(message) => log::get_log(settings, state, message),
+        Request::Move(message) => move_tasks::move_tasks(settings, state, message),
         Request::Parallel(message) => parallel::set_parallel_tasks(message, state),
         Request::Pause(message) => pause::pause(settings, state, message),
         Request::Remove(task_ids) => remove::remove(settings, state, task_ids),
@@ -109,7 +141,7 @@ pub fn handle_income_request(
         Request::Start(message) => start::start(settings, state, message),
         Request::Stash(message) => stash::stash(settings, state, message),
         Request::Switch(message) => switch::switch(settings, state, message),
-        Request::Status => get_status(state),
+        Request::Status => get_status(settings, state),
         Request::EditedTasks(edit) => edit::edit(settings, state, edit),
         _ => Response::Failure("Invalid request".to_string()),
     }
@@ -117,9 +149,15 @@ pub fn handle_income_request(
 
 /// Invoked when calling `pueue status`.
 /// Return the current state.
-fn get_status(state: &SharedState) -> Response {
-    let state = state.lock().unwrap().clone();
-    Response::Status(Box::new(state.inner))
+///
+/// The priority aging interval is included, so clients show the effective priorities the daemon
+/// actually schedules with.
+fn get_status(settings: &Settings, state: &SharedState) -> Response {
+    let state = state.lock().unwrap().clone().inner;
+    Response::Status(Box::new(StatusResponse {
+        state,
+        priority_aging_minutes: settings.daemon.priority_aging_minutes,
+    }))
 }
 
 fn ok_or_failure_message<T, E: Display>(result: Result<T, E>) -> Result<T, Response> {
@@ -140,7 +178,7 @@ macro_rules! ok_or_save_state_failure {
 }
 
 #[cfg(test)]
-mod fixtures {
+pub(crate) mod fixtures {
     use std::{
         collections::HashMap,
         env::temp_dir,
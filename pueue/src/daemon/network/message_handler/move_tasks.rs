@@ -0,0 +1,227 @@
+use pueue_lib::{Settings, Task, message::*};
+
+use super::ok_or_failure_message;
+use crate::{
+    daemon::{
+        internal_state::{SharedState, state::LockedState},
+        network::response_helper::*,
+    },
+    ok_or_save_state_failure,
+};
+
+/// Invoked when calling `pueue move`.
+/// Move queued or stashed tasks to another group or to the front/back of their group.
+///
+/// Task ids never change, which is why all dependencies stay intact.
+pub fn move_tasks(settings: &Settings, state: &SharedState, message: MoveRequest) -> Response {
+    let mut state = state.lock().unwrap();
+
+    // Only tasks that haven't been started yet can be moved.
+    let filtered_tasks = state.filter_tasks(Task::is_pending, Some(message.task_ids));
+
+    let text = match message.destination {
+        MoveDestination::Group(group) => {
+            if let Err(message) = ensure_group_exists(&mut state, &group) {
+                return message;
+            }
+
+            // Tasks from other groups count towards the queue limit of their new group.
+            let additional = filtered_tasks
+                .matching_ids
+                .iter()
+                .filter(|task_id| state.tasks()[*task_id].group != group)
+                .count();
+            if let Err(message) = ensure_queue_capacity(&state, &group, additional) {
+                return message;
+            }
+
+            for task_id in filtered_tasks.matching_ids.iter() {
+                let task = state.task_mut(*task_id).unwrap();
+                task.group = group.clone();
+                // The position is relative to the tasks of the previous group.
+                task.queue_position = 0;
+            }
+
+            format!("Tasks have been moved to group \"{group}\"")
+        }
+        MoveDestination::Front => {
+            for task_id in filtered_tasks.matching_ids.iter() {
+                move_within_group(&mut state, *task_id, &filtered_tasks.matching_ids, true);
+            }
+
+            "Tasks have been moved to the front of their group".to_string()
+        }
+        MoveDestination::Back => {
+            for task_id in filtered_tasks.matching_ids.iter() {
+                move_within_group(&mut state, *task_id, &filtered_tasks.matching_ids, false);
+            }
+
+            "Tasks have been moved to the back of their group".to_string()
+        }
+    };
+
+    ok_or_save_state_failure!(state.save(settings));
+
+    compile_task_response(&text, filtered_tasks)
+}
+
+/// Tasks are executed in the order of their queue position, followed by their priority and id.
+/// To move a task in front of or behind all other pending tasks of its group, its position is
+/// raised above or lowered below the positions of those tasks.
+/// Its priority stays untouched.
+///
+/// `moved` are the ids of all tasks that're being moved at the same time.
+/// Those are ordered by their priority and id afterwards.
+fn move_within_group(state: &mut LockedState, task_id: usize, moved: &[usize], front: bool) {
+    let group = state.tasks().get(&task_id).unwrap().group.clone();
+    let positions = state
+        .tasks()
+        .values()
+        .filter(|task| task.group == group && task.is_pending() && !moved.contains(&task.id))
+        .map(|task| task.queue_position);
+
+    let position = if front {
+        positions.max().map(|max| max.saturating_add(1))
+    } else {
+        positions.min().map(|min| min.saturating_sub(1))
+    };
+
+    // There are no other pending tasks in this group, so there's nothing to do.
+    let Some(position) = position else {
+        return;
+    };
+
+    let task = state.task_mut(task_id).unwrap();
+    if (front && task.queue_position < position) || (!front && task.queue_position > position) {
+        task.queue_position = position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    use super::{super::fixtures::*, *};
+    use crate::daemon::process_handler::scheduling::priority_order;
+
+    fn get_test_state() -> (SharedState, Settings, TempDir) {
+        let (state, settings, tempdir) = get_state();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.create_group("other");
+
+            let task = get_stub_task("0", StubStatus::Queued);
+            state.add_task(task);
+
+            let mut task = get_stub_task("1", StubStatus::Queued);
+            task.priority = 2;
+            state.add_task(task);
+
+            let mut task = get_stub_task("2", StubStatus::Stashed { enqueue_at: None });
+            task.dependencies = vec![0];
+            state.add_task(task);
+
+            let task = get_stub_task("3", StubStatus::Running);
+            state.add_task(task);
+        }
+
+        (state, settings, tempdir)
+    }
+
+    fn get_message(task_ids: Vec<usize>, destination: MoveDestination) -> MoveRequest {
+        MoveRequest {
+            task_ids,
+            destination,
+        }
+    }
+
+    #[test]
+    /// Pending tasks can be moved to another group, while running tasks stay where they are.
+    fn move_to_group() {
+        let (state, settings, _tempdir) = get_test_state();
+
+        let message = get_message(vec![0, 2, 3], MoveDestination::Group("other".into()));
+        let response = move_tasks(&settings, &state, message);
+        assert!(matches!(response, Response::Success(_)));
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.tasks().get(&0).unwrap().group, "other");
+        assert_eq!(state.tasks().get(&2).unwrap().group, "other");
+        assert_eq!(state.tasks().get(&3).unwrap().group, PUEUE_DEFAULT_GROUP);
+        // Dependencies stay intact.
+        assert_eq!(state.tasks().get(&2).unwrap().dependencies, vec![0]);
+    }
+
+    #[test]
+    /// Tasks can't be moved to a non-existing group.
+    fn move_to_missing_group() {
+        let (state, settings, _tempdir) = get_test_state();
+
+        let message = get_message(vec![0], MoveDestination::Group("missing".into()));
+        let response = move_tasks(&settings, &state, message);
+        assert!(matches!(response, Response::Failure(_)));
+    }
+
+    #[test]
+    /// Tasks can't be moved to a group, if that would exceed the group's queue limit.
+    fn move_to_full_group() {
+        let (state, settings, _tempdir) = get_test_state();
+        {
+            let mut state = state.lock().unwrap();
+            state.group_mut("other").unwrap().max_queued = Some(1);
+        }
+
+        let message = get_message(vec![0, 2], MoveDestination::Group("other".into()));
+        let response = move_tasks(&settings, &state, message);
+        assert!(matches!(response, Response::Failure(_)));
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.tasks().get(&0).unwrap().group, PUEUE_DEFAULT_GROUP);
+    }
+
+    #[test]
+    /// Tasks that are moved to the front overtake all other pending tasks of their group.
+    fn move_to_front() {
+        let (state, settings, _tempdir) = get_test_state();
+
+        let response = move_tasks(
+            &settings,
+            &state,
+            get_message(vec![2], MoveDestination::Front),
+        );
+        assert!(matches!(response, Response::Success(_)));
+
+        let state = state.lock().unwrap();
+        let task = state.tasks().get(&2).unwrap();
+        assert_eq!(task.queue_position, 1);
+        // The priority of the task is kept.
+        assert_eq!(task.priority, 0);
+        // The task now overtakes the task with the higher priority.
+        let other = state.tasks().get(&1).unwrap();
+        assert_eq!(priority_order(None, task, other), Ordering::Less);
+    }
+
+    #[test]
+    /// Tasks that are moved to the back are processed after all other pending tasks.
+    fn move_to_back() {
+        let (state, settings, _tempdir) = get_test_state();
+
+        let response = move_tasks(
+            &settings,
+            &state,
+            get_message(vec![1], MoveDestination::Back),
+        );
+        assert!(matches!(response, Response::Success(_)));
+
+        let state = state.lock().unwrap();
+        let task = state.tasks().get(&1).unwrap();
+        assert_eq!(task.queue_position, -1);
+        assert_eq!(task.priority, 2);
+        let other = state.tasks().get(&0).unwrap();
+        assert_eq!(priority_order(None, task, other), Ordering::Greater);
+    }
+}
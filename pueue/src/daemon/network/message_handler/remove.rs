@@ -34,7 +34,7 @@ pub fn remove(settings: &Settings, state: &SharedState, task_ids: Vec<usize>) ->
     }
 
     for task_id in &filtered_tasks.matching_ids {
-        state.tasks_mut().remove(task_id);
+        state.remove_task(*task_id);
 
         clean_log_handles(*task_id, &settings.shared.pueue_directory());
     }
@@ -11,9 +11,7 @@ pub fn reset(settings: &Settings, state: &SharedState, message: ResetRequest) ->
     match message.target {
         ResetTarget::All => {
             // Mark all groups to be reset and kill all tasks
-            for (_name, group) in state.groups_mut().iter_mut() {
-                group.status = GroupStatus::Reset;
-            }
+            state.set_status_for_all_groups(GroupStatus::Reset);
             process_handler::kill::kill(settings, &mut state, TaskSelection::All, false, None);
         }
         ResetTarget::Groups(groups) => {
@@ -27,7 +25,7 @@ pub fn reset(settings: &Settings, state: &SharedState, message: ResetRequest) ->
 
             // Mark all groups to be reset and kill its tasks
             for name in groups.iter() {
-                let group = state.groups_mut().get_mut(name).unwrap();
+                let group = state.group_mut(name).unwrap();
                 group.status = GroupStatus::Reset;
 
                 process_handler::kill::kill(
@@ -5,7 +5,7 @@ use crate::{
     aliasing::insert_alias,
     daemon::{
         internal_state::{SharedState, state::LockedState},
-        network::response_helper::task_action_response_helper,
+        network::response_helper::{ensure_queue_capacities, task_action_response_helper},
         process_handler,
     },
 };
@@ -22,6 +22,16 @@ pub fn restart_multiple(
     let task_ids: Vec<usize> = message.tasks.iter().map(|task| task.task_id).collect();
     let mut state = state.lock().unwrap();
 
+    // Restarted tasks are queued or stashed again, which counts towards their group's limit.
+    let restarted = task_ids
+        .iter()
+        .filter_map(|task_id| state.tasks().get(task_id))
+        .filter(|task| task.is_done())
+        .map(|task| task.group.as_str());
+    if let Err(response) = ensure_queue_capacities(&state, restarted) {
+        return response;
+    }
+
     // We have to compile the response beforehand.
     // Otherwise we no longer know which tasks, were actually capable of being being restarted.
     let response = task_action_response_helper(
@@ -51,7 +61,7 @@ pub fn restart_multiple(
 /// new task, which is completely handled on the client-side.
 fn restart(state: &mut LockedState, to_restart: TaskToRestart, stashed: bool, settings: &Settings) {
     // Check if we actually know this task.
-    let Some(task) = state.tasks_mut().get_mut(&to_restart.task_id) else {
+    let Some(task) = state.task_mut(to_restart.task_id) else {
         return;
     };
 
@@ -75,4 +85,6 @@ fn restart(state: &mut LockedState, to_restart: TaskToRestart, stashed: bool, se
     task.path = to_restart.path;
     task.label = to_restart.label.clone();
     task.priority = to_restart.priority;
+    // Restarted tasks lose their position from a previous move to the front or back.
+    task.queue_position = 0;
 }
@@ -2,7 +2,7 @@ use std::io::Write;
 
 use pueue_lib::{failure_msg, message::*};
 
-use crate::daemon::internal_state::SharedState;
+use crate::daemon::internal_state::{SharedState, children::TaskChild};
 
 /// Invoked when calling `pueue send`.
 /// The message will be forwarded to the task handler, which then sends the user input to the
@@ -13,7 +13,12 @@ pub fn send(state: &SharedState, message: SendRequest) -> Response {
 
     // Check whether the task exists and is running. Abort if that's not the case.
     let child = match state.children.get_child_mut(task_id) {
-        Some(child) => child,
+        Some(TaskChild::Spawned(child)) => child,
+        Some(TaskChild::Adopted(_)) => {
+            return failure_msg!(
+                "Input cannot be sent to tasks that have been started by a previous daemon."
+            );
+        }
         None => {
             return failure_msg!("You can only send input to a running process.");
         }
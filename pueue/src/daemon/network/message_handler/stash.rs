@@ -1,4 +1,4 @@
-use pueue_lib::{Settings, Task, TaskStatus, message::*, success_msg};
+use pueue_lib::{Settings, TaskStatus, message::*, success_msg};
 
 use crate::{
     daemon::{internal_state::SharedState, network::response_helper::*},
@@ -11,51 +11,30 @@ use crate::{
 pub fn stash(settings: &Settings, state: &SharedState, message: StashRequest) -> Response {
     let mut state = state.lock().unwrap();
     // Get the affected task ids, based on the task selection.
-    let selected_tasks = match message.tasks {
-        TaskSelection::TaskIds(ref task_ids) => state
-            .tasks_mut()
-            .iter_mut()
-            .filter(|(task_id, task)| {
-                if !task_ids.contains(task_id) {
-                    return false;
-                }
+    let selected_ids: Vec<usize> = state
+        .tasks()
+        .iter()
+        .filter(|(task_id, task)| match &message.tasks {
+            TaskSelection::TaskIds(task_ids) => task_ids.contains(task_id),
+            TaskSelection::Group(group) => task.group == *group,
+            TaskSelection::All => true,
+        })
+        .filter(|(_, task)| {
+            matches!(
+                task.status,
+                TaskStatus::Queued { .. } | TaskStatus::Locked { .. }
+            )
+        })
+        .map(|(task_id, _)| *task_id)
+        .collect();
 
-                matches!(
-                    task.status,
-                    TaskStatus::Queued { .. } | TaskStatus::Locked { .. }
-                )
-            })
-            .collect::<Vec<(&usize, &mut Task)>>(),
-        TaskSelection::Group(ref group) => state
-            .tasks_mut()
-            .iter_mut()
-            .filter(|(_, task)| {
-                if task.group != *group {
-                    return false;
-                }
-
-                matches!(
-                    task.status,
-                    TaskStatus::Queued { .. } | TaskStatus::Locked { .. }
-                )
-            })
-            .collect::<Vec<(&usize, &mut Task)>>(),
-        TaskSelection::All => state
-            .tasks_mut()
-            .iter_mut()
-            .filter(|(_, task)| {
-                matches!(
-                    task.status,
-                    TaskStatus::Queued { .. } | TaskStatus::Locked { .. }
-                )
-            })
-            .collect::<Vec<(&usize, &mut Task)>>(),
-    };
-
-    for (_, task) in selected_tasks {
-        task.status = TaskStatus::Stashed {
-            enqueue_at: message.enqueue_at,
-        };
+    for task_id in selected_ids {
+        state.change_status(
+            task_id,
+            TaskStatus::Stashed {
+                enqueue_at: message.enqueue_at,
+            },
+        );
     }
 
     // Construct a response depending on the selected tasks.
@@ -27,8 +27,8 @@ pub fn switch(settings: &Settings, state: &SharedState, message: SwitchRequest)
     }
 
     // Get the tasks. Expect them to be there, since we found no mismatch
-    let mut first_task = state.tasks_mut().remove(&task_ids[0]).unwrap();
-    let mut second_task = state.tasks_mut().remove(&task_ids[1]).unwrap();
+    let mut first_task = state.remove_task(task_ids[0]).unwrap();
+    let mut second_task = state.remove_task(task_ids[1]).unwrap();
 
     // Switch task ids
     let first_id = first_task.id;
@@ -6,7 +6,7 @@ use futures::stream::StreamExt;
 use log::{error, info};
 use serde_derive::{Deserialize, Serialize};
 use pueue_lib::message::AddRequest;
-use pueue_lib::Request;
+use pueue_lib::{Request, Response};
 use pueue_lib::settings::Settings;
 use crate::daemon::internal_state::state::SharedState;
 use crate::daemon::network::message_handler::{handle_income_request};
@@ -37,7 +37,7 @@ pub async fn receive_messages(state: SharedState,
                     println!("pueue-001200: Pueue worker registered successfully!");
                 } else if message.starts_with("remove ") { // remove message
                     if let Ok(task_id) =  message[7..].trim().parse::<usize>() {
-                        handle_income_request(Request::Remove(vec![task_id]), &state, &settings);
+                        handle_income_request(Request::Remove(vec![task_id]), &state, &settings).await;
                     }
                 }
                 else if message.starts_with("{") { // json message
@@ -49,7 +49,8 @@ pub async fn receive_messages(state: SharedState,
                         };
                         let add_msg = AddRequest {
                             command: adjust_command_path(&origin_msg.command),
-                            path: PathBuf::from("/tmp"),
+                            path: PathBuf::from(DEFAULT_WORKING_DIRECTORY),
+                            default_path: true,
                             envs: origin_msg.envs,
                             start_immediately: origin_msg.start_immediately,
                             stashed: origin_msg.stashed,
@@ -58,15 +59,22 @@ pub async fn receive_messages(state: SharedState,
                             dependencies: origin_msg.dependencies,
                             priority: origin_msg.priority,
                             label: origin_msg.label,
+                            restartable: origin_msg.restartable,
+                            concurrency_key: origin_msg.concurrency_key,
+                            supersede: origin_msg.supersede,
+                            timestamps: origin_msg.timestamps,
                         };
-                        let _ = handle_income_request(Request::Add(add_msg), &state, &settings);
+                        if let Response::Failure(reason) = handle_income_request(Request::Add(add_msg), &state, &settings).await {
+                            error!("pueue-001202: Rejected task: {}", reason);
+                        }
                     } else {
                         error!("pueue-001201: Invalid message format: {}", message);
                     }
                 } else { // command line only
                     let add_msg = AddRequest {
                         command: adjust_command_path(&message),
-                        path: PathBuf::from("/tmp"),
+                        path: PathBuf::from(DEFAULT_WORKING_DIRECTORY),
+                        default_path: true,
                         envs: Default::default(),
                         start_immediately: false,
                         stashed: false,
@@ -75,8 +83,14 @@ pub async fn receive_messages(state: SharedState,
                         dependencies: vec![],
                         priority: None,
                         label: None,
+                        restartable: false,
+                        concurrency_key: None,
+                        supersede: false,
+                        timestamps: false,
                     };
-                    let _ = handle_income_request(Request::Add(add_msg), &state, &settings);
+                    if let Response::Failure(reason) = handle_income_request(Request::Add(add_msg), &state, &settings).await {
+                        error!("pueue-001202: Rejected task: {}", reason);
+                    }
                 }
             }
             Ok::<(), async_nats::Error>(())
@@ -158,6 +172,10 @@ impl PueuedWorker {
     }
 }
 
+/// The working directory of tasks received via NATS.
+/// The daemon uses the group's default working directory instead, if there's one.
+const DEFAULT_WORKING_DIRECTORY: &str = "/tmp";
+
 fn adjust_command_path(command_line: &str) -> String {
     if command_line.contains("/") {
         command_line.to_owned()
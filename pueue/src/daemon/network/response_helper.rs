@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use pueue_lib::{
     Group, Response, Task,
     message::{create_failure_response, create_success_response},
@@ -12,7 +14,7 @@ pub fn ensure_group_exists<'state>(
     group: &str,
 ) -> Result<&'state mut Group, Response> {
     let group_keys: Vec<String> = state.groups().keys().cloned().collect();
-    if let Some(group) = state.groups_mut().get_mut(group) {
+    if let Some(group) = state.group_mut(group) {
         return Ok(group);
     }
 
@@ -21,6 +23,70 @@ pub fn ensure_group_exists<'state>(
     )))
 }
 
+/// Check whether a group's queue has room for `additional` queued or stashed tasks.
+/// Return a failure message, if the group's `max_queued` limit would be exceeded.
+///
+/// This has to be checked on every path that adds pending tasks to a group, e.g. when adding,
+/// importing, restarting, enqueueing or moving tasks.
+pub fn ensure_queue_capacity(
+    state: &LockedState,
+    group: &str,
+    additional: usize,
+) -> Result<(), Response> {
+    let max_queued = state.groups().get(group).and_then(|group| group.max_queued);
+    check_queue_capacity(
+        group,
+        state.queued_tasks_in_group(group),
+        max_queued,
+        additional,
+    )
+}
+
+/// Check the queue limits for tasks that are about to become pending, which might belong to
+/// several groups. `groups` contains the group of each of those tasks.
+/// See [ensure_queue_capacity].
+pub fn ensure_queue_capacities<'a>(
+    state: &LockedState,
+    groups: impl IntoIterator<Item = &'a str>,
+) -> Result<(), Response> {
+    let mut additional: BTreeMap<&str, usize> = BTreeMap::new();
+    for group in groups {
+        *additional.entry(group).or_default() += 1;
+    }
+
+    for (group, additional) in additional {
+        ensure_queue_capacity(state, group, additional)?;
+    }
+
+    Ok(())
+}
+
+/// Check whether a queue with `queued` pending tasks has room for `additional` tasks.
+/// See [ensure_queue_capacity].
+pub fn check_queue_capacity(
+    group: &str,
+    queued: usize,
+    max_queued: Option<usize>,
+    additional: usize,
+) -> Result<(), Response> {
+    let Some(max_queued) = max_queued else {
+        return Ok(());
+    };
+    if additional == 0 || queued + additional <= max_queued {
+        return Ok(());
+    }
+
+    if queued >= max_queued {
+        return Err(create_failure_response(format!(
+            "Group \"{group}\" already has the maximum of {max_queued} queued tasks"
+        )));
+    }
+    Err(create_failure_response(format!(
+        "Group \"{group}\" only has room for {} more queued tasks",
+        max_queued - queued
+    )))
+}
+
 /// Compile a response for an action that affect several given tasks.
 /// That action can sometimes only succeed for a portion of the given tasks.
 /// E.g. only running tasks can be killed.
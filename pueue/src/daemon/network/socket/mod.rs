@@ -7,12 +7,15 @@
 use std::time::{Duration, SystemTime};
 
 use pueue_lib::{
-    Error, PROTOCOL_VERSION, Settings, message::*, network::protocol::*, secret::read_shared_secret,
+    Error, PROTOCOL_VERSION, message::*, network::protocol::*, secret::read_shared_secret,
 };
 use tokio::time::sleep;
 
 use crate::{
-    daemon::{internal_state::SharedState, network::message_handler::handle_request},
+    daemon::{
+        internal_state::SharedState, network::message_handler::handle_request,
+        reload::SharedSettings,
+    },
     internal_prelude::*,
 };
 
@@ -25,10 +28,12 @@ pub use self::platform::*;
 /// Listen for new connections on the socket.
 /// On a new connection, the connected stream will be handled in a separate tokio task.
 /// See [handle_incoming] for the actual connection handler function.
-pub async fn accept_incoming(settings: Settings, state: SharedState) -> Result<()> {
-    let listener = get_listener(&settings.shared).await?;
+pub async fn accept_incoming(settings: SharedSettings, state: SharedState) -> Result<()> {
+    // The shared settings cannot be changed by a reload.
+    let shared = settings.get().shared;
+    let listener = get_listener(&shared).await?;
     // Read secret once to prevent multiple disk reads.
-    let secret = read_shared_secret(&settings.shared.shared_secret_path())?;
+    let secret = read_shared_secret(&shared.shared_secret_path())?;
 
     loop {
         // Poll incoming connections.
@@ -66,7 +71,7 @@ pub async fn accept_incoming(settings: Settings, state: SharedState) -> Result<(
 pub async fn handle_incoming(
     mut stream: GenericStream,
     state: SharedState,
-    settings: Settings,
+    settings: SharedSettings,
     secret: Vec<u8>,
 ) -> Result<()> {
     // Receive the secret once and check, whether the client is allowed to connect
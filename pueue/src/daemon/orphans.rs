@@ -0,0 +1,136 @@
+//! If the daemon crashes, the processes of running tasks keep running without anybody tracking
+//! them. The processes of each running task are persisted in the state, which allows us to detect
+//! such survivors on the next start.
+use pueue_lib::{Settings, Task, TaskResult, settings::OrphanPolicy, task::TaskProcess};
+
+use crate::{
+    internal_prelude::*,
+    process_helper::{
+        Signal, boot_id, is_same_process, process_exists, process_group_exists,
+        send_signal_to_process_group,
+    },
+};
+
+/// Handle a task that has been running while the daemon crashed.
+///
+/// Surviving processes are handled according to the configured [OrphanPolicy].
+/// Returns the result the task should be marked with.
+pub fn handle_orphan(settings: &Settings, task: &Task) -> TaskResult {
+    let Some(process) = &task.process else {
+        return TaskResult::Killed;
+    };
+    if !survived(process) {
+        return TaskResult::Killed;
+    }
+
+    let id = task.id;
+    let group = process.process_group;
+    match settings.daemon.orphan_policy {
+        OrphanPolicy::Report => {
+            warn!("Processes of task {id} (process group {group}) survived and are left running");
+            TaskResult::Killed
+        }
+        OrphanPolicy::Kill => {
+            info!("Killing surviving processes of task {id} (process group {group})");
+            if let Err(err) = send_signal_to_process_group(group, Signal::SIGKILL) {
+                warn!("Failed to kill surviving processes of task {id}: {err:?}");
+            }
+            TaskResult::Killed
+        }
+        OrphanPolicy::Tag => {
+            warn!("Processes of task {id} (process group {group}) survived and are tagged");
+            TaskResult::Orphaned
+        }
+    }
+}
+
+/// Check whether any process of a task survived.
+///
+/// Process ids are reused by the system, e.g. after a reboot. If a process with the task's pid
+/// exists, it has to be the very process that has been started for the task.
+/// Otherwise, the remaining processes of the task's group might still be alive. The id of a
+/// process group cannot be reused by a new process, as long as the group exists.
+fn survived(process: &TaskProcess) -> bool {
+    let recorded_boot = process.boot_id.as_deref();
+    if recorded_boot.is_some_and(|id| boot_id().as_deref() != Some(id)) {
+        return false;
+    }
+
+    if process_exists(process.pid) {
+        return is_same_process(process.pid, process.start_time, recorded_boot);
+    }
+    process_group_exists(process.process_group)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{process::Command, thread::sleep, time::Duration};
+
+    use command_group::CommandGroup;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        daemon::network::message_handler::fixtures::*, process_helper::process_start_time,
+    };
+
+    #[test]
+    /// Surviving processes are tagged or killed, depending on the policy.
+    fn handle_surviving_processes() -> Result<()> {
+        let (_, mut settings, _tempdir) = get_state();
+        let mut child = Command::new("sleep").arg("60").group_spawn()?;
+        let mut task = get_stub_task("0", StubStatus::Running);
+        task.process = Some(TaskProcess {
+            pid: child.id(),
+            process_group: child.id(),
+            start_time: process_start_time(child.id()),
+            boot_id: boot_id(),
+        });
+
+        settings.daemon.orphan_policy = OrphanPolicy::Tag;
+        assert_eq!(handle_orphan(&settings, &task), TaskResult::Orphaned);
+        assert!(process_group_exists(child.id()));
+
+        settings.daemon.orphan_policy = OrphanPolicy::Kill;
+        assert_eq!(handle_orphan(&settings, &task), TaskResult::Killed);
+        sleep(Duration::from_millis(100));
+        child.wait()?;
+        assert!(!process_group_exists(child.id()));
+
+        // Tasks without surviving processes are simply killed.
+        settings.daemon.orphan_policy = OrphanPolicy::Tag;
+        assert_eq!(handle_orphan(&settings, &task), TaskResult::Killed);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Processes that reuse the id of a task's process are neither killed nor tagged.
+    fn ignore_reused_process_ids() -> Result<()> {
+        let (_, mut settings, _tempdir) = get_state();
+        let mut child = Command::new("sleep").arg("60").group_spawn()?;
+        let mut task = get_stub_task("0", StubStatus::Running);
+        task.process = Some(TaskProcess {
+            pid: child.id(),
+            process_group: child.id(),
+            start_time: process_start_time(child.id()).map(|time| time + 1),
+            boot_id: boot_id(),
+        });
+
+        settings.daemon.orphan_policy = OrphanPolicy::Kill;
+        assert_eq!(handle_orphan(&settings, &task), TaskResult::Killed);
+        sleep(Duration::from_millis(100));
+        assert!(process_group_exists(child.id()));
+
+        // The process has been started in another boot of the system.
+        let process = task.process.as_mut().unwrap();
+        process.start_time = process_start_time(child.id());
+        process.boot_id = Some("another boot".into());
+        settings.daemon.orphan_policy = OrphanPolicy::Tag;
+        assert_eq!(handle_orphan(&settings, &task), TaskResult::Killed);
+
+        child.kill()?;
+        child.wait()?;
+        Ok(())
+    }
+}
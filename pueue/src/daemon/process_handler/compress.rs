@@ -0,0 +1,255 @@
+//! The logs of finished tasks are no longer written to and can be compressed to save space.
+//! This is done in the background, as compressing large logs takes a while.
+//!
+//! Tasks might be restarted, removed or cleaned while their logs are being compressed.
+//! That's why the compressed logs only replace the original logs under the state lock, once
+//! it's been checked that the task still has the same result.
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use chrono::{DateTime, Local};
+use pueue_lib::{
+    Settings, Task, TaskStatus,
+    log::{CompressedLog, compress_log_file, get_log_path, get_stderr_log_path},
+};
+
+use crate::{daemon::internal_state::state::LockedState, internal_prelude::*};
+
+/// The bookkeeping of the background compression of finished tasks' logs.
+#[derive(Debug, Default)]
+pub struct LogCompressions {
+    /// Finished tasks, whose logs are being compressed or have already been compressed,
+    /// together with the end of the run, whose logs are compressed.
+    tasks: BTreeMap<usize, DateTime<Local>>,
+    /// Logs that have been compressed in the background, but don't replace the original logs
+    /// yet.
+    compressed: Arc<Mutex<Vec<CompressedTaskLog>>>,
+}
+
+/// A log that has been compressed in the background, together with the run it belongs to.
+#[derive(Debug)]
+struct CompressedTaskLog {
+    task_id: usize,
+    end: DateTime<Local>,
+    log: CompressedLog,
+}
+
+/// The end of a finished task's run, which identifies the run whose logs are compressed.
+fn finished_at(task: &Task) -> Option<DateTime<Local>> {
+    match task.status {
+        TaskStatus::Done { end, .. } => Some(end),
+        _ => None,
+    }
+}
+
+/// Replace the logs of finished tasks with the logs that have been compressed in the background
+/// and start compressing the logs of all finished tasks, whose logs haven't been compressed yet.
+pub fn compress_finished_logs(settings: &Settings, state: &mut LockedState) {
+    finish_compressions(state);
+
+    if !settings.daemon.compress_finished_logs {
+        return;
+    }
+
+    let done: BTreeMap<usize, DateTime<Local>> = state
+        .tasks()
+        .iter()
+        .filter_map(|(task_id, task)| Some((*task_id, finished_at(task)?)))
+        .collect();
+
+    // Forget about tasks that have been removed or restarted in the meantime.
+    let compressions = &mut state.log_compressions;
+    compressions
+        .tasks
+        .retain(|task_id, end| done.get(task_id) == Some(end));
+    let tasks: Vec<(usize, DateTime<Local>)> = done
+        .into_iter()
+        .filter(|(task_id, _)| !compressions.tasks.contains_key(task_id))
+        .collect();
+    if tasks.is_empty() {
+        return;
+    }
+
+    let pueue_directory = settings.shared.pueue_directory();
+    compressions.tasks.extend(tasks.iter().copied());
+    let compressed = compressions.compressed.clone();
+    thread::spawn(move || {
+        for (task_id, end) in tasks {
+            for path in [
+                get_log_path(task_id, &pueue_directory),
+                get_stderr_log_path(task_id, &pueue_directory),
+            ] {
+                match compress_log_file(&path) {
+                    Ok(Some(log)) => {
+                        compressed
+                            .lock()
+                            .unwrap()
+                            .push(CompressedTaskLog { task_id, end, log })
+                    }
+                    Ok(None) => {}
+                    Err(err) => error!("Failed to compress log of task {task_id}: {err}"),
+                }
+            }
+        }
+    });
+}
+
+/// Replace the original logs with the compressed ones, if their task still has the same result.
+/// Otherwise, the task has been removed or restarted while its logs were compressed and the
+/// compressed logs are outdated.
+fn finish_compressions(state: &mut LockedState) {
+    let compressed = std::mem::take(&mut *state.log_compressions.compressed.lock().unwrap());
+    for CompressedTaskLog { task_id, end, log } in compressed {
+        let current = state.tasks().get(&task_id).and_then(finished_at);
+        if current != Some(end) {
+            log.discard();
+            continue;
+        }
+
+        if let Err(err) = log.finish() {
+            error!("Failed to replace log of task {task_id} with its compressed log: {err}");
+        }
+    }
+}
+
+/// Remove compressed logs that have been left behind.
+/// That's logs that were being compressed when the daemon stopped and compressed logs of tasks
+/// that no longer exist.
+pub fn remove_orphaned_compressions(pueue_directory: &Path, state: &LockedState) {
+    let Ok(entries) = std::fs::read_dir(pueue_directory.join("task_logs")) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let orphaned = if name.ends_with(".log.sz.partial") {
+            true
+        } else if name.ends_with(".log.sz") {
+            name.split('.')
+                .next()
+                .and_then(|task_id| task_id.parse::<usize>().ok())
+                .is_none_or(|task_id| !state.tasks().contains_key(&task_id))
+        } else {
+            false
+        };
+
+        if orphaned {
+            if let Err(err) = std::fs::remove_file(&path) {
+                error!("Failed to remove orphaned compressed log {path:?}: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::PathBuf};
+
+    use pretty_assertions::assert_eq;
+    use pueue_lib::{
+        TaskResult,
+        log::{create_log_file_handles, get_compressed_log_path, get_partial_compressed_log_path},
+    };
+
+    use super::*;
+    use crate::daemon::network::message_handler::fixtures::*;
+
+    /// Get the pueue directory of the test settings, including its task log directory.
+    fn pueue_directory(settings: &Settings) -> PathBuf {
+        let pueue_dir = settings.shared.pueue_directory();
+        std::fs::create_dir_all(pueue_dir.join("task_logs")).unwrap();
+        pueue_dir
+    }
+
+    /// Wait until the logs of a task have been compressed in the background.
+    fn wait_for_compression(state: &mut LockedState) {
+        for _ in 0..100 {
+            if !state.log_compressions.compressed.lock().unwrap().is_empty() {
+                return;
+            }
+            thread::sleep(std::time::Duration::from_millis(20));
+        }
+        panic!("The log hasn't been compressed");
+    }
+
+    /// Write a log for a task and mark it as finished.
+    fn finish_task(state: &mut LockedState, pueue_dir: &Path, task_id: usize) {
+        let (mut log, _) = create_log_file_handles(task_id, pueue_dir, false).unwrap();
+        log.write_all(b"output\n").unwrap();
+        let now = Local::now();
+        state.task_mut(task_id).unwrap().status = TaskStatus::Done {
+            enqueued_at: now,
+            start: now,
+            end: now,
+            result: TaskResult::Success,
+        };
+    }
+
+    /// The logs of finished tasks are replaced by their compressed logs.
+    #[test]
+    fn compress_logs() {
+        let (state, mut settings, _tempdir) = get_stub_state();
+        settings.daemon.compress_finished_logs = true;
+        let pueue_dir = &pueue_directory(&settings);
+        let mut state = state.lock().unwrap();
+        finish_task(&mut state, pueue_dir, 0);
+
+        compress_finished_logs(&settings, &mut state);
+        wait_for_compression(&mut state);
+        compress_finished_logs(&settings, &mut state);
+
+        let log_path = get_log_path(0, pueue_dir);
+        assert!(!log_path.exists());
+        assert!(get_compressed_log_path(&log_path).exists());
+    }
+
+    /// Compressed logs are discarded, if their task has been restarted in the meantime.
+    #[test]
+    fn discard_outdated_compression() {
+        let (state, mut settings, _tempdir) = get_stub_state();
+        settings.daemon.compress_finished_logs = true;
+        let pueue_dir = &pueue_directory(&settings);
+        let mut state = state.lock().unwrap();
+        finish_task(&mut state, pueue_dir, 0);
+
+        compress_finished_logs(&settings, &mut state);
+        wait_for_compression(&mut state);
+        state.task_mut(0).unwrap().status = TaskStatus::Queued {
+            enqueued_at: Local::now(),
+        };
+        compress_finished_logs(&settings, &mut state);
+
+        let log_path = get_log_path(0, pueue_dir);
+        assert_eq!(std::fs::read_to_string(&log_path).unwrap(), "output\n");
+        assert!(!get_compressed_log_path(&log_path).exists());
+        assert!(!get_partial_compressed_log_path(&log_path).exists());
+        assert!(!state.log_compressions.tasks.contains_key(&0));
+    }
+
+    /// Compressed logs of removed tasks and partially compressed logs are removed.
+    #[test]
+    fn remove_orphans() {
+        let (state, settings, _tempdir) = get_stub_state();
+        let pueue_dir = &pueue_directory(&settings);
+        let state = state.lock().unwrap();
+
+        let orphan = get_compressed_log_path(&get_log_path(100, pueue_dir));
+        let partial = get_partial_compressed_log_path(&get_log_path(0, pueue_dir));
+        let existing = get_compressed_log_path(&get_log_path(0, pueue_dir));
+        for path in [&orphan, &partial, &existing] {
+            std::fs::write(path, "").unwrap();
+        }
+
+        remove_orphaned_compressions(pueue_dir, &state);
+        assert!(!orphan.exists());
+        assert!(!partial.exists());
+        assert!(existing.exists());
+    }
+}
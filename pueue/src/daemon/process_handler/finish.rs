@@ -1,17 +1,32 @@
+use std::{path::Path, time::Duration};
+
 use chrono::Local;
-use pueue_lib::{GroupStatus, Settings, TaskResult, TaskStatus, log::clean_log_handles};
+use pueue_lib::{
+    GroupStatus, Settings, TaskResult, TaskStatus, log::clean_log_handles, task::PreemptionAction,
+};
 
 use crate::{
-    daemon::{callbacks::spawn_callback, internal_state::state::LockedState},
+    daemon::{
+        callbacks::spawn_callback,
+        internal_state::{
+            children::TaskChild,
+            state::{InternalState, LockedState},
+        },
+        supervisor,
+    },
     internal_prelude::*,
     ok_or_shutdown,
 };
 
+/// How long we wait for the remaining output of a task after its process exited.
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// Check whether there are any finished processes
 /// In case there are, handle them and update the shared state
 pub fn handle_finished_tasks(settings: &Settings, state: &mut LockedState) {
+    let pueue_directory = settings.shared.pueue_directory();
     // Clone the state ref, so we don't have two mutable borrows later on.
-    let finished = get_finished(state);
+    let finished = get_finished(state, &pueue_directory);
 
     // Nothing to do. Early return
     if finished.is_empty() {
@@ -19,8 +34,13 @@ pub fn handle_finished_tasks(settings: &Settings, state: &mut LockedState) {
     }
 
     for ((task_id, group, worker_id), error) in finished.iter() {
+        // The output has already been recorded by now, see `get_finished`.
+        state.captures.remove(task_id);
+
         let (enqueued_at, start) = {
-            let task = state.tasks().get(task_id).unwrap();
+            let task = state.task_mut(*task_id).unwrap();
+            // The task's process is gone.
+            task.process = None;
             // Get the enqueued_at/start times from the current state.
             match task.status {
                 TaskStatus::Running { enqueued_at, start }
@@ -43,10 +63,11 @@ pub fn handle_finished_tasks(settings: &Settings, state: &mut LockedState) {
                 .expect("Worker group must exist when handling finished tasks.")
                 .remove(worker_id)
                 .expect("Errored child went missing while handling finished task.");
+            supervisor::clean(*task_id, &pueue_directory);
 
             // Update the tasks's state and return a clone for callback handling.
             let task = {
-                let task = state.tasks_mut().get_mut(task_id).unwrap();
+                let task = state.task_mut(*task_id).unwrap();
 
                 task.status = TaskStatus::Done {
                     enqueued_at,
@@ -66,7 +87,7 @@ pub fn handle_finished_tasks(settings: &Settings, state: &mut LockedState) {
         }
 
         // Handle any tasks that exited with some kind of exit code
-        let (_task_id, mut child) = state
+        let (_task_id, child) = state
             .children
             .0
             .get_mut(group)
@@ -74,34 +95,54 @@ pub fn handle_finished_tasks(settings: &Settings, state: &mut LockedState) {
             .remove(worker_id)
             .expect("Child of task {} went away while handling finished task.");
 
-        // Get the exit code of the child.
-        // Errors really shouldn't happen in here, since we already checked if it's finished
-        // with try_wait() before.
-        let exit_code_result = child.wait();
-        let exit_code = exit_code_result
-            .context(format!(
-                "Failed on wait() for finished task {task_id} with error: {error:?}"
-            ))
-            .unwrap()
-            .code();
-
-        // Processes with exit code 0 exited successfully
-        // Processes with `None` have been killed by a Signal
-        let result = match exit_code {
-            Some(0) => TaskResult::Success,
-            Some(exit_code) => TaskResult::Failed(exit_code),
-            None => TaskResult::Killed,
+        let result = match child {
+            TaskChild::Spawned(mut child) => {
+                // Get the exit code of the child.
+                // Errors really shouldn't happen in here, since we already checked if it's
+                // finished with try_wait() before.
+                let exit_code_result = child.wait();
+                let exit_code = exit_code_result
+                    .context(format!(
+                        "Failed on wait() for finished task {task_id} with error: {error:?}"
+                    ))
+                    .unwrap()
+                    .code();
+
+                // Processes with exit code 0 exited successfully
+                // Processes with `None` have been killed by a Signal
+                match exit_code {
+                    Some(0) => TaskResult::Success,
+                    Some(exit_code) => TaskResult::Failed(exit_code),
+                    None => TaskResult::Killed,
+                }
+            }
+            // Adopted supervisors aren't our children, so there's no exit code to wait for.
+            // If they didn't record a result, they have been killed.
+            TaskChild::Adopted(_) => TaskResult::Killed,
         };
+        // Supervisors record the actual result of their task.
+        let result = supervisor::take_result(*task_id, &pueue_directory).unwrap_or(result);
 
         info!("Task {task_id} finished with result: {result:?}");
 
         // Update the tasks's state and return a clone for callback handling.
         let task = {
             let task = state
-                .tasks_mut()
-                .get_mut(task_id)
+                .task_mut(*task_id)
                 .expect("Task was removed before child process has finished!");
 
+            // Restartable tasks that have been killed due to a preemption are put back into
+            // the queue instead of being marked as done.
+            let requeue = task.preemptions.last_mut().filter(|preemption| {
+                preemption.resumed_at.is_none() && preemption.action == PreemptionAction::Requeued
+            });
+            if let Some(preemption) = requeue {
+                preemption.resumed_at = Some(Local::now());
+                task.status = TaskStatus::Queued { enqueued_at };
+                info!("Requeued preempted task {task_id}");
+                continue;
+            }
+
             task.status = TaskStatus::Done {
                 enqueued_at,
                 start,
@@ -125,7 +166,7 @@ pub fn handle_finished_tasks(settings: &Settings, state: &mut LockedState) {
             .map(|group| group.status == GroupStatus::Reset)
             .unwrap_or(true)
         {
-            clean_log_handles(*task_id, &settings.shared.pueue_directory());
+            clean_log_handles(*task_id, &pueue_directory);
         }
     }
 
@@ -134,10 +175,40 @@ pub fn handle_finished_tasks(settings: &Settings, state: &mut LockedState) {
 
 /// Gather all finished tasks and sort them by finished and errored.
 /// Returns a list of finished task ids and whether they errored or not.
-fn get_finished(state: &mut LockedState) -> Vec<((usize, String, usize), Option<std::io::Error>)> {
+///
+/// Tasks whose output is piped through the daemon are only considered finished, once their whole
+/// output has been recorded. As the state is locked in here, we don't wait for that, but check
+/// again on the next iteration of the task handler.
+fn get_finished(
+    state: &mut LockedState,
+    pueue_directory: &Path,
+) -> Vec<((usize, String, usize), Option<std::io::Error>)> {
     let mut finished = Vec::new();
-    for (group, children) in state.children.0.iter_mut() {
+    let InternalState {
+        children, captures, ..
+    } = &mut **state;
+    for (group, children) in children.0.iter_mut() {
         for (worker_id, (task_id, child)) in children.iter_mut() {
+            // The process already exited, but its output might still be recorded.
+            if let Some(capture) = captures.get_mut(task_id) {
+                if capture.has_exited() {
+                    if capture.is_recorded(OUTPUT_TIMEOUT) {
+                        finished.push(((*task_id, group.clone(), *worker_id), None));
+                    }
+                    continue;
+                }
+            }
+
+            let child = match child {
+                TaskChild::Spawned(child) => child,
+                TaskChild::Adopted(process) => {
+                    if supervisor::is_finished(*task_id, process, pueue_directory) {
+                        finished.push(((*task_id, group.clone(), *worker_id), None));
+                    }
+                    continue;
+                }
+            };
+
             match child.try_wait() {
                 // Handle a child error.
                 Err(error) => {
@@ -146,7 +217,12 @@ fn get_finished(state: &mut LockedState) -> Vec<((usize, String, usize), Option<
                 // Child process did not exit yet
                 Ok(None) => continue,
                 Ok(_exit_status) => {
-                    finished.push(((*task_id, group.clone(), *worker_id), None));
+                    let recorded = captures
+                        .get_mut(task_id)
+                        .is_none_or(|capture| capture.is_recorded(OUTPUT_TIMEOUT));
+                    if recorded {
+                        finished.push(((*task_id, group.clone(), *worker_id), None));
+                    }
                 }
             }
         }
@@ -5,10 +5,8 @@ use pueue_lib::{
 
 
 use crate::{
-    daemon::internal_state::state::LockedState,
-    internal_prelude::*,
-    ok_or_shutdown,
-    process_helper::{kill_child, send_signal_to_child, signal_from_internal},
+    daemon::internal_state::state::LockedState, internal_prelude::*, ok_or_shutdown,
+    process_helper::signal_from_internal,
 };
 use crate::daemon::network::nats::PueuedWorker;
 
@@ -52,7 +50,7 @@ pub fn kill(
 
             // Check whether the group should be paused before killing the tasks.
             if should_pause_group(state, issued_by_user, &group_name) {
-                let group = state.groups_mut().get_mut(&group_name).unwrap();
+                let group = state.group_mut(&group_name).unwrap();
                 group.status = GroupStatus::Paused;
             }
 
@@ -96,7 +94,7 @@ pub fn kill(
 }
 
 /// Send a signal to a specific child process.
-/// This is a wrapper around [send_signal_to_child], which does a little bit of
+/// This is a wrapper around the child's `signal` method, which does a little bit of
 /// additional error handling.
 pub fn send_internal_signal(state: &mut LockedState, task_id: usize, signal: Signal) {
     let child = match state.children.get_child_mut(task_id) {
@@ -107,7 +105,7 @@ pub fn send_internal_signal(state: &mut LockedState, task_id: usize, signal: Sig
         }
     };
 
-    if let Err(err) = send_signal_to_child(child, signal_from_internal(signal)) {
+    if let Err(err) = child.signal(signal_from_internal(signal)) {
         warn!("Failed to send signal to task {task_id} with error: {err}");
     };
 }
@@ -116,7 +114,7 @@ pub fn send_internal_signal(state: &mut LockedState, task_id: usize, signal: Sig
 /// Triggered on `reset` and `kill`.
 pub fn kill_task(state: &mut LockedState, task_id: usize) {
     if let Some(child) = state.children.get_child_mut(task_id) {
-        kill_child(task_id, child).unwrap_or_else(|err| {
+        child.kill(task_id).unwrap_or_else(|err| {
             warn!(
                 "Failed to send kill to task {task_id} child process {child:?} with error {err:?}"
             );
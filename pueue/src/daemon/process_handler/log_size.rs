@@ -0,0 +1,101 @@
+//! A single task may print enough output to fill the whole disk.
+//! That's why the log files of running tasks are regularly checked against their size limit.
+use pueue_lib::{Settings, log::get_log_paths, settings::LogSizePolicy};
+
+use crate::{
+    daemon::{internal_state::state::LockedState, process_handler::kill::kill_task},
+    internal_prelude::*,
+};
+
+/// The maximum log size and policy for tasks of a group.
+/// The group's own configuration takes precedence over the global one.
+pub fn log_size_limit(settings: &Settings, group: &str) -> Option<(u64, LogSizePolicy)> {
+    let declared = settings.daemon.groups.get(group);
+    let max_size = declared
+        .and_then(|declared| declared.max_log_size)
+        .or(settings.daemon.max_log_size)?;
+    let policy = declared
+        .and_then(|declared| declared.log_size_policy)
+        .unwrap_or(settings.daemon.log_size_policy);
+
+    Some((max_size, policy))
+}
+
+/// Check the log files of all running tasks and kill those that exceed their `max_log_size`.
+///
+/// The logs of tasks, whose output is piped through the daemon, are truncated or rotated while
+/// their output is recorded. All other tasks, i.e. tasks that run under a supervisor, write
+/// directly into their log files. Those logs can't be truncated or rotated without losing
+/// output, which is why such tasks are killed, regardless of their [LogSizePolicy].
+pub fn limit_log_sizes(settings: &Settings, state: &mut LockedState) {
+    let pueue_directory = settings.shared.pueue_directory();
+    let tasks: Vec<(usize, u64, LogSizePolicy)> = state
+        .tasks()
+        .values()
+        .filter(|task| task.is_running())
+        .filter_map(|task| {
+            log_size_limit(settings, &task.group).map(|(size, policy)| (task.id, size, policy))
+        })
+        .filter(|(task_id, _, policy)| {
+            *policy == LogSizePolicy::Kill || !state.captures.contains_key(task_id)
+        })
+        .collect();
+
+    for (task_id, max_size, policy) in tasks {
+        let Ok(paths) = get_log_paths(task_id, &pueue_directory, None) else {
+            continue;
+        };
+
+        let exceeded = paths
+            .iter()
+            .any(|path| std::fs::metadata(path).is_ok_and(|metadata| metadata.len() > max_size));
+        if !exceeded {
+            continue;
+        }
+
+        if policy == LogSizePolicy::Kill {
+            warn!("Killing task {task_id}, as its log exceeds {max_size} bytes");
+        } else {
+            warn!(
+                "Killing task {task_id}, as its log exceeds {max_size} bytes and can't be \
+                truncated or rotated, because its output isn't piped through the daemon"
+            );
+        }
+        kill_task(state, task_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use pueue_lib::settings::GroupSettings;
+
+    use super::*;
+    use crate::daemon::network::message_handler::fixtures::*;
+
+    #[test]
+    /// Groups can override the global log size limit.
+    fn group_log_size_limit() {
+        let (_, mut settings, _tempdir) = get_state();
+        assert_eq!(log_size_limit(&settings, PUEUE_DEFAULT_GROUP), None);
+
+        settings.daemon.max_log_size = Some(1024);
+        settings.daemon.groups.insert(
+            "huge".into(),
+            GroupSettings {
+                max_log_size: Some(4096),
+                log_size_policy: Some(LogSizePolicy::Rotate),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            log_size_limit(&settings, PUEUE_DEFAULT_GROUP),
+            Some((1024, LogSizePolicy::Truncate))
+        );
+        assert_eq!(
+            log_size_limit(&settings, "huge"),
+            Some((4096, LogSizePolicy::Rotate))
+        );
+    }
+}
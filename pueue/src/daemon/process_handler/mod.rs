@@ -4,14 +4,19 @@ use pueue_lib::{
 };
 
 use crate::{
-    daemon::internal_state::state::LockedState,
+    daemon::{internal_state::state::LockedState, supervisor},
     internal_prelude::*,
-    process_helper::{ProcessAction, send_signal_to_child},
+    process_helper::ProcessAction,
 };
 
+pub mod compress;
 pub mod finish;
 pub mod kill;
+pub mod log_size;
 pub mod pause;
+pub mod preempt;
+pub mod retention;
+pub mod scheduling;
 pub mod spawn;
 pub mod start;
 
@@ -39,11 +44,16 @@ macro_rules! ok_or_shutdown {
 /// Initiate shutdown, which includes killing all children and pausing all groups.
 /// We don't have to pause any groups, as no new tasks will be spawned during shutdown anyway.
 /// Any groups with queued tasks, will be automatically paused on state-restoration.
+///
+/// On a graceful shutdown, tasks that run under a supervisor are left running.
 pub fn initiate_shutdown(settings: &Settings, state: &mut LockedState, shutdown: ShutdownRequest) {
     // Only start shutdown if we aren't already in one.
     // Otherwise, we might end up with an endless recursion as `kill` might fail and initiate
     // shutdown once again.
     if state.shutdown.is_none() {
+        if shutdown == ShutdownRequest::Graceful {
+            supervisor::detach_tasks(settings, state);
+        }
         state.shutdown = Some(shutdown);
         self::kill::kill(settings, state, TaskSelection::All, false, None);
     }
@@ -55,7 +65,7 @@ pub fn perform_action(state: &mut LockedState, id: usize, action: ProcessAction)
     match state.children.get_child_mut(id) {
         Some(child) => {
             debug!("Executing action {action:?} to {id}");
-            send_signal_to_child(child, action.into())?;
+            child.signal(action.into())?;
 
             Ok(true)
         }
@@ -0,0 +1,264 @@
+use std::cmp::Reverse;
+
+use chrono::Local;
+use pueue_lib::{
+    GroupStatus, Settings, Task, TaskStatus,
+    task::{Preemption, PreemptionAction},
+};
+
+use crate::{
+    daemon::{
+        internal_state::state::LockedState,
+        process_handler::{
+            kill::kill_task,
+            perform_action,
+            scheduling::priority_order,
+            spawn::{concurrency_key_available, dependencies_fulfilled},
+            start::continue_task,
+        },
+    },
+    internal_prelude::*,
+    ok_or_shutdown,
+    process_helper::ProcessAction,
+};
+
+/// Check all groups that have preemption enabled, whether a queued task should take the slot of
+/// a running task with a lower priority.
+///
+/// Preempted tasks are paused, unless they're marked as restartable.
+/// In that case, they're killed and put back into the queue once their process exited.
+pub fn preempt_tasks(settings: &Settings, state: &mut LockedState) {
+    let groups: Vec<(String, i32)> = state
+        .groups()
+        .iter()
+        .filter(|(_, group)| group.status == GroupStatus::Running && group.parallel_tasks != 0)
+        .filter_map(|(name, group)| group.preemption_margin.map(|margin| (name.clone(), margin)))
+        .collect();
+
+    let mut changed = false;
+    for (group, margin) in groups {
+        // Each preemption frees a slot, so this loop ends as soon as there's enough room for
+        // all queued tasks that justify a preemption.
+        while let Some((task_id, victim_id)) = find_preemption(settings, state, &group, margin) {
+            if !preempt(state, victim_id, task_id) {
+                break;
+            }
+            changed = true;
+        }
+    }
+
+    if changed {
+        ok_or_shutdown!(settings, state, state.save(settings));
+    }
+}
+
+/// Resume tasks that have been paused due to a preemption, once their group has a free slot.
+/// Tasks with a higher priority are resumed first.
+pub fn resume_preempted_tasks(settings: &Settings, state: &mut LockedState) {
+    let mut preempted: Vec<&Task> = state
+        .tasks()
+        .values()
+        .filter(|task| task.is_paused() && task.is_preempted())
+        .collect();
+    // Sort by group first, as the queue position is only comparable within a group.
+    preempted.sort_by(|a, b| {
+        a.group
+            .cmp(&b.group)
+            .then_with(|| priority_order(None, a, b))
+    });
+    let preempted: Vec<(usize, String)> = preempted
+        .into_iter()
+        .map(|task| (task.id, task.group.clone()))
+        .collect();
+
+    let mut changed = false;
+    for (task_id, group) in preempted {
+        let Some(parallel_tasks) = state
+            .groups()
+            .get(&group)
+            .filter(|group| group.status == GroupStatus::Running)
+            .map(|group| group.parallel_tasks)
+        else {
+            continue;
+        };
+
+        if parallel_tasks != 0 && active_tasks(state, &group).len() >= parallel_tasks {
+            continue;
+        }
+
+        info!("Resuming preempted task {task_id}");
+        continue_task(state, task_id);
+        changed = true;
+    }
+
+    if changed {
+        ok_or_shutdown!(settings, state, state.save(settings));
+    }
+}
+
+/// Determine whether a queued task of the given group should preempt a running task.
+///
+/// Returns the `(task_id, victim_id)` of the queued task with the highest priority and the
+/// running task with the lowest priority, if:
+/// - All slots of the group are taken.
+/// - The queued task could be started right now, if there was a free slot.
+/// - The queued task's priority exceeds the running task's priority by at least `margin`.
+pub fn find_preemption(
+    settings: &Settings,
+    state: &LockedState,
+    group: &str,
+    margin: i32,
+) -> Option<(usize, usize)> {
+    let parallel_tasks = state.groups().get(group)?.parallel_tasks;
+    let active = active_tasks(state, group);
+    if active.len() < parallel_tasks {
+        return None;
+    }
+
+    let aging = settings.daemon.priority_aging_interval();
+    let task = state
+        .tasks()
+        .values()
+        .filter(|task| task.group == group && matches!(task.status, TaskStatus::Queued { .. }))
+        .filter(|task| dependencies_fulfilled(state, task))
+        .filter(|task| concurrency_key_available(state, task))
+        .min_by(|a, b| priority_order(aging, a, b))?;
+
+    // Prefer the most recently added task, if several tasks have the same low priority.
+    let victim = active
+        .into_iter()
+        .filter(|task| matches!(task.status, TaskStatus::Running { .. }))
+        .min_by_key(|task| (task.priority, Reverse(task.id)))?;
+
+    let difference = task
+        .effective_priority(aging)
+        .saturating_sub(victim.priority);
+    if difference < margin.max(1) {
+        return None;
+    }
+
+    Some((task.id, victim.id))
+}
+
+/// All tasks of a group that currently occupy a slot.
+fn active_tasks<'a>(state: &'a LockedState, group: &str) -> Vec<&'a Task> {
+    state
+        .tasks()
+        .values()
+        .filter(|task| task.group == group && task.is_running() && !task.is_preempted())
+        .collect()
+}
+
+/// Preempt a running task in favor of the task with the id `preempted_by`.
+/// Returns `false`, if the task couldn't be preempted.
+fn preempt(state: &mut LockedState, task_id: usize, preempted_by: usize) -> bool {
+    let Some(restartable) = state.tasks().get(&task_id).map(|task| task.restartable) else {
+        return false;
+    };
+
+    let action = if restartable {
+        // The task will be requeued in `handle_finished_tasks` once its process exited.
+        kill_task(state, task_id);
+        PreemptionAction::Requeued
+    } else {
+        match perform_action(state, task_id, ProcessAction::Pause) {
+            Ok(true) => PreemptionAction::Paused,
+            Ok(false) => return false,
+            Err(err) => {
+                error!("Failed to pause task {task_id} for preemption: {err:?}");
+                return false;
+            }
+        }
+    };
+
+    let task = state.task_mut(task_id).unwrap();
+    if action == PreemptionAction::Paused {
+        if let TaskStatus::Running { enqueued_at, start } = task.status {
+            task.status = TaskStatus::Paused { enqueued_at, start };
+        }
+    }
+    task.preemptions.push(Preemption {
+        preempted_by,
+        at: Local::now(),
+        action,
+        resumed_at: None,
+    });
+    info!("Task {task_id} has been preempted by task {preempted_by} ({action})");
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::daemon::{internal_state::SharedState, network::message_handler::fixtures::*};
+
+    /// Create a state with two running tasks in a group with two slots and an urgent task.
+    fn get_preemption_state() -> (SharedState, Settings, TempDir) {
+        let (state, settings, tempdir) = get_state();
+        {
+            let mut state = state.lock().unwrap();
+            state.create_group("build").parallel_tasks = 2;
+
+            let mut task = get_stub_task_in_group("0", "build", StubStatus::Running);
+            task.priority = 0;
+            state.add_task(task);
+            let mut task = get_stub_task_in_group("1", "build", StubStatus::Running);
+            task.priority = -5;
+            state.add_task(task);
+            let mut task = get_stub_task_in_group("2", "build", StubStatus::Queued);
+            task.priority = 5;
+            state.add_task(task);
+        }
+
+        (state, settings, tempdir)
+    }
+
+    #[test]
+    /// The running task with the lowest priority is preempted.
+    fn preempt_lowest_priority() {
+        let (state, settings, _tempdir) = get_preemption_state();
+        let state = state.lock().unwrap();
+
+        assert_eq!(find_preemption(&settings, &state, "build", 5), Some((2, 1)));
+    }
+
+    #[test]
+    /// No preemption happens, if the priority difference is smaller than the margin.
+    fn respect_margin() {
+        let (state, settings, _tempdir) = get_preemption_state();
+        let state = state.lock().unwrap();
+
+        assert_eq!(find_preemption(&settings, &state, "build", 11), None);
+    }
+
+    #[test]
+    /// No preemption happens, if there's still a free slot.
+    fn no_preemption_with_free_slots() {
+        let (state, settings, _tempdir) = get_preemption_state();
+        let mut state = state.lock().unwrap();
+        state.group_mut("build").unwrap().parallel_tasks = 3;
+
+        assert_eq!(find_preemption(&settings, &state, "build", 1), None);
+    }
+
+    #[test]
+    /// Already preempted tasks don't occupy a slot and aren't preempted twice.
+    fn ignore_preempted_tasks() {
+        let (state, settings, _tempdir) = get_preemption_state();
+        let mut state = state.lock().unwrap();
+        let task = state.task_mut(1).unwrap();
+        task.status = get_stub_task("1", StubStatus::Paused).status;
+        task.preemptions.push(Preemption {
+            preempted_by: 2,
+            at: Local::now(),
+            action: PreemptionAction::Paused,
+            resumed_at: None,
+        });
+
+        assert_eq!(find_preemption(&settings, &state, "build", 1), None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Finished tasks pile up over time, if they aren't cleaned regularly.
+//! The retention policies remove old finished tasks and their logs automatically.
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{TimeDelta, prelude::*};
+use pueue_lib::{Settings, TaskStatus};
+
+use crate::{
+    daemon::{internal_state::state::LockedState, network::message_handler::remove_finished_tasks},
+    internal_prelude::*,
+    ok_or_shutdown,
+};
+
+/// Remove all finished tasks that exceed the `retention_days`, `failed_retention_days` or
+/// `max_finished_tasks` limits.
+///
+/// The oldest tasks of a group are removed first, if a group has too many finished tasks.
+/// Tasks that other tasks still depend on are kept and skipped without further checks, but they
+/// still count towards the `max_finished_tasks` of their group.
+pub fn apply_retention_policies(settings: &Settings, state: &mut LockedState) {
+    let daemon = &settings.daemon;
+    if daemon.retention_days.is_none()
+        && daemon.failed_retention_days.is_none()
+        && daemon.max_finished_tasks.is_none()
+    {
+        return;
+    }
+
+    // Finished tasks can only be removed, once no unfinished task depends on them anymore.
+    let required: HashSet<usize> = state
+        .tasks()
+        .values()
+        .filter(|task| !task.is_done())
+        .flat_map(|task| task.dependencies.iter().copied())
+        .collect();
+
+    let now = Local::now();
+    let mut expired = Vec::new();
+    // The end time and id of all finished tasks that aren't expired yet, per group.
+    let mut finished: BTreeMap<&str, Vec<(DateTime<Local>, usize)>> = BTreeMap::new();
+    for (task_id, task) in state.tasks() {
+        let TaskStatus::Done { end, .. } = task.status else {
+            continue;
+        };
+
+        let retention_days = if task.failed() {
+            daemon.failed_retention_days.or(daemon.retention_days)
+        } else {
+            daemon.retention_days
+        };
+        if !required.contains(task_id)
+            && retention_days.is_some_and(|days| now - end > TimeDelta::days(days.into()))
+        {
+            expired.push(*task_id);
+            continue;
+        }
+
+        finished
+            .entry(&task.group)
+            .or_default()
+            .push((end, *task_id));
+    }
+
+    if let Some(max_finished_tasks) = daemon.max_finished_tasks {
+        for tasks in finished.values_mut() {
+            if tasks.len() <= max_finished_tasks {
+                continue;
+            }
+            tasks.sort();
+            // Skip the tasks that have to be kept and remove the next oldest ones instead.
+            let excess = tasks.len() - max_finished_tasks;
+            expired.extend(
+                tasks
+                    .iter()
+                    .map(|(_, task_id)| *task_id)
+                    .filter(|task_id| !required.contains(task_id))
+                    .take(excess),
+            );
+        }
+    }
+
+    if expired.is_empty() {
+        return;
+    }
+
+    let removed = remove_finished_tasks(settings, state, &expired);
+    if removed.is_empty() {
+        return;
+    }
+    info!("Removed finished tasks due to the retention policies: {removed:?}");
+
+    ok_or_shutdown!(settings, state, state.save(settings));
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::daemon::network::message_handler::fixtures::*;
+
+    /// Add a finished task, that ended the given amount of days ago.
+    fn add_finished_task(state: &mut LockedState, group: &str, result: TaskResult, days: i64) {
+        let mut task = get_stub_task_in_group("0", group, StubStatus::Done(result));
+        if let TaskStatus::Done { end, .. } = &mut task.status {
+            *end = Local::now() - TimeDelta::days(days);
+        }
+        state.add_task(task);
+    }
+
+    #[test]
+    /// Finished tasks are removed after their retention days.
+    /// Failed tasks are kept around for longer.
+    fn retention_days() {
+        let (state, mut settings, _tempdir) = get_state();
+        settings.daemon.retention_days = Some(7);
+        settings.daemon.failed_retention_days = Some(30);
+
+        let mut state = state.lock().unwrap();
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Success, 1);
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Success, 10);
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Failed(1), 10);
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Failed(1), 40);
+
+        apply_retention_policies(&settings, &mut state);
+
+        let task_ids: Vec<usize> = state.tasks().keys().copied().collect();
+        assert_eq!(task_ids, vec![0, 2]);
+    }
+
+    #[test]
+    /// Only the most recent finished tasks of each group are kept.
+    fn max_finished_tasks_per_group() {
+        let (state, mut settings, _tempdir) = get_state();
+        settings.daemon.max_finished_tasks = Some(2);
+
+        let mut state = state.lock().unwrap();
+        state.create_group("other");
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Success, 3);
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Failed(1), 1);
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Success, 2);
+        add_finished_task(&mut state, "other", TaskResult::Success, 5);
+        state.add_task(get_stub_task("4", StubStatus::Queued));
+
+        apply_retention_policies(&settings, &mut state);
+
+        let task_ids: Vec<usize> = state.tasks().keys().copied().collect();
+        assert_eq!(task_ids, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    /// Tasks that other tasks depend on are kept, the next oldest tasks are removed instead.
+    /// Expired tasks are kept as well, as long as they're a dependency.
+    fn keep_dependencies() {
+        let (state, mut settings, _tempdir) = get_state();
+        settings.daemon.retention_days = Some(7);
+        settings.daemon.max_finished_tasks = Some(1);
+
+        let mut state = state.lock().unwrap();
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Success, 10);
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Success, 3);
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Success, 2);
+        add_finished_task(&mut state, PUEUE_DEFAULT_GROUP, TaskResult::Success, 1);
+        let mut task = get_stub_task("4", StubStatus::Queued);
+        task.dependencies = vec![0, 1];
+        state.add_task(task);
+
+        apply_retention_policies(&settings, &mut state);
+
+        let task_ids: Vec<usize> = state.tasks().keys().copied().collect();
+        assert_eq!(task_ids, vec![0, 1, 4]);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use chrono::TimeDelta;
+use pueue_lib::{Settings, Task, settings::SchedulingPolicy};
+
+use crate::daemon::internal_state::state::LockedState;
+
+/// Pick the task that should be started next from a list of tasks that could be started
+/// right now. The decision is made based on the configured [SchedulingPolicy].
+pub fn select_next_task<'a>(
+    settings: &Settings,
+    state: &LockedState,
+    candidates: Vec<&'a Task>,
+) -> Option<&'a Task> {
+    let aging = settings.daemon.priority_aging_interval();
+    match settings.daemon.scheduling_policy {
+        SchedulingPolicy::Fifo => candidates
+            .into_iter()
+            .min_by(|a, b| priority_order(aging, a, b)),
+        SchedulingPolicy::FairShare => {
+            let weight = |group: &str| {
+                settings
+                    .daemon
+                    .group_weights
+                    .get(group)
+                    .copied()
+                    .unwrap_or(1)
+                    .max(1)
+            };
+            // Count the running tasks of each group.
+            let mut running_per_group: HashMap<&str, usize> = HashMap::new();
+            for task in state.tasks().values().filter(|task| task.is_running()) {
+                *running_per_group.entry(task.group.as_str()).or_default() += 1;
+            }
+            let running = |group: &str| running_per_group.get(group).copied().unwrap_or(0);
+
+            candidates.into_iter().min_by(|a, b| {
+                // Compare `running_a / weight_a` with `running_b / weight_b` without having
+                // to deal with floating point numbers.
+                let share_a = running(&a.group) * weight(&b.group);
+                let share_b = running(&b.group) * weight(&a.group);
+                share_a
+                    .cmp(&share_b)
+                    .then_with(|| priority_order(aging, a, b))
+            })
+        }
+        SchedulingPolicy::RoundRobin => {
+            // Count the running tasks of each label.
+            let mut running_per_label: HashMap<Option<&str>, usize> = HashMap::new();
+            for task in state.tasks().values().filter(|task| task.is_running()) {
+                *running_per_label.entry(task.label.as_deref()).or_default() += 1;
+            }
+            let running = |task: &Task| {
+                running_per_label
+                    .get(&task.label.as_deref())
+                    .copied()
+                    .unwrap_or(0)
+            };
+
+            candidates.into_iter().min_by(|a, b| {
+                running(a)
+                    .cmp(&running(b))
+                    .then_with(|| priority_order(aging, a, b))
+            })
+        }
+    }
+}
+
+/// The default execution order of tasks.
+/// Tasks that have been moved to the front or back of their group go first or last.
+/// See [Task::queue_position]. This only applies to tasks of the same group, so moving a task
+/// within its group doesn't affect other groups.
+/// Otherwise, tasks with higher effective priority go first. See [Task::effective_priority].
+/// Tasks with the same priority are ordered by their id in ascending order, meaning that
+/// tasks with smaller id will be processed first.
+pub fn priority_order(aging: Option<TimeDelta>, a: &Task, b: &Task) -> Ordering {
+    let position = if a.group == b.group {
+        b.queue_position.cmp(&a.queue_position)
+    } else {
+        Ordering::Equal
+    };
+
+    position
+        .then_with(|| {
+            b.effective_priority(aging)
+                .cmp(&a.effective_priority(aging))
+        })
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use pretty_assertions::assert_eq;
+    use pueue_lib::settings::SchedulingPolicy;
+    use tempfile::TempDir;
+
+    use crate::daemon::{
+        internal_state::SharedState, network::message_handler::fixtures::*,
+        process_handler::spawn::get_next_task_id,
+    };
+
+    /// Create a state with two groups that compete for three daemon-wide slots.
+    /// Group `flood` has a lot of high priority tasks and already a running one.
+    /// Group `other` has a single queued task with default priority.
+    fn get_competing_state() -> (SharedState, Settings, TempDir) {
+        let (state, mut settings, tempdir) = get_state();
+        settings.daemon.max_running_tasks = Some(3);
+        {
+            let mut state = state.lock().unwrap();
+            for group in ["flood", "other"] {
+                // Unlimited slots, so only the daemon-wide limit applies.
+                state.create_group(group).parallel_tasks = 0;
+            }
+
+            let mut task = get_stub_task_in_group("0", "flood", StubStatus::Running);
+            task.priority = 10;
+            state.add_task(task);
+            for id in 1..4 {
+                let mut task = get_stub_task_in_group(&id.to_string(), "flood", StubStatus::Queued);
+                task.priority = 10;
+                state.add_task(task);
+            }
+            let task = get_stub_task_in_group("4", "other", StubStatus::Queued);
+            state.add_task(task);
+        }
+
+        (state, settings, tempdir)
+    }
+
+    #[test]
+    /// FIFO always prefers the high priority flood.
+    fn fifo_prefers_priority() {
+        let (state, settings, _tempdir) = get_competing_state();
+
+        let state = state.lock().unwrap();
+        assert_eq!(get_next_task_id(&settings, &state), Some(1));
+    }
+
+    #[test]
+    /// Fair share gives the other group a turn, as the flooding group already has a running task.
+    fn fair_share_between_groups() {
+        let (state, mut settings, _tempdir) = get_competing_state();
+        settings.daemon.scheduling_policy = SchedulingPolicy::FairShare;
+
+        let state = state.lock().unwrap();
+        assert_eq!(get_next_task_id(&settings, &state), Some(4));
+    }
+
+    #[test]
+    /// Groups with a higher weight may use a larger share of the running tasks.
+    fn fair_share_weights() {
+        let (state, mut settings, _tempdir) = get_competing_state();
+        settings.daemon.scheduling_policy = SchedulingPolicy::FairShare;
+        settings.daemon.max_running_tasks = None;
+
+        let mut state = state.lock().unwrap();
+        state.change_status(1, get_stub_task("1", StubStatus::Running).status);
+        let task = get_stub_task_in_group("5", "other", StubStatus::Running);
+        state.add_task(task);
+
+        // `flood` runs two tasks, `other` a single one.
+        assert_eq!(get_next_task_id(&settings, &state), Some(4));
+
+        // With a weight of 3, `flood` is entitled to three times as many tasks as `other`.
+        settings.daemon.group_weights.insert("flood".into(), 3);
+        assert_eq!(get_next_task_id(&settings, &state), Some(2));
+    }
+
+    #[test]
+    /// Round robin alternates between labels.
+    fn round_robin_between_labels() {
+        let (state, mut settings, _tempdir) = get_competing_state();
+        settings.daemon.scheduling_policy = SchedulingPolicy::RoundRobin;
+
+        let mut state = state.lock().unwrap();
+        for id in 0..4 {
+            state.task_mut(id).unwrap().label = Some("alice".into());
+        }
+        state.task_mut(4).unwrap().label = Some("bob".into());
+
+        assert_eq!(get_next_task_id(&settings, &state), Some(4));
+    }
+
+    #[test]
+    /// Tasks that wait for a long time overtake tasks with a higher static priority.
+    fn priority_aging() {
+        let (state, mut settings, _tempdir) = get_state();
+
+        let mut state = state.lock().unwrap();
+        // This task has been queued for five minutes.
+        let task = get_stub_task("0", StubStatus::Queued);
+        state.add_task(task);
+        // This task has just been queued.
+        let mut task = get_stub_task("1", StubStatus::Queued);
+        task.priority = 3;
+        task.status = TaskStatus::Queued {
+            enqueued_at: Local::now(),
+        };
+        state.add_task(task);
+        state.group_mut(PUEUE_DEFAULT_GROUP).unwrap().parallel_tasks = 0;
+
+        assert_eq!(get_next_task_id(&settings, &state), Some(1));
+
+        // With one point per minute, the old task has an effective priority of 5.
+        settings.daemon.priority_aging_minutes = Some(1);
+        assert_eq!(get_next_task_id(&settings, &state), Some(0));
+    }
+
+    #[test]
+    /// Tasks with the same concurrency key never run at the same time, even across groups.
+    fn concurrency_key() {
+        let (state, settings, _tempdir) = get_competing_state();
+
+        let mut state = state.lock().unwrap();
+        for id in [0, 1, 4] {
+            state.task_mut(id).unwrap().concurrency_key = Some("deploy".into());
+        }
+
+        // Task 0 holds the key, so both 1 and 4 have to wait.
+        assert_eq!(get_next_task_id(&settings, &state), Some(2));
+    }
+
+    #[test]
+    /// Tasks moved to the front of their group don't overtake higher priority tasks of other
+    /// groups.
+    fn queue_position_within_group() {
+        let (state, settings, _tempdir) = get_competing_state();
+
+        let mut state = state.lock().unwrap();
+        let task = state.task_mut(4).unwrap();
+        task.priority = 20;
+        let task = state.task_mut(3).unwrap();
+        task.queue_position = 1;
+        assert_eq!(get_next_task_id(&settings, &state), Some(4));
+
+        // Within its group, the moved task still goes first.
+        state.task_mut(4).unwrap().priority = 0;
+        assert_eq!(get_next_task_id(&settings, &state), Some(3));
+    }
+
+    #[test]
+    /// No task is started once the daemon-wide limit is reached.
+    fn daemon_wide_limit() {
+        let (state, mut settings, _tempdir) = get_competing_state();
+        settings.daemon.max_running_tasks = Some(1);
+
+        let state = state.lock().unwrap();
+        assert_eq!(get_next_task_id(&settings, &state), None);
+    }
+}
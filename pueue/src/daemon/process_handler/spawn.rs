@@ -4,21 +4,34 @@ use chrono::Local;
 use command_group::CommandGroup;
 use pueue_lib::{
     GroupStatus, Settings, Task, TaskResult, TaskStatus,
-    log::{create_log_file_handles, get_writable_log_file_handle},
+    log::{
+        create_log_file_handles, create_timestamp_file_handles, get_log_path, get_stderr_log_path,
+        get_writable_log_file_handle,
+    },
+    settings::LogSizePolicy,
+    task::TaskProcess,
 };
 
 use crate::{
-    daemon::{callbacks::spawn_callback, internal_state::state::LockedState},
+    daemon::{
+        callbacks::spawn_callback,
+        capture::{LogLimit, OutputRecorder},
+        internal_state::{children::TaskChild, state::LockedState},
+        process_handler::{log_size::log_size_limit, scheduling::select_next_task},
+        supervisor,
+    },
     internal_prelude::*,
     ok_or_shutdown,
-    process_helper::compile_shell_command,
+    process_helper::{
+        boot_id, compile_command_with_shell, compile_shell_command, process_start_time,
+    },
 };
 
 /// See if we can start a new queued task.
 pub fn spawn_new(settings: &Settings, state: &mut LockedState) {
     // Check whether a new task can be started.
     // Spawn tasks until we no longer have free slots available.
-    while let Some(id) = get_next_task_id(state) {
+    while let Some(id) = get_next_task_id(settings, state) {
         spawn_process(settings, state, id);
     }
 }
@@ -29,13 +42,26 @@ pub fn spawn_new(settings: &Settings, state: &mut LockedState) {
 /// - There are free slots in the task's group
 /// - The group is running
 /// - has all its dependencies in `Done` state
+/// - No other running task holds the same concurrency key
+/// - The daemon-wide limit of running tasks hasn't been reached yet
 ///
-/// Order at which tasks are picked (descending relevancy):
-/// - Task with highest priority first
-/// - Task with lowest ID first
-pub fn get_next_task_id(state: &LockedState) -> Option<usize> {
+/// The order at which tasks are picked is determined by the configured scheduling policy.
+/// Take a look at [select_next_task] for more info.
+pub fn get_next_task_id(settings: &Settings, state: &LockedState) -> Option<usize> {
+    // Don't start any new tasks, if the daemon-wide limit of running tasks is reached.
+    if let Some(max_running_tasks) = settings.daemon.max_running_tasks {
+        let running_tasks = state
+            .tasks()
+            .values()
+            .filter(|task| task.is_running() && !task.is_preempted())
+            .count();
+        if running_tasks >= max_running_tasks {
+            return None;
+        }
+    }
+
     // Get all tasks that could theoretically be started right now.
-    let mut potential_tasks: Vec<&Task> = state
+    let potential_tasks: Vec<&Task> = state
             .tasks()
             .iter()
             .filter(|(_, task)| matches!(task.status, TaskStatus::Queued {..}))
@@ -65,8 +91,14 @@ pub fn get_next_task_id(state: &LockedState) -> Option<usize> {
 
                 // Get the currently running tasks by looking at the actually running processes.
                 // They're sorted by group, which makes this quite convenient.
+                // Preempted tasks gave up their slot, which is why they aren't counted.
                 let running_tasks = match state.children.0.get(&task.group) {
-                    Some(children) => children.len(),
+                    Some(children) => children
+                        .values()
+                        .filter(|(task_id, _)| {
+                            !state.tasks().get(task_id).is_some_and(Task::is_preempted)
+                        })
+                        .count(),
                     None => {
                         error!(
                             "Got valid group {}, but no worker pool has been initialized. This is a bug!",
@@ -79,32 +111,40 @@ pub fn get_next_task_id(state: &LockedState) -> Option<usize> {
                 // Make sure there are free slots in the task's group
                 running_tasks < group.parallel_tasks
             })
-            .filter(|(_, task)| {
-                // Check whether all dependencies for this task are fulfilled.
-                task.dependencies
-                    .iter()
-                    .flat_map(|id| state.tasks().get(id))
-                    .all(|task| matches!(task.status, TaskStatus::Done{result: TaskResult::Success, ..}))
-            })
+            .filter(|(_, task)| dependencies_fulfilled(state, task))
+            .filter(|(_, task)| concurrency_key_available(state, task))
             .map(|(_, task)| {task})
             .collect();
 
-    // Order the tasks based on their priortiy and their task id.
-    // Tasks with higher priority go first.
-    // Tasks with the same priority are ordered by their id in ascending order, meaning that
-    // tasks with smaller id will be processed first.
-    potential_tasks.sort_by(|a, b| {
-        // If they have the same prio, decide the execution order by task_id!
-        if a.priority == b.priority {
-            return a.id.cmp(&b.id);
-        }
+    // Let the scheduling policy decide which of the tasks should be started first.
+    select_next_task(settings, state, potential_tasks).map(|task| task.id)
+}
+
+/// Check whether all dependencies of a task finished successfully.
+pub fn dependencies_fulfilled(state: &LockedState, task: &Task) -> bool {
+    task.dependencies
+        .iter()
+        .flat_map(|id| state.tasks().get(id))
+        .all(|task| {
+            matches!(
+                task.status,
+                TaskStatus::Done {
+                    result: TaskResult::Success,
+                    ..
+                }
+            )
+        })
+}
 
-        // Otherwise, let the priority decide.
-        b.priority.cmp(&a.priority)
-    });
+/// Check whether no running or paused task holds the concurrency key of the given task.
+pub fn concurrency_key_available(state: &LockedState, task: &Task) -> bool {
+    let Some(key) = &task.concurrency_key else {
+        return true;
+    };
 
-    // Return the id of the first task (if one has been found).
-    potential_tasks.first().map(|task| task.id)
+    !state.tasks().values().any(|other| {
+        other.id != task.id && other.is_running() && other.concurrency_key.as_ref() == Some(key)
+    })
 }
 
 /// Actually spawn a new sub process
@@ -133,7 +173,9 @@ pub fn spawn_process(settings: &Settings, state: &mut LockedState, task_id: usiz
 
     // Try to get the log file to which the output of the process will be written to.
     // Panic if this doesn't work! This is unrecoverable.
-    let (stdout_log, stderr_log) = match create_log_file_handles(task_id, &pueue_directory) {
+    let log_handles =
+        create_log_file_handles(task_id, &pueue_directory, settings.daemon.separate_stderr);
+    let (stdout_log, stderr_log) = match log_handles {
         Ok((out, err)) => (out, err),
         Err(err) => {
             panic!("Failed to create child log files: {err:?}");
@@ -141,18 +183,35 @@ pub fn spawn_process(settings: &Settings, state: &mut LockedState, task_id: usiz
     };
 
     // Get all necessary info for starting the task
-    let (command, path, group, mut envs) = {
+    let (command, path, group, mut envs, timestamps) = {
         let task = state.tasks().get(&task_id).unwrap();
         (
             task.command.clone(),
             task.path.clone(),
             task.group.clone(),
             task.envs.clone(),
+            task.timestamps || settings.daemon.timestamp_output,
         )
     };
 
-    // Build the shell command that should be executed.
-    let mut command = compile_shell_command(settings, &command);
+    // Apply the defaults of the task's group, if it's declared in the configuration file.
+    // The group's shell command takes precedence over the global one.
+    let mut command = match settings.daemon.groups.get(&group) {
+        Some(declared) => {
+            for (key, value) in declared.env_vars.iter() {
+                if envs.get(key).is_none() {
+                    envs.insert(key.clone(), value.clone());
+                }
+            }
+
+            let shell_command = declared
+                .shell_command
+                .as_ref()
+                .or(settings.daemon.shell_command.as_ref());
+            compile_command_with_shell(settings, shell_command.map(Vec::as_slice), &command)
+        }
+        None => compile_shell_command(settings, &command),
+    };
 
     // Determine the worker's id depending on the current group.
     // Inject that info into the environment.
@@ -170,18 +229,66 @@ pub fn spawn_process(settings: &Settings, state: &mut LockedState, task_id: usiz
         );
     }
 
+    // Run the task under a supervisor, so it survives restarts of the daemon.
+    let mut supervised = false;
+    if supervisor::enabled(settings) {
+        match supervisor::supervised_command(&command, task_id, &pueue_directory) {
+            Ok(supervisor) => {
+                command = supervisor;
+                supervised = true;
+            }
+            Err(err) => error!("Failed to run task {task_id} under a supervisor: {err:?}"),
+        }
+    }
+
+    // Timestamped output and output of logs that are truncated or rotated is written into pipes,
+    // which are recorded by the daemon.
+    // Supervised tasks must not depend on the daemon, which is why their output isn't recorded.
+    // Their logs can't be truncated or rotated, so the task handler kills them once their log
+    // exceeds its size limit.
+    let limit = log_size_limit(settings, &group)
+        .filter(|(_, policy)| *policy != LogSizePolicy::Kill)
+        .map(|(max_size, policy)| LogLimit {
+            max_size,
+            policy,
+            max_segments: settings.daemon.max_log_segments,
+        });
+    if timestamps && supervised {
+        warn!("The output of task {task_id} isn't timestamped, as it runs under a supervisor");
+    }
+    let (stdout, stderr, recorder) = if !supervised && (timestamps || limit.is_some()) {
+        let separate_stderr = settings.daemon.separate_stderr;
+        let paths = (
+            get_log_path(task_id, &pueue_directory),
+            get_stderr_log_path(task_id, &pueue_directory),
+        );
+        let mut recorder = OutputRecorder::new(paths, (stdout_log, stderr_log), separate_stderr);
+        if timestamps {
+            match create_timestamp_file_handles(task_id, &pueue_directory, separate_stderr) {
+                Ok(handles) => recorder = recorder.with_timestamps(handles),
+                Err(err) => error!("Failed to create timestamp files of task {task_id}: {err:?}"),
+            }
+        }
+        if let Some(limit) = limit {
+            recorder = recorder.with_limit(limit);
+        }
+        (Stdio::piped(), Stdio::piped(), Some(recorder))
+    } else {
+        (Stdio::from(stdout_log), Stdio::from(stderr_log), None)
+    };
+
     // Spawn the actual subprocess
     let spawned_command = command
         .current_dir(path)
         .stdin(Stdio::piped())
         .env_clear()
-        .envs(envs.clone())
-        .stdout(Stdio::from(stdout_log))
-        .stderr(Stdio::from(stderr_log))
+        .envs(envs.iter())
+        .stdout(stdout)
+        .stderr(stderr)
         .group_spawn();
 
     // Check if the task managed to spawn
-    let child = match spawned_command {
+    let mut child = match spawned_command {
         Ok(child) => child,
         Err(err) => {
             let error_msg = format!("Failed to spawn child {task_id} with err: {:?}", err);
@@ -203,7 +310,7 @@ pub fn spawn_process(settings: &Settings, state: &mut LockedState, task_id: usiz
 
             // Update all necessary fields on the task.
             let task = {
-                let task = state.tasks_mut().get_mut(&task_id).unwrap();
+                let task = state.task_mut(task_id).unwrap();
                 task.status = TaskStatus::Done {
                     enqueued_at,
                     start: Local::now(),
@@ -222,14 +329,37 @@ pub fn spawn_process(settings: &Settings, state: &mut LockedState, task_id: usiz
         }
     };
 
+    if supervised {
+        if let Err(err) = supervisor::record_pid(task_id, child.id(), &pueue_directory) {
+            error!("Task {task_id} cannot be re-adopted after a restart: {err:?}");
+        }
+    }
+
+    if let Some(recorder) = recorder {
+        let process = child.inner();
+        let capture = recorder.start(task_id, process.stdout.take(), process.stderr.take());
+        state.captures.insert(task_id, capture);
+    }
+
+    // The child is the leader of its own process group.
+    let process = TaskProcess {
+        pid: child.id(),
+        process_group: child.id(),
+        start_time: process_start_time(child.id()),
+        boot_id: boot_id(),
+    };
+
     // Save the process handle in our self.children datastructure.
-    state.children.add_child(&group, worker_id, task_id, child);
+    state
+        .children
+        .add_child(&group, worker_id, task_id, TaskChild::Spawned(child));
 
-    let task = state.tasks_mut().get_mut(&task_id).unwrap();
+    let task = state.task_mut(task_id).unwrap();
     task.status = TaskStatus::Running {
         enqueued_at,
         start: Local::now(),
     };
+    task.process = Some(process);
     // Overwrite the task's environment variables with the new ones, containing the
     // PUEUE_WORKER_ID and PUEUE_GROUP variables.
     task.envs = envs;
@@ -1,3 +1,4 @@
+use chrono::Local;
 use pueue_lib::{GroupStatus, Settings, TaskStatus, message::TaskSelection};
 
 use crate::{
@@ -35,7 +36,7 @@ pub fn start(settings: &Settings, state: &mut LockedState, tasks: TaskSelection)
         }
         TaskSelection::Group(group_name) => {
             // Ensure that a given group exists. (Might not happen due to concurrency)
-            let group = match state.groups_mut().get_mut(&group_name) {
+            let group = match state.group_mut(&group_name) {
                 Some(group) => group,
                 None => return,
             };
@@ -69,7 +70,7 @@ pub fn start(settings: &Settings, state: &mut LockedState, tasks: TaskSelection)
 }
 
 /// Send a start signal to a paused task to continue execution.
-fn continue_task(state: &mut LockedState, task_id: usize) {
+pub fn continue_task(state: &mut LockedState, task_id: usize) {
     // Task doesn't exist
     if !state.children.has_child(task_id) {
         return;
@@ -78,7 +79,7 @@ fn continue_task(state: &mut LockedState, task_id: usize) {
     // Encapsulate to prevent a duplicate borrow on `state`.
     let (enqueued_at, start) = {
         // Task is already done
-        let Some(task) = state.tasks_mut().get_mut(&task_id) else {
+        let Some(task) = state.task_mut(task_id) else {
             return;
         };
 
@@ -106,5 +107,12 @@ fn continue_task(state: &mut LockedState, task_id: usize) {
 
     if success {
         state.change_status(task_id, TaskStatus::Running { enqueued_at, start });
+
+        // If the task has been paused due to a preemption, that preemption is over now.
+        if let Some(task) = state.task_mut(task_id) {
+            if let Some(preemption) = task.preemptions.last_mut() {
+                preemption.resumed_at.get_or_insert_with(Local::now);
+            }
+        }
     }
 }
@@ -0,0 +1,173 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use pueue_lib::Settings;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{daemon::internal_state::SharedState, internal_prelude::*};
+
+/// The daemon's settings, which can be reloaded while the daemon is running.
+///
+/// Every component takes a snapshot via [SharedSettings::get] whenever it starts to handle
+/// something, which is why reloaded settings are picked up without a restart.
+#[derive(Clone, Debug)]
+pub struct SharedSettings {
+    settings: Arc<RwLock<Settings>>,
+    /// The configuration file and profile the daemon has been started with.
+    config_path: Option<PathBuf>,
+    profile: Option<String>,
+}
+
+impl SharedSettings {
+    pub fn new(settings: Settings, config_path: Option<PathBuf>, profile: Option<String>) -> Self {
+        SharedSettings {
+            settings: Arc::new(RwLock::new(settings)),
+            config_path,
+            profile,
+        }
+    }
+
+    /// Get a snapshot of the current settings.
+    pub fn get(&self) -> Settings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Re-read the configuration file including the active profile and swap the settings.
+    ///
+    /// The `shared` section contains things like the socket path and the pueue directory, which
+    /// cannot be changed while the daemon is running. Changes to it are ignored and reported.
+    ///
+    /// Returns a summary of all changes.
+    pub fn reload(&self, state: &SharedState) -> Result<String> {
+        let (mut new, found) =
+            Settings::read(&self.config_path).context("Failed to read configuration file.")?;
+        if !found {
+            bail!("Couldn't find a configuration file.");
+        }
+        if let Some(profile) = &self.profile {
+            new.load_profile(profile)?;
+        }
+        validate(&new)?;
+
+        let current = self.get();
+        let mut ignored = changed_fields("shared", &current.shared, &new.shared)?;
+        new.shared = current.shared.clone();
+        // The connection to NATS is only established on startup.
+        if new.daemon.nats_host != current.daemon.nats_host {
+            ignored.push("daemon.nats_host".into());
+            new.daemon.nats_host = current.daemon.nats_host.clone();
+        }
+        // The state backend is only opened on startup.
+        if new.daemon.state_backend != current.daemon.state_backend {
+            ignored.push("daemon.state_backend".into());
+            new.daemon.state_backend = current.daemon.state_backend;
+        }
+        let changed = changed_fields("daemon", &current.daemon, &new.daemon)?;
+
+        // Create all groups that have been declared in the meantime.
+        {
+            let mut state = state.lock().unwrap();
+            state.reconcile_groups(&new);
+            let groups: Vec<String> = state.groups().keys().cloned().collect();
+            for name in groups {
+                state.children.0.entry(name).or_default();
+            }
+            state.save(&new).context("Failed to save state.")?;
+        }
+
+        *self.settings.write().unwrap() = new;
+
+        let mut summary = if changed.is_empty() {
+            "Settings reloaded, nothing changed.".to_string()
+        } else {
+            info!("Reloaded settings, changed: {}", changed.join(", "));
+            format!("Settings reloaded, changed: {}", changed.join(", "))
+        };
+        if !ignored.is_empty() {
+            warn!(
+                "Ignored settings that require a restart: {}",
+                ignored.join(", ")
+            );
+            summary.push_str(&format!(
+                "\nThese settings only take effect after a restart of the daemon: {}",
+                ignored.join(", ")
+            ));
+        }
+
+        Ok(summary)
+    }
+}
+
+/// Reject settings that would only fail once they're used.
+fn validate(settings: &Settings) -> Result<()> {
+    if let Some(command) = &settings.daemon.shell_command {
+        if command.is_empty() {
+            bail!("daemon.shell_command must not be empty.");
+        }
+    }
+
+    for (name, group) in settings.daemon.groups.iter() {
+        if let Some(command) = &group.shell_command {
+            if command.is_empty() {
+                bail!("daemon.groups.{name}.shell_command must not be empty.");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare two settings sections and return the names of all top-level fields that differ.
+fn changed_fields<T: Serialize>(section: &str, old: &T, new: &T) -> Result<Vec<String>> {
+    let (Value::Object(old), Value::Object(new)) =
+        (serde_json::to_value(old)?, serde_json::to_value(new)?)
+    else {
+        bail!("Settings section {section} isn't an object.");
+    };
+
+    Ok(new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, _)| format!("{section}.{key}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use pueue_lib::settings::GroupSettings;
+
+    use super::*;
+
+    #[test]
+    /// Only fields that actually changed are reported.
+    fn detect_changed_fields() -> Result<()> {
+        let old = Settings::default();
+        let mut new = old.clone();
+        new.daemon.callback = Some("echo done".into());
+        new.daemon
+            .groups
+            .insert("remote".into(), GroupSettings::default());
+
+        assert_eq!(
+            changed_fields("daemon", &old.daemon, &new.daemon)?,
+            vec!["daemon.callback".to_string(), "daemon.groups".to_string()]
+        );
+        assert!(changed_fields("shared", &old.shared, &new.shared)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    /// Empty shell commands are rejected.
+    fn reject_empty_shell_command() {
+        let mut settings = Settings::default();
+        assert!(validate(&settings).is_ok());
+
+        settings.daemon.shell_command = Some(Vec::new());
+        assert!(validate(&settings).is_err());
+    }
+}
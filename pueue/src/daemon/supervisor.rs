@@ -0,0 +1,436 @@
+//! Tasks can run under a small supervisor process, which is the `pueued` binary itself started
+//! with the hidden `--supervise` flag.
+//!
+//! The supervisor is the leader of the task's process group. It spawns the actual task, waits
+//! for it and records its result in a file inside the pueue directory. As the supervisor isn't
+//! bound to the daemon, running tasks survive restarts and upgrades of the daemon.
+//! The next daemon re-adopts all supervisors via their pid file.
+//!
+//! Pid files might be stale, e.g. after a reboot. That's why the start time and the command line
+//! of a supervisor are verified, before it's adopted or signaled.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use pueue_lib::{Settings, TaskResult, TaskStatus};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daemon::internal_state::{children::TaskChild, state::LockedState},
+    internal_prelude::*,
+    process_helper::{boot_id, is_same_process, process_command_line, process_start_time},
+};
+
+/// The hidden flag that starts `pueued` as a supervisor.
+const SUPERVISE_FLAG: &str = "--supervise";
+
+/// The supervisor process of a task, as recorded in its pid file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SupervisorProcess {
+    pub pid: u32,
+    /// The start time of the process, which tells it apart from processes that reuse its pid.
+    #[serde(default)]
+    start_time: Option<u64>,
+    /// The boot of the system the supervisor has been started in.
+    #[serde(default)]
+    boot_id: Option<String>,
+}
+
+impl SupervisorProcess {
+    /// Record the identity of a supervisor that has just been spawned.
+    pub fn new(pid: u32) -> Self {
+        SupervisorProcess {
+            pid,
+            start_time: process_start_time(pid),
+            boot_id: boot_id(),
+        }
+    }
+
+    /// Whether the recorded supervisor is still running.
+    ///
+    /// The process with the recorded pid might be a completely unrelated process by now, so its
+    /// start time and, if available, its command line have to match as well.
+    pub fn is_alive(&self) -> bool {
+        is_same_process(self.pid, self.start_time, self.boot_id.as_deref())
+            && process_command_line(self.pid)
+                .is_none_or(|args| args.iter().any(|arg| arg == SUPERVISE_FLAG))
+    }
+}
+
+/// The directory that contains the pid and result files of all supervisors.
+pub fn supervisor_directory(pueue_directory: &Path) -> PathBuf {
+    pueue_directory.join("supervisors")
+}
+
+fn pid_path(task_id: usize, pueue_directory: &Path) -> PathBuf {
+    supervisor_directory(pueue_directory).join(format!("{task_id}.pid"))
+}
+
+fn result_path(task_id: usize, pueue_directory: &Path) -> PathBuf {
+    supervisor_directory(pueue_directory).join(format!("{task_id}.result"))
+}
+
+/// Whether new tasks should be started under a supervisor.
+pub fn enabled(settings: &Settings) -> bool {
+    settings.daemon.supervise_tasks && cfg!(unix)
+}
+
+/// Wrap a compiled task command, so it's run by a supervisor.
+///
+/// Leftovers of a previous run of the same task are removed.
+pub fn supervised_command(
+    command: &Command,
+    task_id: usize,
+    pueue_directory: &Path,
+) -> Result<Command> {
+    create_directory(pueue_directory)?;
+    clean(task_id, pueue_directory);
+
+    let executable = std::env::current_exe().context("Failed to find the pueued executable")?;
+    let mut supervisor = Command::new(executable);
+    supervisor
+        .arg(SUPERVISE_FLAG)
+        .arg(result_path(task_id, pueue_directory))
+        .arg("--")
+        .arg(command.get_program())
+        .args(command.get_args());
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            supervisor.env(key, value);
+        }
+    }
+
+    Ok(supervisor)
+}
+
+fn create_directory(pueue_directory: &Path) -> Result<()> {
+    let directory = supervisor_directory(pueue_directory);
+    if !directory.exists() {
+        std::fs::create_dir_all(&directory).context(format!(
+            "Failed to create supervisor directory {directory:?}"
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Remember the supervisor of a task, so it can be re-adopted after a restart.
+pub fn record_pid(task_id: usize, pid: u32, pueue_directory: &Path) -> Result<()> {
+    let path = pid_path(task_id, pueue_directory);
+    let supervisor = serde_json::to_string(&SupervisorProcess::new(pid))?;
+    std::fs::write(&path, supervisor).context(format!("Failed to write pid file {path:?}"))
+}
+
+/// The recorded supervisor of a task, if the task has been started under a supervisor.
+///
+/// Pid files that can't be read or parsed are an error.
+pub fn read_pid(task_id: usize, pueue_directory: &Path) -> Result<Option<SupervisorProcess>> {
+    let path = pid_path(task_id, pueue_directory);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content =
+        std::fs::read_to_string(&path).context(format!("Failed to read pid file {path:?}"))?;
+    let supervisor =
+        serde_json::from_str(&content).context(format!("Invalid pid file {path:?}"))?;
+
+    Ok(Some(supervisor))
+}
+
+/// The supervisor of a task, if it's still running or has recorded a result.
+///
+/// Stale pid files, whose supervisor is gone without recording a result, are removed.
+pub fn find_supervisor(task_id: usize, pueue_directory: &Path) -> Option<SupervisorProcess> {
+    let supervisor = match read_pid(task_id, pueue_directory) {
+        Ok(supervisor) => supervisor?,
+        Err(err) => {
+            error!("Failed to read supervisor of task {task_id}: {err:#}");
+            return None;
+        }
+    };
+    if supervisor.is_alive() || result_path(task_id, pueue_directory).exists() {
+        return Some(supervisor);
+    }
+
+    info!(
+        "Removing stale pid file of task {task_id}, supervisor {} is gone",
+        supervisor.pid
+    );
+    clean(task_id, pueue_directory);
+    None
+}
+
+/// Whether the supervisor of a task is done.
+pub fn is_finished(task_id: usize, supervisor: &SupervisorProcess, pueue_directory: &Path) -> bool {
+    result_path(task_id, pueue_directory).exists() || !supervisor.is_alive()
+}
+
+/// Read the result that has been recorded by a task's supervisor.
+/// The supervisor's files are removed afterwards.
+pub fn take_result(task_id: usize, pueue_directory: &Path) -> Option<TaskResult> {
+    let result = std::fs::read_to_string(result_path(task_id, pueue_directory))
+        .ok()
+        .and_then(|content| match serde_json::from_str(&content) {
+            Ok(result) => Some(result),
+            Err(err) => {
+                warn!("Failed to read recorded result of task {task_id}: {err}");
+                None
+            }
+        });
+    clean(task_id, pueue_directory);
+
+    result
+}
+
+/// Remove all files of a task's supervisor.
+pub fn clean(task_id: usize, pueue_directory: &Path) {
+    for path in [
+        pid_path(task_id, pueue_directory),
+        result_path(task_id, pueue_directory),
+    ] {
+        if path.exists() {
+            if let Err(err) = std::fs::remove_file(&path) {
+                warn!("Failed to remove supervisor file {path:?}: {err}");
+            }
+        }
+    }
+}
+
+/// Re-adopt all running and paused tasks, whose supervisor survived a restart of the daemon.
+///
+/// Supervisors that finished while the daemon was down are adopted as well. They're detected as
+/// finished right away and their recorded result is used.
+pub fn adopt_tasks(settings: &Settings, state: &mut LockedState) {
+    let pueue_directory = settings.shared.pueue_directory();
+    let tasks: Vec<(usize, String, Option<usize>)> = state
+        .tasks()
+        .values()
+        .filter(|task| {
+            matches!(
+                task.status,
+                TaskStatus::Running { .. } | TaskStatus::Paused { .. }
+            )
+        })
+        .map(|task| {
+            let worker_id = task
+                .envs
+                .get("PUEUE_WORKER_ID")
+                .and_then(|id| id.parse().ok());
+            (task.id, task.group.clone(), worker_id)
+        })
+        .collect();
+
+    for (task_id, group, worker_id) in tasks {
+        let Some(supervisor) = find_supervisor(task_id, &pueue_directory) else {
+            continue;
+        };
+
+        let pool = state.children.0.entry(group.clone()).or_default();
+        let worker_id = match worker_id {
+            Some(worker_id) if !pool.contains_key(&worker_id) => worker_id,
+            _ => state.children.get_next_group_worker(&group),
+        };
+        info!(
+            "Re-adopted task {task_id} with supervisor {}",
+            supervisor.pid
+        );
+        state
+            .children
+            .add_child(&group, worker_id, task_id, TaskChild::Adopted(supervisor));
+    }
+}
+
+/// Let go of all tasks that run under a supervisor without killing them.
+/// They survive the shutdown of the daemon and are re-adopted on the next start.
+pub fn detach_tasks(settings: &Settings, state: &mut LockedState) {
+    let pueue_directory = settings.shared.pueue_directory();
+    for pool in state.children.0.values_mut() {
+        pool.retain(|_, (task_id, child)| match child {
+            TaskChild::Adopted(_) => false,
+            TaskChild::Spawned(_) => match read_pid(*task_id, &pueue_directory) {
+                Ok(supervisor) => supervisor.is_none(),
+                Err(err) => {
+                    error!("Failed to detach task {task_id}: {err:#}");
+                    true
+                }
+            },
+        });
+    }
+}
+
+/// The entry point of a supervisor process.
+///
+/// Run the given command, wait for it to finish and record its result in the given file.
+/// Termination signals are ignored, as they're meant for the task in our process group.
+/// The supervisor exits with the task's exit code.
+pub async fn supervise(result_path: PathBuf, command: Vec<String>) -> Result<()> {
+    let Some((program, args)) = command.split_first() else {
+        bail!("The supervisor needs a command to run.");
+    };
+
+    // Signal handlers, contrary to ignored signals, aren't inherited by the spawned task.
+    #[cfg(unix)]
+    let _signals = {
+        use tokio::signal::unix::{SignalKind, signal};
+        [
+            signal(SignalKind::terminate())?,
+            signal(SignalKind::interrupt())?,
+            signal(SignalKind::hangup())?,
+        ]
+    };
+
+    let mut child = Command::new(program)
+        .args(args)
+        .spawn()
+        .context(format!("Failed to spawn {program}"))?;
+    let status = tokio::task::spawn_blocking(move || child.wait()).await??;
+
+    let result = match status.code() {
+        Some(0) => TaskResult::Success,
+        Some(code) => TaskResult::Failed(code),
+        None => TaskResult::Killed,
+    };
+
+    // Write the result atomically, as the daemon might read it at any time.
+    let temp = result_path.with_extension("partial");
+    std::fs::write(&temp, serde_json::to_string(&result)?)
+        .context(format!("Failed to write result to {temp:?}"))?;
+    std::fs::rename(&temp, &result_path)
+        .context(format!("Failed to write result to {result_path:?}"))?;
+
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::process::Child;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::daemon::network::message_handler::fixtures::*;
+
+    /// Spawn a process that looks like a supervisor.
+    /// The trailing `true` prevents the shell from replacing itself with `sleep`.
+    fn spawn_supervisor() -> Result<Child> {
+        let child = Command::new("sh")
+            .args(["-c", "sleep 60; true", SUPERVISE_FLAG])
+            .spawn()?;
+
+        // The command line of the child is only replaced once it has been executed.
+        for _ in 0..100 {
+            if process_command_line(child.id())
+                .is_some_and(|args| args.iter().any(|arg| arg == SUPERVISE_FLAG))
+            {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        Ok(child)
+    }
+
+    #[test]
+    /// Running tasks with a supervisor are adopted with their old worker id.
+    /// Tasks without a supervisor are left alone.
+    fn adopt_supervised_tasks() -> Result<()> {
+        let (state, settings, _tempdir) = get_state();
+        let pueue_directory = settings.shared.pueue_directory();
+        create_directory(&pueue_directory)?;
+        let mut child = spawn_supervisor()?;
+
+        let mut state = state.lock().unwrap();
+        state
+            .children
+            .0
+            .insert(PUEUE_DEFAULT_GROUP.into(), Default::default());
+        for id in 0..2 {
+            let mut task = get_stub_task(&id.to_string(), StubStatus::Running);
+            task.envs.insert("PUEUE_WORKER_ID".into(), "3".into());
+            state.add_task(task);
+        }
+        record_pid(1, child.id(), &pueue_directory)?;
+
+        adopt_tasks(&settings, &mut state);
+
+        let pool = state.children.0.get(PUEUE_DEFAULT_GROUP).unwrap();
+        assert_eq!(pool.len(), 1);
+        assert!(matches!(pool.get(&3), Some((1, TaskChild::Adopted(_)))));
+
+        child.kill()?;
+        child.wait()?;
+        Ok(())
+    }
+
+    #[test]
+    /// Pid files of processes that aren't the recorded supervisor are stale and get removed.
+    fn remove_stale_pid_files() -> Result<()> {
+        let (_, settings, _tempdir) = get_state();
+        let pueue_directory = settings.shared.pueue_directory();
+        create_directory(&pueue_directory)?;
+        let mut child = spawn_supervisor()?;
+
+        record_pid(0, child.id(), &pueue_directory)?;
+        assert!(find_supervisor(0, &pueue_directory).is_some());
+
+        // The pid has been reused by a process that has been started at another time.
+        let mut supervisor = read_pid(0, &pueue_directory)?.unwrap();
+        supervisor.start_time = supervisor.start_time.map(|time| time + 1);
+        assert!(!supervisor.is_alive());
+
+        // The pid belongs to a process that isn't a supervisor.
+        record_pid(0, std::process::id(), &pueue_directory)?;
+        assert_eq!(find_supervisor(0, &pueue_directory), None);
+        assert_eq!(read_pid(0, &pueue_directory)?, None);
+
+        child.kill()?;
+        child.wait()?;
+        Ok(())
+    }
+
+    #[test]
+    /// Pid files that don't contain a recorded supervisor are rejected.
+    fn reject_invalid_pid_files() -> Result<()> {
+        let (_, settings, _tempdir) = get_state();
+        let pueue_directory = settings.shared.pueue_directory();
+        create_directory(&pueue_directory)?;
+
+        std::fs::write(pid_path(0, &pueue_directory), "1234")?;
+        assert!(read_pid(0, &pueue_directory).is_err());
+        assert_eq!(find_supervisor(0, &pueue_directory), None);
+
+        Ok(())
+    }
+
+    #[test]
+    /// The recorded result of a supervisor is used and all of its files are removed.
+    fn take_recorded_result() -> Result<()> {
+        let (_, settings, _tempdir) = get_state();
+        let pueue_directory = settings.shared.pueue_directory();
+        create_directory(&pueue_directory)?;
+        let mut child = spawn_supervisor()?;
+
+        record_pid(0, child.id(), &pueue_directory)?;
+        let supervisor = read_pid(0, &pueue_directory)?.unwrap();
+        assert!(!is_finished(0, &supervisor, &pueue_directory));
+
+        std::fs::write(
+            result_path(0, &pueue_directory),
+            serde_json::to_string(&TaskResult::Failed(3))?,
+        )?;
+        assert!(is_finished(0, &supervisor, &pueue_directory));
+
+        assert_eq!(
+            take_result(0, &pueue_directory),
+            Some(TaskResult::Failed(3))
+        );
+        assert_eq!(read_pid(0, &pueue_directory)?, None);
+        assert_eq!(take_result(0, &pueue_directory), None);
+
+        child.kill()?;
+        child.wait()?;
+        Ok(())
+    }
+}
@@ -9,7 +9,16 @@ use crate::{
         internal_state::{SharedState, children::Children, state::LockedState},
         network::socket::socket_cleanup,
         pid::cleanup_pid_file,
-        process_handler::{finish::handle_finished_tasks, spawn::spawn_new},
+        process_handler::{
+            compress::{compress_finished_logs, remove_orphaned_compressions},
+            finish::handle_finished_tasks,
+            log_size::limit_log_sizes,
+            preempt::{preempt_tasks, resume_preempted_tasks},
+            retention::apply_retention_policies,
+            spawn::spawn_new,
+        },
+        reload::SharedSettings,
+        supervisor,
     },
     internal_prelude::*,
     ok_or_shutdown,
@@ -19,15 +28,20 @@ use crate::{
 /// In here a few things happen:
 ///
 /// - Handle finished tasks, i.e. cleanup processes, update statuses.
+/// - Enforce the size limit of the log files of running tasks.
+/// - Compress the logs of finished tasks in the background.
+/// - Remove old finished tasks according to the retention policies.
 /// - Callback handling logic. This is rather uncritical.
 /// - Enqueue any stashed processes which are ready for being queued.
 /// - Ensure tasks with dependencies have no failed ancestors
+/// - Resume preempted tasks and preempt low priority tasks in favor of urgent ones.
 /// - Handle shutdown logic (graceful & not graceful).
 /// - If the client requested a reset: reset the state if all children have been killed and handled.
 /// - Check whether we can spawn new tasks.
 ///
 /// We also wait for 300ms to prevent this loop from running hot.
-pub async fn run(state: SharedState, settings: Settings) -> Result<()> {
+/// The settings are fetched anew on every iteration, so reloaded settings take effect right away.
+pub async fn run(state: SharedState, shared_settings: SharedSettings) -> Result<()> {
     // Initialize the subprocess management structure.
     {
         let mut state = state.lock().unwrap();
@@ -36,14 +50,23 @@ pub async fn run(state: SharedState, settings: Settings) -> Result<()> {
             pools.insert(group.clone(), BTreeMap::new());
         }
         state.children = Children(pools);
+
+        // Re-adopt tasks that kept running under their supervisor while the daemon was down.
+        supervisor::adopt_tasks(&shared_settings.get(), &mut state);
+
+        remove_orphaned_compressions(&shared_settings.get().shared.pueue_directory(), &state);
     }
 
     loop {
         'mutex_block: {
+            let settings = shared_settings.get();
             let mut state = state.lock().unwrap();
 
             check_callbacks(&mut state);
             handle_finished_tasks(&settings, &mut state);
+            limit_log_sizes(&settings, &mut state);
+            compress_finished_logs(&settings, &mut state);
+            apply_retention_policies(&settings, &mut state);
 
             // Check if we're in shutdown.
             // If all tasks are killed, we do some cleanup and exit.
@@ -56,6 +79,8 @@ pub async fn run(state: SharedState, settings: Settings) -> Result<()> {
             handle_group_resets(&settings, &mut state);
             enqueue_delayed_tasks(&settings, &mut state);
             check_failed_dependencies(&settings, &mut state);
+            resume_preempted_tasks(&settings, &mut state);
+            preempt_tasks(&settings, &mut state);
             spawn_new(&settings, &mut state);
         }
 
@@ -115,7 +140,7 @@ fn handle_group_resets(_settings: &Settings, state: &mut LockedState) {
         state.tasks_mut().retain(|_id, task| &task.group != name);
 
         // Restart the group, now that it's devoid of tasks.
-        if let Some(group) = state.groups_mut().get_mut(name) {
+        if let Some(group) = state.group_mut(name) {
             group.status = GroupStatus::Running;
         }
     }
@@ -124,24 +149,28 @@ fn handle_group_resets(_settings: &Settings, state: &mut LockedState) {
 /// As time passes, some delayed tasks may need to be enqueued.
 /// Gather all stashed tasks and enqueue them if it is after the task's enqueue_at
 fn enqueue_delayed_tasks(settings: &Settings, state: &mut LockedState) {
-    let mut changed = false;
-    for (_, task) in state.tasks_mut().iter_mut() {
-        if let TaskStatus::Stashed {
-            enqueue_at: Some(time),
-        } = task.status
-        {
-            if time <= Local::now() {
-                info!("Enqueuing delayed task : {}", task.id);
+    let due_ids: Vec<usize> = state
+        .tasks()
+        .iter()
+        .filter(|(_, task)| {
+            matches!(task.status, TaskStatus::Stashed {
+                enqueue_at: Some(time),
+            } if time <= Local::now())
+        })
+        .map(|(id, _)| *id)
+        .collect();
 
-                task.status = TaskStatus::Queued {
-                    enqueued_at: Local::now(),
-                };
-                changed = true;
-            }
-        }
+    for id in due_ids.iter() {
+        info!("Enqueuing delayed task : {id}");
+        state.change_status(
+            *id,
+            TaskStatus::Queued {
+                enqueued_at: Local::now(),
+            },
+        );
     }
     // Save the state if a task has been enqueued
-    if changed {
+    if !due_ids.is_empty() {
         ok_or_shutdown!(settings, state, state.save(settings));
     }
 }
@@ -193,7 +222,7 @@ fn check_failed_dependencies(settings: &Settings, state: &mut LockedState) {
 
         // Update the task and return a clone to build the callback.
         let task = {
-            let task = state.tasks_mut().get_mut(&id).unwrap();
+            let task = state.task_mut(id).unwrap();
             // We know that this must be true, but we have to check anyway.
             let TaskStatus::Queued { enqueued_at } = task.status else {
                 continue;
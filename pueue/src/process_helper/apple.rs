@@ -1,6 +1,23 @@
-use libproc::libproc::{proc_pid, task_info};
+use libproc::libproc::{bsd_info::BSDInfo, proc_pid, task_info};
 
 /// Check, whether a specific process exists or not
 pub fn process_exists(pid: u32) -> bool {
     proc_pid::pidinfo::<task_info::TaskInfo>(pid.try_into().unwrap(), 0).is_ok()
 }
+
+/// The start time of a process in microseconds since the unix epoch.
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let info = proc_pid::pidinfo::<BSDInfo>(pid.try_into().ok()?, 0).ok()?;
+    Some(info.pbi_start_tvsec * 1_000_000 + info.pbi_start_tvusec)
+}
+
+/// The command line of a process isn't available on this platform.
+pub fn process_command_line(_pid: u32) -> Option<Vec<String>> {
+    None
+}
+
+/// The start time of processes is an absolute time on this platform, which is why it doesn't
+/// need the boot id to identify a process.
+pub fn boot_id() -> Option<String> {
+    None
+}
@@ -5,6 +5,21 @@ pub fn process_exists(pid: u32) -> bool {
     return Path::new(&format!("/proc/{}", pid)).is_dir();
 }
 
+/// The start time of processes isn't available on this platform.
+pub fn process_start_time(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// The command line of processes isn't available on this platform.
+pub fn process_command_line(_pid: u32) -> Option<Vec<String>> {
+    None
+}
+
+/// The boot id isn't available on this platform.
+pub fn boot_id() -> Option<String> {
+    None
+}
+
 #[cfg(test)]
 pub mod tests {
     /// Get all processes in a process group
@@ -10,3 +10,21 @@ pub fn process_exists(pid: u32) -> bool {
         },
     }
 }
+
+/// The start time of a process in clock ticks since the system has been booted.
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let process = process::Process::new(pid.try_into().ok()?).ok()?;
+    process.stat().ok().map(|stat| stat.starttime)
+}
+
+/// The command line a process has been started with.
+pub fn process_command_line(pid: u32) -> Option<Vec<String>> {
+    let process = process::Process::new(pid.try_into().ok()?).ok()?;
+    process.cmdline().ok()
+}
+
+/// The id of the current boot of the system.
+pub fn boot_id() -> Option<String> {
+    let id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+    Some(id.trim().to_string())
+}
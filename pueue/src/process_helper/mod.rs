@@ -14,7 +14,7 @@ use crate::internal_prelude::*;
 #[cfg(unix)]
 mod unix;
 #[cfg(unix)]
-use command_group::Signal;
+pub use command_group::Signal;
 
 #[cfg(unix)]
 pub use self::unix::*;
@@ -59,9 +59,33 @@ pub fn signal_from_internal(signal: InternalSignal) -> Signal {
     }
 }
 
+/// Check whether a process that has been recorded earlier is still alive.
+///
+/// Process ids are reused by the system, especially after a reboot. That's why the start time of
+/// the process and the boot id of the system have to match as well, as far as they're known on
+/// this platform.
+pub fn is_same_process(pid: u32, start_time: Option<u64>, boot_id: Option<&str>) -> bool {
+    if boot_id.is_some_and(|id| platform::boot_id().as_deref() != Some(id)) {
+        return false;
+    }
+
+    process_exists(pid)
+        && start_time.is_none_or(|start_time| process_start_time(pid) == Some(start_time))
+}
+
 /// Take a platform specific shell command and insert the actual task command via templating.
 pub fn compile_shell_command(settings: &Settings, command: &str) -> Command {
-    let shell_command = get_shell_command(settings);
+    compile_command_with_shell(settings, settings.daemon.shell_command.as_deref(), command)
+}
+
+/// Same as [compile_shell_command], but with an explicit shell command instead of the one from
+/// the settings. The platform's default shell is used, if no shell command is given.
+pub fn compile_command_with_shell(
+    settings: &Settings,
+    shell_command: Option<&[String]>,
+    command: &str,
+) -> Command {
+    let shell_command = get_shell_command(shell_command);
 
     let mut handlebars = handlebars::Handlebars::new();
     handlebars.set_strict_mode(true);
@@ -4,12 +4,12 @@
 // type.
 use color_eyre::Result;
 use command_group::{GroupChild, Signal, UnixChildExt};
-use pueue_lib::Settings;
+use nix::{errno::Errno, sys::signal::killpg, unistd::Pid};
 
 use crate::internal_prelude::*;
 
-pub fn get_shell_command(settings: &Settings) -> Vec<String> {
-    let Some(ref shell_command) = settings.daemon.shell_command else {
+pub fn get_shell_command(shell_command: Option<&[String]>) -> Vec<String> {
+    let Some(shell_command) = shell_command else {
         return vec![
             "sh".into(),
             "-c".into(),
@@ -17,7 +17,7 @@ pub fn get_shell_command(settings: &Settings) -> Vec<String> {
         ];
     };
 
-    shell_command.clone()
+    shell_command.to_vec()
 }
 
 /// Send a signal to one of Pueue's child process group handle.
@@ -26,6 +26,22 @@ pub fn send_signal_to_child(child: &mut GroupChild, signal: Signal) -> Result<()
     Ok(())
 }
 
+/// Send a signal to the process group of a process that isn't a child of the daemon.
+/// The process must be the leader of its process group.
+pub fn send_signal_to_process_group(pid: u32, signal: Signal) -> Result<()> {
+    killpg(Pid::from_raw(pid as i32), signal)?;
+    Ok(())
+}
+
+/// Check whether any process of the given process group is still alive.
+pub fn process_group_exists(process_group: u32) -> bool {
+    // Signal `0` only checks whether the process group exists.
+    !matches!(
+        killpg(Pid::from_raw(process_group as i32), None),
+        Err(Errno::ESRCH)
+    )
+}
+
 /// This is a helper function to safely kill a child process group.
 /// Its purpose is to properly kill all processes and prevent any dangling processes.
 pub fn kill_child(task_id: usize, child: &mut GroupChild) -> std::io::Result<()> {
@@ -48,6 +64,7 @@ mod tests {
     use command_group::CommandGroup;
     use libproc::processes::{ProcFilter, pids_by_type};
     use pretty_assertions::assert_eq;
+    use pueue_lib::Settings;
 
     use super::*;
     use crate::process_helper::{compile_shell_command, process_exists};
@@ -2,7 +2,6 @@
 // As soon as it's obvious that this is code is intended to be exposed to library users, we have to
 // go ahead and replace any `anyhow` usage by proper error handling via our own Error type.
 use command_group::GroupChild;
-use pueue_lib::Settings;
 use winapi::{
     shared::{minwindef::FALSE, ntdef::NULL},
     um::{
@@ -28,8 +27,8 @@ pub enum Signal {
     SIGSTOP,
 }
 
-pub fn get_shell_command(settings: &Settings) -> Vec<String> {
-    let Some(ref shell_command) = settings.daemon.shell_command else {
+pub fn get_shell_command(shell_command: Option<&[String]>) -> Vec<String> {
+    let Some(shell_command) = shell_command else {
         // Chain two `powershell` commands, one that sets the output encoding to utf8 and then the
         // user provided one.
         return vec![
@@ -40,7 +39,7 @@ pub fn get_shell_command(settings: &Settings) -> Vec<String> {
         ];
     };
 
-    shell_command.clone()
+    shell_command.to_vec()
 }
 
 /// Send a signal to a windows process.
@@ -73,6 +72,16 @@ pub fn send_signal_to_child(child: &mut GroupChild, signal: Signal) -> Result<()
     Ok(())
 }
 
+/// Processes that aren't children of the daemon are only known on Unix.
+pub fn send_signal_to_process_group(_pid: u32, _signal: Signal) -> Result<()> {
+    bail!("Sending signals to process groups isn't supported on Windows.");
+}
+
+/// There are no process groups on Windows, so only the main process is checked.
+pub fn process_group_exists(process_group: u32) -> bool {
+    process_exists(process_group)
+}
+
 /// Kill a child process
 pub fn kill_child(task_id: usize, child: &mut GroupChild) -> std::io::Result<()> {
     match child.kill() {
@@ -223,6 +232,21 @@ fn resume_thread(tid: u32) {
     }
 }
 
+/// The start time of processes isn't available on this platform.
+pub fn process_start_time(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// The command line of processes isn't available on this platform.
+pub fn process_command_line(_pid: u32) -> Option<Vec<String>> {
+    None
+}
+
+/// The boot id isn't available on this platform.
+pub fn boot_id() -> Option<String> {
+    None
+}
+
 /// Assert that certain process id no longer exists
 pub fn process_exists(pid: u32) -> bool {
     unsafe {
@@ -255,6 +279,7 @@ mod test {
     use std::{process::Command, thread::sleep, time::Duration};
 
     use command_group::CommandGroup;
+    use pueue_lib::Settings;
 
     use super::*;
     use crate::process_helper::compile_shell_command;
@@ -0,0 +1,81 @@
+use std::{collections::BTreeMap, io::Read};
+
+use flate2::read::GzDecoder;
+use pueue_lib::task::Task;
+use serde_json::Value;
+use tar::Archive;
+
+use crate::{client::helper::*, internal_prelude::*};
+
+/// Read all files of a gzipped tar archive into a map of their paths and contents.
+fn read_bundle(path: &std::path::Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut archive = Archive::new(GzDecoder::new(std::fs::File::open(path)?));
+
+    let mut files = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.insert(path, content);
+    }
+
+    Ok(files)
+}
+
+/// Calling `pueue bundle` writes the logs and a manifest of the selected tasks to an archive.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn bundle() -> Result<()> {
+    let daemon = daemon().await?;
+    let shared = &daemon.settings.shared;
+
+    assert_success(add_task(shared, "echo success").await?);
+    assert_success(add_task(shared, "echo failure && false").await?);
+    wait_for_task_condition(shared, 1, Task::is_done).await?;
+
+    let path = daemon.tempdir.path().join("run.tar.gz");
+    let output = run_client_command(
+        shared,
+        &["bundle", "--status", "failed", "-o", path.to_str().unwrap()],
+    )?;
+    assert!(output.status.success(), "Bundle command failed: {output:?}");
+    // Only the bundle itself is left behind.
+    let files: Vec<_> = std::fs::read_dir(daemon.tempdir.path())?
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(".pueue-bundle")
+        })
+        .collect();
+    assert!(
+        files.is_empty(),
+        "Temporary bundle files are left: {files:?}"
+    );
+
+    let files = read_bundle(&path)?;
+    let paths: Vec<&String> = files.keys().collect();
+    assert_eq!(paths, vec!["logs/1.log", "manifest.json"]);
+    assert_eq!(
+        String::from_utf8_lossy(&files["logs/1.log"]).trim(),
+        "failure"
+    );
+
+    let manifest: Value = serde_json::from_slice(&files["manifest.json"])?;
+    assert!(manifest["daemon_version"].is_string());
+    let tasks = manifest["tasks"].as_array().unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0]["id"], 1);
+    assert_eq!(tasks[0]["command"], "echo failure && false");
+    // Only the names of environment variables are included.
+    assert!(
+        tasks[0]["envs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(Value::is_string)
+    );
+
+    Ok(())
+}
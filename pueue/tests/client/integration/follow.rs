@@ -1,4 +1,4 @@
-use pueue_lib::task::Task;
+use pueue_lib::{GroupStatus, state::PUEUE_DEFAULT_GROUP, task::Task};
 use rstest::rstest;
 
 use crate::{client::helper::*, internal_prelude::*};
@@ -102,6 +102,55 @@ async fn wait_for_task(#[case] read_local_logs: bool) -> Result<()> {
     Ok(())
 }
 
+/// Test that the output of several tasks is interleaved and prefixed with their id and label.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn multiple_tasks() -> Result<()> {
+    let daemon = daemon().await?;
+    let shared = &daemon.settings.shared;
+
+    assert_success(add_task(shared, "sleep 1 && echo first").await?);
+    run_client_command(shared, &["add", "--label", "second", "echo second"])?;
+    wait_for_task_condition(shared, 0, Task::is_running).await?;
+
+    // Follow both tasks until they finished.
+    let output = run_client_command(shared, &["follow", "0", "1"])?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines: Vec<&str> = stdout.lines().collect();
+    lines.sort();
+
+    assert_eq!(lines, vec!["[0] first", "[1|second] second"]);
+
+    Ok(())
+}
+
+/// Following a group only waits for tasks, that are going to be started.
+/// Stashed tasks and tasks of paused groups don't keep the stream open.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn group_without_startable_tasks() -> Result<()> {
+    let daemon = daemon().await?;
+    let shared = &daemon.settings.shared;
+
+    run_client_command(shared, &["add", "--stashed", "echo stashed"])?;
+    assert_success(add_task(shared, "sleep 1 && echo running").await?);
+    wait_for_task_condition(shared, 1, Task::is_running).await?;
+
+    let output = run_client_command(shared, &["follow", "--group", PUEUE_DEFAULT_GROUP])?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim(), "[1] running");
+
+    // Queued tasks of a paused group won't be started either.
+    run_client_command(shared, &["group", "add", "paused"])?;
+    wait_for_group(shared, "paused").await?;
+    run_client_command(shared, &["pause", "--group", "paused"])?;
+    wait_for_group_status(shared, "paused", GroupStatus::Paused).await?;
+    run_client_command(shared, &["add", "--group", "paused", "echo paused"])?;
+
+    let output = run_client_command(shared, &["follow", "--group", "paused"])?;
+    assert!(output.stdout.is_empty());
+
+    Ok(())
+}
+
 /// Fail when following a non-existing task
 #[rstest]
 #[case(true)]
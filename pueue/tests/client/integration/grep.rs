@@ -0,0 +1,72 @@
+use pueue_lib::{Task, message::*, settings::Shared};
+
+use crate::{client::helper::*, internal_prelude::*};
+
+/// Add a task, that prints a few lines, and wait for it to finish.
+async fn add_finished_task(shared: &Shared) -> Result<()> {
+    run_client_command(
+        shared,
+        &[
+            "add",
+            "--label",
+            "numbers",
+            "--",
+            "printf 'one\\ntwo\\nthree\\nfour\\nfive\\nsix\\n'",
+        ],
+    )?;
+    wait_for_task_condition(shared, 0, Task::is_done).await?;
+
+    Ok(())
+}
+
+/// Search the logs of all tasks and print the matches like `grep -n`.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn default() -> Result<()> {
+    let daemon = daemon().await?;
+    let shared = &daemon.settings.shared;
+    add_finished_task(shared).await?;
+
+    let output = run_client_command(shared, &["grep", "t[wh]"])?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "Task 0 (numbers):\n2:two\n3:three\n");
+
+    // Context lines are printed as well, while non-consecutive blocks are separated.
+    let output = run_client_command(shared, &["grep", "--context", "1", "one|six"])?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "Task 0 (numbers):\n1:one\n2-two\n--\n5-five\n6:six\n"
+    );
+
+    // Tasks without matches aren't printed at all.
+    let output = run_client_command(shared, &["grep", "seven"])?;
+    assert!(output.stdout.is_empty());
+
+    Ok(())
+}
+
+/// The matching lines can be printed as json.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn json() -> Result<()> {
+    let daemon = daemon().await?;
+    let shared = &daemon.settings.shared;
+    add_finished_task(shared).await?;
+
+    let output = run_client_command(shared, &["grep", "--json", "two"])?;
+    let responses: Vec<GrepTaskResponse> = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        responses,
+        vec![GrepTaskResponse {
+            task_id: 0,
+            label: Some("numbers".to_string()),
+            lines: vec![GrepLine {
+                line_number: 2,
+                text: "two".to_string(),
+                is_match: true,
+                stream: None,
+            }],
+        }]
+    );
+
+    Ok(())
+}
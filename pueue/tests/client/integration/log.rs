@@ -134,7 +134,7 @@ async fn json() -> Result<()> {
     let mut state = get_state(shared).await?;
     let original_task = state.tasks.get_mut(&0).unwrap();
     // Clean the environment variables, as they aren't transmitted when calling `log`.
-    original_task.envs = HashMap::new();
+    original_task.envs = HashMap::new().into();
 
     let task_log = task_logs.get_mut(&0).expect("Expected one task log");
     assert_eq!(
@@ -148,3 +148,34 @@ async fn json() -> Result<()> {
 
     Ok(())
 }
+
+/// Calling `pueue log --ndjson` prints one json object per task and line.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn ndjson() -> Result<()> {
+    let daemon = daemon().await?;
+    let shared = &daemon.settings.shared;
+
+    // Add two tasks and wait until they finish.
+    assert_success(add_task(shared, "echo first").await?);
+    assert_success(add_task(shared, "echo second").await?);
+    wait_for_task_condition(shared, 1, Task::is_done).await?;
+
+    let output = run_client_command(shared, &["log", "--ndjson"])?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let task_logs = stdout
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<TaskLog>(line)
+                .context(format!("Failed to deserialize json task: \n{line}"))
+        })
+        .collect::<Result<Vec<TaskLog>>>()?;
+
+    let logs: Vec<(usize, &str)> = task_logs
+        .iter()
+        .map(|task_log| (task_log.task.id, task_log.output.as_str()))
+        .collect();
+    assert_eq!(logs, vec![(0, "first"), (1, "second")]);
+
+    Ok(())
+}
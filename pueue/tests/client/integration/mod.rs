@@ -1,8 +1,10 @@
+mod bundle;
 mod completions;
 mod configuration;
 mod edit;
 mod env;
 mod follow;
+mod grep;
 mod group;
 mod log;
 mod restart;
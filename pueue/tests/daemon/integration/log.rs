@@ -3,7 +3,12 @@ use std::{
     path::Path,
 };
 
-use pueue_lib::{Task, message::*};
+use pueue_lib::{
+    Task,
+    log::{get_compressed_log_path, get_log_path},
+    message::*,
+    task::LogStream,
+};
 use tempfile::TempDir;
 
 use crate::{helper::*, internal_prelude::*};
@@ -98,6 +103,11 @@ async fn test_partial_log() -> Result<()> {
         tasks: TaskSelection::TaskIds(vec![0]),
         send_logs: true,
         lines: Some(5),
+        stream: None,
+        timestamps: false,
+        since: None,
+        bytes: None,
+        streamed: false,
     };
     let response = send_request(shared, Request::Log(log_message)).await?;
     let logs = match response {
@@ -135,6 +145,11 @@ async fn test_correct_log_order() -> Result<()> {
         tasks: TaskSelection::TaskIds(vec![0]),
         send_logs: true,
         lines: None,
+        stream: None,
+        timestamps: false,
+        since: None,
+        bytes: None,
+        streamed: false,
     };
     let response = send_request(shared, Request::Log(log_message)).await?;
     let logs = match response {
@@ -178,6 +193,11 @@ async fn logs_of_group() -> Result<()> {
         tasks: TaskSelection::Group("test_2".to_string()),
         send_logs: true,
         lines: None,
+        stream: None,
+        timestamps: false,
+        since: None,
+        bytes: None,
+        streamed: false,
     };
     let response = send_request(shared, message).await?;
     let logs = match response {
@@ -212,6 +232,11 @@ async fn logs_for_all() -> Result<()> {
         tasks: TaskSelection::All,
         send_logs: true,
         lines: None,
+        stream: None,
+        timestamps: false,
+        since: None,
+        bytes: None,
+        streamed: false,
     };
     let response = send_request(shared, message).await?;
     let logs = match response {
@@ -223,3 +248,179 @@ async fn logs_for_all() -> Result<()> {
 
     Ok(())
 }
+
+/// Make sure that separately captured streams can be requested on their own.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn separate_streams() -> Result<()> {
+    let (mut settings, tempdir) = daemon_base_setup()?;
+    settings.daemon.separate_stderr = true;
+    settings
+        .save(&Some(tempdir.path().join("pueue.yml")))
+        .context("Couldn't write pueue config to temporary directory")?;
+    let daemon = daemon_with_settings(settings, tempdir).await?;
+    let shared = &daemon.settings.shared;
+
+    let command = "echo 'test' && echo 'error' >&2 && echo 'test'";
+    assert_success(add_task(shared, command).await?);
+    wait_for_task_condition(shared, 0, Task::is_done).await?;
+
+    // Without a selected stream, both streams are sent separately.
+    let message = LogRequest {
+        tasks: TaskSelection::TaskIds(vec![0]),
+        send_logs: true,
+        lines: None,
+        stream: None,
+        timestamps: false,
+        since: None,
+        bytes: None,
+        streamed: false,
+    };
+    let Response::Log(mut logs) = send_request(shared, message).await? else {
+        bail!("Didn't get log response");
+    };
+    let log = logs.remove(&0).unwrap();
+    assert_eq!(decompress_log(log.output.unwrap())?, "test\ntest\n");
+    assert_eq!(decompress_log(log.stderr.unwrap())?, "error\n");
+
+    // Only request stderr.
+    let message = LogRequest {
+        tasks: TaskSelection::TaskIds(vec![0]),
+        send_logs: true,
+        lines: None,
+        stream: Some(LogStream::Stderr),
+        timestamps: false,
+        since: None,
+        bytes: None,
+        streamed: false,
+    };
+    let Response::Log(mut logs) = send_request(shared, message).await? else {
+        bail!("Didn't get log response");
+    };
+    let log = logs.remove(&0).unwrap();
+    assert_eq!(decompress_log(log.output.unwrap())?, "error\n");
+    assert!(log.stderr.is_none());
+
+    Ok(())
+}
+
+/// Make sure that timestamped output can be rendered with timestamps and filtered by time.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn timestamped_output() -> Result<()> {
+    let (mut settings, tempdir) = daemon_base_setup()?;
+    settings.daemon.timestamp_output = true;
+    settings
+        .save(&Some(tempdir.path().join("pueue.yml")))
+        .context("Couldn't write pueue config to temporary directory")?;
+    let daemon = daemon_with_settings(settings, tempdir).await?;
+    let shared = &daemon.settings.shared;
+
+    assert_success(add_task(shared, "echo 'test' && echo 'line'").await?);
+    wait_for_task_condition(shared, 0, Task::is_done).await?;
+
+    // The log file itself stays untouched.
+    let output = get_task_log(shared, 0, None).await?;
+    assert_eq!(output, "test\nline\n");
+
+    // Each line is prefixed with its timestamp.
+    let message = LogRequest {
+        tasks: TaskSelection::TaskIds(vec![0]),
+        send_logs: true,
+        lines: None,
+        stream: None,
+        timestamps: true,
+        since: None,
+        bytes: None,
+        streamed: false,
+    };
+    let Response::Log(mut logs) = send_request(shared, message).await? else {
+        bail!("Didn't get log response");
+    };
+    let output = decompress_log(logs.remove(&0).unwrap().output.unwrap())?;
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2, "Expected two lines, got: {output}");
+    assert!(lines[0].starts_with('[') && lines[0].ends_with("] test"));
+    assert!(lines[1].starts_with('[') && lines[1].ends_with("] line"));
+
+    // Lines that have been printed before `since` are dropped.
+    let message = LogRequest {
+        tasks: TaskSelection::TaskIds(vec![0]),
+        send_logs: true,
+        lines: None,
+        stream: None,
+        timestamps: false,
+        since: Some(chrono::Local::now()),
+        bytes: None,
+        streamed: false,
+    };
+    let Response::Log(mut logs) = send_request(shared, message).await? else {
+        bail!("Didn't get log response");
+    };
+    let output = decompress_log(logs.remove(&0).unwrap().output.unwrap())?;
+    assert_eq!(output, "");
+
+    Ok(())
+}
+
+/// Make sure that the logs of finished tasks are compressed and can still be read.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn compressed_logs() -> Result<()> {
+    let (mut settings, tempdir) = daemon_base_setup()?;
+    settings.daemon.compress_finished_logs = true;
+    settings
+        .save(&Some(tempdir.path().join("pueue.yml")))
+        .context("Couldn't write pueue config to temporary directory")?;
+    let daemon = daemon_with_settings(settings, tempdir).await?;
+    let shared = &daemon.settings.shared;
+
+    assert_success(add_task(shared, "echo 'test' && echo 'line'").await?);
+    wait_for_task_condition(shared, 0, Task::is_done).await?;
+
+    // Wait until the log has been compressed in the background.
+    let log_path = get_log_path(0, &shared.pueue_directory());
+    let mut tries = 0;
+    while log_path.exists() {
+        if tries > 100 {
+            bail!("The log of task 0 hasn't been compressed.");
+        }
+        tries += 1;
+        sleep_ms(50).await;
+    }
+    assert!(get_compressed_log_path(&log_path).exists());
+
+    let output = get_task_log(shared, 0, None).await?;
+    assert_eq!(output, "test\nline\n");
+    let output = get_task_log(shared, 0, Some(1)).await?;
+    assert_eq!(output, "line\n");
+
+    Ok(())
+}
+
+/// Make sure that a byte range of a task's output can be fetched.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn byte_range() -> Result<()> {
+    let daemon = daemon().await?;
+    let shared = &daemon.settings.shared;
+
+    assert_success(add_task(shared, "echo first && echo second").await?);
+    wait_for_task_condition(shared, 0, Task::is_done).await?;
+
+    let message = LogRequest {
+        tasks: TaskSelection::TaskIds(vec![0]),
+        send_logs: true,
+        lines: None,
+        stream: None,
+        timestamps: false,
+        since: None,
+        bytes: Some(6..100),
+        streamed: false,
+    };
+    let Response::Log(mut logs) = send_request(shared, message).await? else {
+        bail!("Didn't get log response");
+    };
+    let log = logs.remove(&0).unwrap();
+    assert_eq!(decompress_log(log.output.unwrap())?, "second\n");
+    assert_eq!(log.bytes, Some(6..13));
+    assert!(!log.output_complete);
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use pueue_lib::{
+    Settings, Task, TaskResult, TaskStatus,
+    log::{get_log_path, get_log_segment_paths},
+    settings::LogSizePolicy,
+};
+
+use crate::{helper::*, internal_prelude::*};
+
+/// Start a daemon, whose tasks' logs are limited to 1000 bytes.
+async fn daemon_with_log_limit(policy: LogSizePolicy) -> Result<PueueDaemon> {
+    let (mut settings, tempdir) = daemon_base_setup()?;
+    settings.daemon.max_log_size = Some(1000);
+    settings.daemon.log_size_policy = policy;
+    settings.daemon.max_log_segments = 2;
+    settings
+        .save(&Some(tempdir.path().join("pueue.yml")))
+        .context("Couldn't write pueue config to temporary directory")?;
+
+    daemon_with_settings(settings, tempdir).await
+}
+
+/// Wait until a running task printed all numbers of `seq 1 2000`.
+async fn wait_for_sequence(settings: &Settings, task_id: usize) -> Result<String> {
+    wait_for_task_condition(&settings.shared, task_id, Task::is_running).await?;
+    let mut tries = 0;
+    loop {
+        let output = get_task_log(&settings.shared, task_id, None).await?;
+        if output.ends_with("\n2000\n") {
+            return Ok(output);
+        }
+        if tries > 100 {
+            bail!("Task {task_id} didn't print the whole sequence, got: {output}");
+        }
+        tries += 1;
+        sleep_ms(50).await;
+    }
+}
+
+/// Make sure that the output ends with an uninterrupted sequence of numbers up to 2000.
+/// The first line is skipped, as it might have been cut in half.
+fn assert_sequence(output: &str) {
+    let numbers: Vec<usize> = output
+        .lines()
+        .skip(1)
+        .map(|line| line.parse().unwrap())
+        .collect();
+    assert!(!numbers.is_empty(), "Expected some output, got: {output}");
+    let first = numbers[0];
+    assert_eq!(numbers, (first..=2000).collect::<Vec<_>>());
+}
+
+/// The log of a running task is truncated to half of its size limit, without losing any of the
+/// output that's printed in the meantime.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn truncate_running_task() -> Result<()> {
+    let daemon = daemon_with_log_limit(LogSizePolicy::Truncate).await?;
+    let shared = &daemon.settings.shared;
+
+    assert_success(add_task(shared, "seq 1 2000 && sleep 60").await?);
+    let output = wait_for_sequence(&daemon.settings, 0).await?;
+    assert!(get_task(shared, 0).await?.is_running());
+
+    let log_path = get_log_path(0, &shared.pueue_directory());
+    assert!(std::fs::metadata(&log_path)?.len() <= 1000);
+    assert!(output.len() <= 1000);
+    assert_sequence(&output);
+
+    Ok(())
+}
+
+/// The log of a running task is rotated into segments, while its whole recent output can still
+/// be read.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn rotate_running_task() -> Result<()> {
+    let daemon = daemon_with_log_limit(LogSizePolicy::Rotate).await?;
+    let shared = &daemon.settings.shared;
+
+    assert_success(add_task(shared, "seq 1 2000 && sleep 60").await?);
+    let output = wait_for_sequence(&daemon.settings, 0).await?;
+    assert!(get_task(shared, 0).await?.is_running());
+
+    // Only the most recent segments are kept.
+    let log_path = get_log_path(0, &shared.pueue_directory());
+    assert_eq!(get_log_segment_paths(&log_path).len(), 2);
+    assert!(std::fs::metadata(&log_path)?.len() <= 1000);
+    // The log and both segments are read as a single log.
+    assert!(output.len() > 1000);
+    assert_sequence(&output);
+
+    Ok(())
+}
+
+/// A running task is killed, once its log exceeds the size limit.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn kill_running_task() -> Result<()> {
+    let daemon = daemon_with_log_limit(LogSizePolicy::Kill).await?;
+    let shared = &daemon.settings.shared;
+
+    assert_success(add_task(shared, "seq 1 2000 && sleep 60").await?);
+    let task = wait_for_task_condition(shared, 0, Task::is_done).await?;
+    assert!(matches!(
+        task.status,
+        TaskStatus::Done {
+            result: TaskResult::Killed,
+            ..
+        }
+    ));
+
+    Ok(())
+}
@@ -8,6 +8,7 @@ mod environment_variables;
 mod group;
 mod kill;
 mod log;
+mod log_size;
 mod parallel_tasks;
 mod pause;
 mod priority;
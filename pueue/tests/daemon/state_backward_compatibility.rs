@@ -1,7 +1,7 @@
 use std::{fs::File, io::prelude::*};
 
 use pueue::daemon::internal_state::state::InternalState;
-use pueue_lib::Settings;
+use pueue_lib::{Settings, settings::StateBackend};
 use tempfile::TempDir;
 
 use crate::internal_prelude::*;
@@ -29,7 +29,7 @@ fn test_restore_from_old_state() -> Result<()> {
     file.write_all(old_state.as_bytes())?;
 
     let mut settings = Settings::default();
-    settings.shared.pueue_directory = Some(temp_path);
+    settings.shared.pueue_directory = Some(temp_path.clone());
     debug!("{settings:#?}");
 
     let state =
@@ -37,5 +37,45 @@ fn test_restore_from_old_state() -> Result<()> {
 
     assert!(state.is_some());
 
+    // The unversioned state has been backed up before it's overwritten by the migrated state.
+    assert!(temp_path.join("state.json.v0.backup").exists());
+
+    Ok(())
+}
+
+/// The old v4.0.0 state is migrated into a new SQLite database,
+/// once the SQLite state backend is enabled.
+#[test]
+fn test_migrate_old_state_to_sqlite() -> Result<()> {
+    let old_state = include_str!("data/v4.0.0_state.json");
+
+    let temp_dir = TempDir::new()?;
+    let temp_path = temp_dir.path().to_path_buf();
+
+    let temp_state_path = temp_path.join("state.json");
+    let mut file = File::create(&temp_state_path)?;
+    file.write_all(old_state.as_bytes())?;
+
+    let mut settings = Settings::default();
+    settings.shared.pueue_directory = Some(temp_path.clone());
+    settings.daemon.state_backend = StateBackend::Sqlite;
+
+    let state =
+        InternalState::restore_state(&settings).context("Failed to migrate state in test")?;
+    assert!(state.is_some());
+
+    // The old file has been moved out of the way and the database has been created.
+    assert!(!temp_state_path.exists());
+    assert!(temp_path.join("state.json.migrated").exists());
+    assert!(temp_path.join("state.sqlite").exists());
+
+    // The next restore reads from the database.
+    let restored =
+        InternalState::restore_state(&settings).context("Failed to restore state in test")?;
+    assert_eq!(
+        restored.map(|state| state.inner.tasks.len()),
+        state.map(|state| state.inner.tasks.len())
+    );
+
     Ok(())
 }
@@ -107,6 +107,11 @@ pub async fn assert_worker_envs(
             tasks: TaskSelection::TaskIds(vec![task_id]),
             send_logs: true,
             lines: None,
+            stream: None,
+            timestamps: false,
+            since: None,
+            bytes: None,
+            streamed: false,
         },
     )
     .await?;
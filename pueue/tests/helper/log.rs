@@ -24,6 +24,11 @@ pub async fn get_task_log(shared: &Shared, task_id: usize, lines: Option<usize>)
         tasks: TaskSelection::TaskIds(vec![task_id]),
         send_logs: true,
         lines,
+        stream: None,
+        timestamps: false,
+        since: None,
+        bytes: None,
+        streamed: false,
     };
     let response = send_request(shared, message).await?;
 
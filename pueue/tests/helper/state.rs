@@ -6,7 +6,7 @@ use super::*;
 pub async fn get_state(shared: &Shared) -> Result<Box<State>> {
     let response = send_request(shared, Request::Status).await?;
     match response {
-        Response::Status(state) => Ok(state),
+        Response::Status(status) => Ok(Box::new(status.state)),
         _ => bail!("Didn't get status response in get_state"),
     }
 }
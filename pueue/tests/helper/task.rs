@@ -15,6 +15,7 @@ pub fn create_add_message<C: ToString>(shared: &Shared, command: C) -> AddReques
     AddRequest {
         command: command.to_string(),
         path: shared.pueue_directory(),
+        default_path: false,
         envs: HashMap::from_iter(vars()),
         start_immediately: false,
         stashed: false,
@@ -23,6 +24,10 @@ pub fn create_add_message<C: ToString>(shared: &Shared, command: C) -> AddReques
         dependencies: Vec::new(),
         priority: None,
         label: None,
+        restartable: false,
+        concurrency_key: None,
+        supersede: false,
+        timestamps: false,
     }
 }
 
@@ -2,13 +2,15 @@
 use std::{
     fs::{File, remove_file},
     io::{self, Read, SeekFrom, prelude::*},
+    ops::Range,
     path::{Path, PathBuf},
 };
 
+use chrono::{DateTime, Local};
 use rev_buf_reader::RevBufReader;
 use snap::write::FrameEncoder;
 
-use crate::{error::Error, internal_prelude::*};
+use crate::{error::Error, internal_prelude::*, task::LogStream};
 
 /// Get the path to the log file of a task.
 pub fn get_log_path(task_id: usize, pueue_dir: &Path) -> PathBuf {
@@ -16,26 +18,138 @@ pub fn get_log_path(task_id: usize, pueue_dir: &Path) -> PathBuf {
     task_log_dir.join(format!("{task_id}.log"))
 }
 
+/// Get the path to the separate stderr log file of a task. \
+/// This file only exists, if the task's output streams are captured separately.
+pub fn get_stderr_log_path(task_id: usize, pueue_dir: &Path) -> PathBuf {
+    let task_log_dir = pueue_dir.join("task_logs");
+    task_log_dir.join(format!("{task_id}.stderr.log"))
+}
+
+/// Whether the stdout and stderr of a task have been captured into separate files.
+pub fn has_separate_streams(task_id: usize, pueue_dir: &Path) -> bool {
+    log_exists(&get_stderr_log_path(task_id, pueue_dir))
+}
+
+/// Get the path to the compressed version of a log file. \
+/// The logs of finished tasks are compressed, if `compress_finished_logs` is enabled.
+pub fn get_compressed_log_path(log_path: &Path) -> PathBuf {
+    log_path.with_extension("log.sz")
+}
+
+/// Get the path to the file, that a log is compressed into, before it replaces the log.
+pub fn get_partial_compressed_log_path(log_path: &Path) -> PathBuf {
+    log_path.with_extension("log.sz.partial")
+}
+
+/// Whether a log file exists, either as is or in its compressed form.
+pub fn log_exists(log_path: &Path) -> bool {
+    log_path.exists() || get_compressed_log_path(log_path).exists()
+}
+
+/// Get the path to the log file that contains the given stream of a task.
+///
+/// `None` selects the main log file. That's the task's whole output, or only its stdout if the
+/// streams are captured separately.
+/// Selecting a specific stream fails, if both streams have been captured into the same file.
+pub fn get_stream_log_path(
+    task_id: usize,
+    pueue_dir: &Path,
+    stream: Option<LogStream>,
+) -> Result<PathBuf, Error> {
+    let Some(stream) = stream else {
+        return Ok(get_log_path(task_id, pueue_dir));
+    };
+
+    if !has_separate_streams(task_id, pueue_dir) {
+        return Err(Error::Generic(format!(
+            "The output streams of task {task_id} haven't been captured separately."
+        )));
+    }
+
+    match stream {
+        LogStream::Stdout => Ok(get_log_path(task_id, pueue_dir)),
+        LogStream::Stderr => Ok(get_stderr_log_path(task_id, pueue_dir)),
+    }
+}
+
+/// Get the paths of all log files that contain the given stream of a task.
+///
+/// `None` selects the task's whole output, which is spread across two files, if the streams
+/// are captured separately.
+pub fn get_log_paths(
+    task_id: usize,
+    pueue_dir: &Path,
+    stream: Option<LogStream>,
+) -> Result<Vec<PathBuf>, Error> {
+    if stream.is_none() && has_separate_streams(task_id, pueue_dir) {
+        return Ok(vec![
+            get_log_path(task_id, pueue_dir),
+            get_stderr_log_path(task_id, pueue_dir),
+        ]);
+    }
+
+    Ok(vec![get_stream_log_path(task_id, pueue_dir, stream)?])
+}
+
 /// Create and return the two file handles for the `(stdout, stderr)` log file of a task.
-/// These are two handles to the same file.
-pub fn create_log_file_handles(task_id: usize, pueue_dir: &Path) -> Result<(File, File), Error> {
+///
+/// If `separate_streams` is `false`, these are two handles to the same file.
+/// Otherwise stderr is written to its own file.
+pub fn create_log_file_handles(
+    task_id: usize,
+    pueue_dir: &Path,
+    separate_streams: bool,
+) -> Result<(File, File), Error> {
+    // Remove the timestamps and rotated segments of a previous run of this task.
+    remove_log_sidecars(task_id, pueue_dir)?;
+
     let log_path = get_log_path(task_id, pueue_dir);
-    let stdout_handle = File::create(&log_path)
+    let stdout_handle = create_appendable_file(&log_path)
         .map_err(|err| Error::IoPathError(log_path, "getting stdout handle", err))?;
-    let stderr_handle = stdout_handle
-        .try_clone()
-        .map_err(|err| Error::IoError("cloning stderr handle".to_string(), err))?;
+
+    let stderr_path = get_stderr_log_path(task_id, pueue_dir);
+    let stderr_handle = if separate_streams {
+        create_appendable_file(&stderr_path)
+            .map_err(|err| Error::IoPathError(stderr_path, "getting stderr handle", err))?
+    } else {
+        // Remove the stderr file of a previous run of this task.
+        if stderr_path.exists() {
+            remove_file(&stderr_path)
+                .map_err(|err| Error::IoPathError(stderr_path, "removing stderr file", err))?;
+        }
+        stdout_handle
+            .try_clone()
+            .map_err(|err| Error::IoError("cloning stderr handle".to_string(), err))?
+    };
 
     Ok((stdout_handle, stderr_handle))
 }
 
-/// Return the file handle for the log file of a task.
-pub fn get_log_file_handle(task_id: usize, pueue_dir: &Path) -> Result<File, Error> {
-    let path = get_log_path(task_id, pueue_dir);
-    let handle = File::open(&path)
-        .map_err(|err| Error::IoPathError(path, "getting log file handle", err))?;
+/// Create an empty file, which is only written to at its end.
+///
+/// Log files must be opened in append mode, as they may be truncated by the daemon while the
+/// task is writing to them. Otherwise, the task would continue to write at its old position.
+fn create_appendable_file(path: &Path) -> io::Result<File> {
+    if path.exists() {
+        remove_file(path)?;
+    }
+    File::options().append(true).create(true).open(path)
+}
 
-    Ok(handle)
+/// Return a reader for the log file of a task.
+/// Take a look at [LogReader] for how rotated and compressed logs are handled.
+pub fn get_log_file_handle(task_id: usize, pueue_dir: &Path) -> Result<LogReader, Error> {
+    LogReader::open(&get_log_path(task_id, pueue_dir))
+}
+
+/// Return a reader for the log file that contains the given stream of a task.
+/// Take a look at [get_stream_log_path] for how the stream is selected.
+pub fn get_stream_log_file_handle(
+    task_id: usize,
+    pueue_dir: &Path,
+    stream: Option<LogStream>,
+) -> Result<LogReader, Error> {
+    LogReader::open(&get_stream_log_path(task_id, pueue_dir, stream)?)
 }
 
 /// Return the file handle for the log file of a task.
@@ -57,10 +171,22 @@ pub fn clean_log_handles(task_id: usize, pueue_dir: &Path) {
             error!("Failed to remove stdout file for task {task_id} with error {err:?}");
         };
     }
+
+    let path = get_stderr_log_path(task_id, pueue_dir);
+    if path.exists() {
+        if let Err(err) = remove_file(path) {
+            error!("Failed to remove stderr file for task {task_id} with error {err:?}");
+        };
+    }
+
+    if let Err(err) = remove_log_sidecars(task_id, pueue_dir) {
+        error!("Failed to remove log sidecar files of task {task_id} with error {err:?}");
+    }
 }
 
 /// Return the output of a task. \
 /// Task output is compressed using [snap] to save some memory and bandwidth.
+/// `stream` selects the log file, take a look at [get_stream_log_path] for more info.
 /// Return type is `(Vec<u8>, bool)`
 /// - `Vec<u8>` the compressed task output.
 /// - `bool` Whether the full task's output has been read. `false` indicate that the log output has
@@ -69,8 +195,9 @@ pub fn read_and_compress_log_file(
     task_id: usize,
     pueue_dir: &Path,
     lines: Option<usize>,
+    stream: Option<LogStream>,
 ) -> Result<(Vec<u8>, bool), Error> {
-    let mut file = get_log_file_handle(task_id, pueue_dir)?;
+    let mut file = get_stream_log_file_handle(task_id, pueue_dir, stream)?;
 
     let mut content = Vec::new();
 
@@ -94,6 +221,106 @@ pub fn read_and_compress_log_file(
     Ok((content, output_complete))
 }
 
+/// Return a byte range of a task's output. \
+/// The output is compressed using [snap], just like in [read_and_compress_log_file].
+/// The range is given in offsets of the task's whole output, take a look at [LogReader::offset].
+/// Return type is `(Vec<u8>, Range<u64>, bool)`
+/// - `Vec<u8>` the compressed task output.
+/// - `Range<u64>` the range that has actually been read. This is limited to the output that's
+///   still around.
+/// - `bool` Whether the range contains the task's whole output, that's still around.
+pub fn read_and_compress_log_range(
+    task_id: usize,
+    pueue_dir: &Path,
+    stream: Option<LogStream>,
+    range: Range<u64>,
+) -> Result<(Vec<u8>, Range<u64>, bool), Error> {
+    let mut file = get_stream_log_file_handle(task_id, pueue_dir, stream)?;
+    let first = file.offset();
+    let last = first + file.size()?;
+
+    file.seek_to_offset(range.start);
+    let start = file.offset();
+    let length = range.end.saturating_sub(start);
+
+    let mut content = Vec::new();
+    let read = {
+        let mut compressor = FrameEncoder::new(&mut content);
+        io::copy(&mut file.by_ref().take(length), &mut compressor)
+            .map_err(|err| Error::IoError("compressing log output".to_string(), err))?
+    };
+    let end = start + read;
+
+    Ok((content, start..end, start <= first && end >= last))
+}
+
+/// Read the output of a task, while the recorded timestamps are used to filter and render it. \
+/// Take a look at [TimestampReader::render] for how the output is rendered.
+///
+/// `lines` limits the output to the last lines, after lines before `since` have been dropped.
+/// Return type is `(String, bool)`
+/// - `String` the rendered task output.
+/// - `bool` Whether the full task's output has been read.
+pub fn read_timestamped_log_file(
+    task_id: usize,
+    pueue_dir: &Path,
+    lines: Option<usize>,
+    stream: Option<LogStream>,
+    show_timestamps: bool,
+    since: Option<DateTime<Local>>,
+) -> Result<(String, bool), Error> {
+    let path = get_stream_log_path(task_id, pueue_dir, stream)?;
+    let mut file = LogReader::open(&path)?;
+    let mut timestamps = TimestampReader::new(&path);
+    timestamps.update()?;
+
+    // Lines can only be filtered by time if the whole log is read.
+    let mut output_complete = true;
+    if let (Some(lines), None) = (lines, since) {
+        output_complete = seek_to_last_lines(&mut file, lines)?;
+    }
+
+    let offset = file.offset();
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)
+        .map_err(|err| Error::IoError("reading log output".to_string(), err))?;
+    let mut output = timestamps.render(&content, offset, show_timestamps, since);
+
+    // Only keep the last lines of the filtered output.
+    if let (Some(lines), Some(_)) = (lines, since) {
+        let total = output.split_inclusive('\n').count();
+        if total > lines {
+            output_complete = false;
+            output = output.split_inclusive('\n').skip(total - lines).collect();
+        }
+    }
+
+    Ok((output, output_complete))
+}
+
+/// Same as [read_timestamped_log_file], but the output is compressed using [snap].
+pub fn read_and_compress_timestamped_log_file(
+    task_id: usize,
+    pueue_dir: &Path,
+    lines: Option<usize>,
+    stream: Option<LogStream>,
+    show_timestamps: bool,
+    since: Option<DateTime<Local>>,
+) -> Result<(Vec<u8>, bool), Error> {
+    let (output, output_complete) =
+        read_timestamped_log_file(task_id, pueue_dir, lines, stream, show_timestamps, since)?;
+
+    let mut content = Vec::new();
+    {
+        let mut compressor = FrameEncoder::new(&mut content);
+        compressor
+            .write_all(output.as_bytes())
+            .map_err(|err| Error::IoError("compressing log output".to_string(), err))?;
+    }
+
+    Ok((content, output_complete))
+}
+
 /// Return the last lines of of a task's output. \
 /// This output is uncompressed and may take a lot of memory, which is why we only read
 /// the last few lines.
@@ -101,8 +328,9 @@ pub fn read_last_log_file_lines(
     task_id: usize,
     pueue_dir: &Path,
     lines: usize,
+    stream: Option<LogStream>,
 ) -> Result<String, Error> {
-    let mut file = get_log_file_handle(task_id, pueue_dir)?;
+    let mut file = get_stream_log_file_handle(task_id, pueue_dir, stream)?;
 
     // Get the last few lines of both files
     Ok(read_last_lines(&mut file, lines))
@@ -115,7 +343,7 @@ pub fn read_last_log_file_lines(
 // We allow this clippy check.
 // The iterators cannot be chained, as RevBufReader.lines doesn't implement the necessary traits.
 #[allow(clippy::needless_collect)]
-pub fn read_last_lines(file: &mut File, amount: usize) -> String {
+pub fn read_last_lines<R: Read + Seek>(file: &mut R, amount: usize) -> String {
     let reader = RevBufReader::new(file);
 
     let lines: Vec<String> = reader
@@ -132,7 +360,7 @@ pub fn read_last_lines(file: &mut File, amount: usize) -> String {
 ///
 /// The `bool` return value indicates whether we sought to the start of the file (there were less
 /// lines than the limit). `true` means that the handle is now at the very start of the file.
-pub fn seek_to_last_lines(file: &mut File, amount: usize) -> Result<bool, Error> {
+pub fn seek_to_last_lines<R: Read + Seek>(file: &mut R, amount: usize) -> Result<bool, Error> {
     let mut reader = RevBufReader::new(file);
     // The position from which the RevBufReader starts reading.
     // The file size might change while we're reading the file. Hence we have to save it now.
@@ -202,3 +430,931 @@ pub fn seek_to_last_lines(file: &mut File, amount: usize) -> Result<bool, Error>
 
     Ok(false)
 }
+
+/// Get the path to a rotated segment of a log file.
+/// Segments are numbered starting from `1`, which is the most recent one.
+pub fn get_log_segment_path(log_path: &Path, index: usize) -> PathBuf {
+    log_path.with_extension(format!("{index}.log"))
+}
+
+/// Get the paths of all rotated segments of a log file, the most recent one first.
+pub fn get_log_segment_paths(log_path: &Path) -> Vec<PathBuf> {
+    (1..)
+        .map(|index| get_log_segment_path(log_path, index))
+        .take_while(|path| path.exists())
+        .collect()
+}
+
+/// Get the path to the file that contains the offset of a log file.
+///
+/// That's the position of the log file's first byte in the task's whole output.
+/// It's increased every time output is removed from the head of the log file.
+pub fn get_log_offset_path(log_path: &Path) -> PathBuf {
+    log_path.with_extension("offset")
+}
+
+/// Read the offset of a log file. Logs that have never been truncated start at `0`.
+pub fn read_log_offset(log_path: &Path) -> u64 {
+    std::fs::read_to_string(get_log_offset_path(log_path))
+        .ok()
+        .and_then(|offset| offset.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Write the offset of a log file.
+/// The file is replaced atomically, as it may be read at any time.
+fn write_log_offset(log_path: &Path, offset: u64) -> Result<(), Error> {
+    let path = get_log_offset_path(log_path);
+    let temp = path.with_extension("offset.partial");
+    std::fs::write(&temp, offset.to_string())
+        .map_err(|err| Error::IoPathError(temp.clone(), "writing log offset", err))?;
+    std::fs::rename(&temp, &path).map_err(|err| Error::IoPathError(path, "writing log offset", err))
+}
+
+/// Copy the bytes from `start` to `end` in `source` to `write_position` in `target`.
+///
+/// The copy is bounded, so a task that keeps writing to the source cannot keep us busy forever.
+fn copy_range(
+    source: &mut File,
+    start: u64,
+    end: u64,
+    target: &mut File,
+    write_position: u64,
+) -> io::Result<()> {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut read_position = start;
+    while read_position < end {
+        let chunk = buffer.len().min((end - read_position) as usize);
+        source.seek(SeekFrom::Start(read_position))?;
+        source.read_exact(&mut buffer[..chunk])?;
+        target.seek(SeekFrom::Start(write_position + read_position - start))?;
+        target.write_all(&buffer[..chunk])?;
+        read_position += chunk as u64;
+    }
+
+    Ok(())
+}
+
+/// Drop the oldest output of a log file, so only its last `keep` bytes remain.
+///
+/// The remaining output is moved to the start of the file, as the task keeps writing to it.
+/// Output that has been appended while moving is moved as well, but only once.
+/// If the task doesn't write through the daemon, output that's appended in the very moment the
+/// file is cut may therefore get lost.
+pub fn truncate_log_head(log_path: &Path, keep: u64) -> Result<(), Error> {
+    let io_error = |err| Error::IoPathError(log_path.to_path_buf(), "truncating log", err);
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .open(log_path)
+        .map_err(io_error)?;
+    let size = file.metadata().map_err(io_error)?.len();
+    if size <= keep {
+        return Ok(());
+    }
+
+    // Reading and writing happens through the same handle, so the task's output is moved in
+    // place. This is fine, as the output is always moved towards the start of the file.
+    let dropped = size - keep;
+    let mut target = file.try_clone().map_err(io_error)?;
+    copy_range(&mut file, dropped, size, &mut target, 0).map_err(io_error)?;
+    let end = file.metadata().map_err(io_error)?.len();
+    copy_range(&mut file, size, end, &mut target, keep).map_err(io_error)?;
+    file.set_len(end - dropped).map_err(io_error)?;
+
+    write_log_offset(log_path, read_log_offset(log_path) + dropped)
+}
+
+/// Move the output of a log file into a new segment and empty the log file afterwards.
+///
+/// Only the most recent `max_segments` segments are kept, older ones are removed.
+/// The task keeps writing to the same file, which is why its content is copied instead of
+/// moving the file. Just like in [truncate_log_head], output that's appended while copying is
+/// copied once more, output that's appended in the very moment the file is emptied may get lost.
+pub fn rotate_log_file(log_path: &Path, max_segments: usize) -> Result<(), Error> {
+    let io_error = |err| Error::IoPathError(log_path.to_path_buf(), "rotating log", err);
+
+    // Remove the oldest segments and make room for the new one.
+    let segments = get_log_segment_paths(log_path);
+    for path in segments.iter().skip(max_segments.saturating_sub(1)) {
+        remove_file(path)
+            .map_err(|err| Error::IoPathError(path.clone(), "removing segment", err))?;
+    }
+    for (index, path) in segments
+        .iter()
+        .enumerate()
+        .take(max_segments.saturating_sub(1))
+        .rev()
+    {
+        let target = get_log_segment_path(log_path, index + 2);
+        std::fs::rename(path, &target)
+            .map_err(|err| Error::IoPathError(path.clone(), "moving segment", err))?;
+    }
+
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .open(log_path)
+        .map_err(io_error)?;
+    let size = file.metadata().map_err(io_error)?.len();
+    let rotated = if max_segments == 0 {
+        size
+    } else {
+        let path = get_log_segment_path(log_path, 1);
+        let mut segment =
+            File::create(&path).map_err(|err| Error::IoPathError(path, "creating segment", err))?;
+        copy_range(&mut file, 0, size, &mut segment, 0).map_err(io_error)?;
+        let end = file.metadata().map_err(io_error)?.len();
+        copy_range(&mut file, size, end, &mut segment, size).map_err(io_error)?;
+        end
+    };
+    file.set_len(0).map_err(io_error)?;
+
+    write_log_offset(log_path, read_log_offset(log_path) + rotated)
+}
+
+/// A log, that has been compressed into a temporary file by [compress_log_file].
+/// It replaces the uncompressed log once [CompressedLog::finish] is called.
+#[derive(Debug)]
+pub struct CompressedLog {
+    log_path: PathBuf,
+    temp: PathBuf,
+    /// The end of the log in the task's whole output, at the time it has been compressed.
+    end: u64,
+}
+
+impl CompressedLog {
+    /// Replace the log file and all of its rotated segments with the compressed log.
+    ///
+    /// The compressed log is discarded instead, if the log has changed in the meantime.
+    pub fn finish(self) -> Result<(), Error> {
+        let reader = LogReader::open(&self.log_path)?;
+        if reader.current.is_none() || reader.offset() + reader.size()? != self.end {
+            let path = self.log_path.clone();
+            self.discard();
+            return Err(Error::IoPathError(
+                path,
+                "replacing log",
+                io::Error::other("the log changed while it was compressed"),
+            ));
+        }
+
+        let path = get_compressed_log_path(&self.log_path);
+        std::fs::rename(&self.temp, &path)
+            .map_err(|err| Error::IoPathError(path, "writing compressed log", err))?;
+
+        // The compressed log is read as a single segment, which ends where the log file ended.
+        write_log_offset(&self.log_path, self.end)?;
+        for path in get_log_segment_paths(&self.log_path)
+            .into_iter()
+            .chain([self.log_path])
+        {
+            remove_file(&path)
+                .map_err(|err| Error::IoPathError(path, "removing uncompressed log", err))?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove the compressed log and keep the uncompressed one.
+    pub fn discard(self) {
+        if let Err(err) = remove_file(&self.temp) {
+            error!("Failed to remove compressed log {:?}: {err}", self.temp);
+        }
+    }
+}
+
+/// Compress a log file together with all of its rotated segments into a single file.
+///
+/// The log is compressed into a temporary file, which only replaces the uncompressed files once
+/// [CompressedLog::finish] is called. That way, the caller can make sure that the log hasn't
+/// been removed or restarted in the meantime. \
+/// `None` is returned, if there's no uncompressed log.
+///
+/// This must only be done once the task is finished, as its output is no longer written to
+/// the log file afterwards.
+pub fn compress_log_file(log_path: &Path) -> Result<Option<CompressedLog>, Error> {
+    if !log_path.exists() {
+        return Ok(None);
+    }
+
+    let mut reader = LogReader::open(log_path)?;
+    let end = reader.offset() + reader.size()?;
+
+    let temp = get_partial_compressed_log_path(log_path);
+    let file = File::create(&temp)
+        .map_err(|err| Error::IoPathError(temp.clone(), "creating compressed log", err))?;
+    let mut encoder = FrameEncoder::new(file);
+    if let Err(err) = io::copy(&mut reader, &mut encoder).and_then(|_| encoder.flush()) {
+        drop(encoder);
+        let _ = remove_file(&temp);
+        return Err(Error::IoPathError(temp, "compressing log", err));
+    }
+
+    Ok(Some(CompressedLog {
+        log_path: log_path.to_path_buf(),
+        temp,
+        end,
+    }))
+}
+
+/// Reads a log file together with all of its rotated segments, as if they were a single file.
+///
+/// Compressed logs of finished tasks are read transparently. Only the chunk of the compressed
+/// log that's currently read is decompressed into memory.
+///
+/// Positions are relative to the start of the oldest segment that's still around.
+/// Take a look at [LogReader::offset] for the position in the task's whole output.
+#[derive(Debug)]
+pub struct LogReader {
+    path: PathBuf,
+    /// The offset of the log file, see [get_log_offset_path].
+    offset: u64,
+    /// All rotated segments, the oldest one first, together with their size.
+    segments: Vec<(Segment, u64)>,
+    /// The log file, unless it has been compressed.
+    current: Option<File>,
+    position: u64,
+}
+
+/// A part of a log, that's no longer written to.
+#[derive(Debug)]
+enum Segment {
+    File(File),
+    Compressed(CompressedSegment),
+}
+
+impl Read for Segment {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Segment::File(file) => file.read(buf),
+            Segment::Compressed(segment) => segment.read(buf),
+        }
+    }
+}
+
+impl Seek for Segment {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Segment::File(file) => file.seek(pos),
+            Segment::Compressed(segment) => segment.seek(pos),
+        }
+    }
+}
+
+/// A compressed log, which is decompressed one chunk at a time.
+///
+/// Snappy's frame format consists of independently compressed chunks of at most 64KiB.
+/// These chunks are indexed when the log is opened, so any part of the log can be read without
+/// decompressing everything before it.
+#[derive(Debug)]
+struct CompressedSegment {
+    file: File,
+    chunks: Vec<Chunk>,
+    /// The chunk that has been decompressed last, by its index.
+    cached: Option<(usize, Vec<u8>)>,
+    position: u64,
+}
+
+/// A chunk of a compressed log.
+#[derive(Debug)]
+struct Chunk {
+    /// The position of the chunk in the decompressed log.
+    start: u64,
+    /// The position of the chunk's data in the compressed file, right behind its checksum.
+    file_offset: u64,
+    /// The length of the chunk's data in the compressed file.
+    length: usize,
+    compressed: bool,
+}
+
+impl CompressedSegment {
+    fn open(path: &Path) -> io::Result<Self> {
+        let mut reader = io::BufReader::new(File::open(path)?);
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut file_offset = 0;
+        let mut data = Vec::new();
+        loop {
+            let mut header = [0; 4];
+            match reader.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            }
+            let length = u32::from_le_bytes([header[1], header[2], header[3], 0]) as usize;
+            file_offset += 4;
+
+            match header[0] {
+                // Compressed and uncompressed data, both start with a checksum.
+                kind @ (0x00 | 0x01) => {
+                    if length < 4 {
+                        return Err(invalid("chunk is too short"));
+                    }
+                    data.resize(length, 0);
+                    reader.read_exact(&mut data)?;
+                    let compressed = kind == 0x00;
+                    let size = if compressed {
+                        snap::raw::decompress_len(&data[4..]).map_err(io::Error::other)?
+                    } else {
+                        length - 4
+                    };
+                    chunks.push(Chunk {
+                        start,
+                        file_offset: file_offset + 4,
+                        length: length - 4,
+                        compressed,
+                    });
+                    start += size as u64;
+                }
+                0x02..=0x7f => return Err(invalid("unknown chunk type")),
+                // The stream identifier, padding and skippable chunks.
+                _ => {
+                    io::copy(&mut reader.by_ref().take(length as u64), &mut io::sink())?;
+                }
+            }
+            file_offset += length as u64;
+        }
+        chunks.push(Chunk {
+            start,
+            file_offset,
+            length: 0,
+            compressed: false,
+        });
+
+        Ok(CompressedSegment {
+            file: reader.into_inner(),
+            chunks,
+            cached: None,
+            position: 0,
+        })
+    }
+
+    /// The size of the decompressed log.
+    fn size(&self) -> u64 {
+        // The last chunk is an empty marker for the end of the log.
+        self.chunks
+            .last()
+            .map(|chunk| chunk.start)
+            .unwrap_or_default()
+    }
+
+    /// Decompress a chunk, unless it's already cached.
+    fn chunk(&mut self, index: usize) -> io::Result<&[u8]> {
+        if self
+            .cached
+            .as_ref()
+            .is_none_or(|(cached, _)| *cached != index)
+        {
+            let chunk = &self.chunks[index];
+            let mut data = vec![0; chunk.length];
+            self.file.seek(SeekFrom::Start(chunk.file_offset))?;
+            self.file.read_exact(&mut data)?;
+            if chunk.compressed {
+                data = snap::raw::Decoder::new()
+                    .decompress_vec(&data)
+                    .map_err(io::Error::other)?;
+            }
+            self.cached = Some((index, data));
+        }
+
+        Ok(self
+            .cached
+            .as_ref()
+            .map(|(_, data)| data.as_slice())
+            .unwrap())
+    }
+}
+
+impl Read for CompressedSegment {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size() {
+            return Ok(0);
+        }
+
+        let index = self
+            .chunks
+            .partition_point(|chunk| chunk.start <= self.position)
+            - 1;
+        let skip = (self.position - self.chunks[index].start) as usize;
+        let data = &self.chunk(index)?[skip..];
+        let length = data.len().min(buf.len());
+        buf[..length].copy_from_slice(&data[..length]);
+        self.position += length as u64;
+
+        Ok(length)
+    }
+}
+
+impl Seek for CompressedSegment {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(position) => {
+                self.position = position;
+                return Ok(position);
+            }
+            SeekFrom::End(delta) => (self.size(), delta),
+            SeekFrom::Current(delta) => (self.position, delta),
+        };
+
+        let Some(position) = base.checked_add_signed(delta) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ));
+        };
+        self.position = position;
+
+        Ok(position)
+    }
+}
+
+impl LogReader {
+    pub fn open(log_path: &Path) -> Result<Self, Error> {
+        let offset = read_log_offset(log_path);
+
+        let compressed_path = get_compressed_log_path(log_path);
+        if !log_path.exists() && compressed_path.exists() {
+            let segment = CompressedSegment::open(&compressed_path).map_err(|err| {
+                Error::IoPathError(compressed_path, "opening compressed log", err)
+            })?;
+            let size = segment.size();
+
+            return Ok(LogReader {
+                path: log_path.to_path_buf(),
+                offset,
+                segments: vec![(Segment::Compressed(segment), size)],
+                current: None,
+                position: 0,
+            });
+        }
+
+        let current = File::open(log_path)
+            .map_err(|err| Error::IoPathError(log_path.to_path_buf(), "opening log", err))?;
+
+        let mut segments = Vec::new();
+        for path in get_log_segment_paths(log_path).into_iter().rev() {
+            // The segment might've been rotated away in the meantime.
+            let Ok(file) = File::open(&path) else {
+                continue;
+            };
+            let size = file
+                .metadata()
+                .map_err(|err| Error::IoPathError(path, "reading segment size", err))?
+                .len();
+            segments.push((Segment::File(file), size));
+        }
+
+        Ok(LogReader {
+            path: log_path.to_path_buf(),
+            offset,
+            segments,
+            current: Some(current),
+            position: 0,
+        })
+    }
+
+    fn segments_size(&self) -> u64 {
+        self.segments.iter().map(|(_, size)| size).sum()
+    }
+
+    /// The current size of the log file and all of its segments.
+    pub fn size(&self) -> Result<u64, Error> {
+        let current = match &self.current {
+            Some(file) => file
+                .metadata()
+                .map_err(|err| Error::IoPathError(self.path.clone(), "reading log size", err))?
+                .len(),
+            None => 0,
+        };
+
+        Ok(self.segments_size() + current)
+    }
+
+    /// The position of the reader in the task's whole output.
+    /// Output that has already been dropped due to the log size limit is included.
+    pub fn offset(&self) -> u64 {
+        self.offset.saturating_sub(self.segments_size()) + self.position
+    }
+
+    /// Move the reader to a position in the task's whole output, see [LogReader::offset].
+    /// If that part of the output has already been dropped, the reader is moved to the oldest
+    /// output that's still around.
+    pub fn seek_to_offset(&mut self, offset: u64) {
+        self.position = offset.saturating_sub(self.offset.saturating_sub(self.segments_size()));
+    }
+
+    /// Reopen the log, if it has been rotated or truncated since it has been opened.
+    /// The reader continues at the same position of the task's output, if it's still around.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        if read_log_offset(&self.path) == self.offset {
+            return Ok(());
+        }
+
+        let offset = self.offset();
+        let mut reader = LogReader::open(&self.path)?;
+        reader.seek_to_offset(offset);
+        *self = reader;
+
+        Ok(())
+    }
+}
+
+impl Read for LogReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut start = 0;
+        for (file, size) in self.segments.iter_mut() {
+            if self.position < start + *size {
+                let remaining = start + *size - self.position;
+                let length = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+                file.seek(SeekFrom::Start(self.position - start))?;
+                let read = file.read(&mut buf[..length])?;
+                self.position += read as u64;
+                return Ok(read);
+            }
+            start += *size;
+        }
+
+        let Some(current) = self.current.as_mut() else {
+            return Ok(0);
+        };
+        current.seek(SeekFrom::Start(self.position - start))?;
+        let read = current.read(buf)?;
+        self.position += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl Seek for LogReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(position) => {
+                self.position = position;
+                return Ok(position);
+            }
+            SeekFrom::End(delta) => (self.size().map_err(io::Error::other)?, delta),
+            SeekFrom::Current(delta) => (self.position, delta),
+        };
+
+        let Some(position) = base.checked_add_signed(delta) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ));
+        };
+        self.position = position;
+
+        Ok(position)
+    }
+}
+
+/// Get the path to the file that records the timestamps of the lines in a log file.
+pub fn get_timestamps_path(log_path: &Path) -> PathBuf {
+    log_path.with_extension("timestamps")
+}
+
+/// Create the files that record the timestamps of a task's `(stdout, stderr)` output.
+/// If the streams aren't captured separately, a single file is used for both streams.
+pub fn create_timestamp_file_handles(
+    task_id: usize,
+    pueue_dir: &Path,
+    separate_streams: bool,
+) -> Result<(File, Option<File>), Error> {
+    let path = get_timestamps_path(&get_log_path(task_id, pueue_dir));
+    let stdout_handle = File::create(&path)
+        .map_err(|err| Error::IoPathError(path, "creating timestamp file", err))?;
+
+    let mut stderr_handle = None;
+    if separate_streams {
+        let path = get_timestamps_path(&get_stderr_log_path(task_id, pueue_dir));
+        let handle = File::create(&path)
+            .map_err(|err| Error::IoPathError(path, "creating timestamp file", err))?;
+        stderr_handle = Some(handle);
+    }
+
+    Ok((stdout_handle, stderr_handle))
+}
+
+/// Remove all files that belong to the log files of a task.
+/// That's the recorded timestamps, the rotated segments, the log offsets and compressed logs.
+fn remove_log_sidecars(task_id: usize, pueue_dir: &Path) -> Result<(), Error> {
+    for log_path in [
+        get_log_path(task_id, pueue_dir),
+        get_stderr_log_path(task_id, pueue_dir),
+    ] {
+        let mut paths = vec![
+            get_timestamps_path(&log_path),
+            get_log_offset_path(&log_path),
+            get_compressed_log_path(&log_path),
+            get_partial_compressed_log_path(&log_path),
+        ];
+        paths.extend(get_log_segment_paths(&log_path));
+        for path in paths {
+            if path.exists() {
+                remove_file(&path)
+                    .map_err(|err| Error::IoPathError(path, "removing log sidecar file", err))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The time at which a line of a task's output has been printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineTimestamp {
+    /// The position of the line's first byte in the task's whole output.
+    /// This matches the position in the log file, unless the log has been truncated.
+    pub offset: u64,
+    pub time: DateTime<Local>,
+}
+
+impl LineTimestamp {
+    /// Serialize the timestamp into a single line of a timestamp file.
+    pub fn to_record(&self) -> String {
+        format!("{} {}\n", self.offset, self.time.timestamp_millis())
+    }
+
+    fn from_record(record: &str) -> Option<LineTimestamp> {
+        let (offset, millis) = record.split_once(' ')?;
+        let time = DateTime::from_timestamp_millis(millis.parse().ok()?)?;
+
+        Some(LineTimestamp {
+            offset: offset.parse().ok()?,
+            time: time.with_timezone(&Local),
+        })
+    }
+}
+
+/// Reads the timestamps of a log file.
+///
+/// The file is read incrementally, which allows to follow the timestamps of running tasks.
+/// Logs without any recorded timestamps simply don't have any timestamps.
+#[derive(Debug)]
+pub struct TimestampReader {
+    file: Option<File>,
+    /// A record that hasn't been completely written yet.
+    partial: Vec<u8>,
+    timestamps: Vec<LineTimestamp>,
+}
+
+impl TimestampReader {
+    pub fn new(log_path: &Path) -> Self {
+        TimestampReader {
+            file: File::open(get_timestamps_path(log_path)).ok(),
+            partial: Vec::new(),
+            timestamps: Vec::new(),
+        }
+    }
+
+    /// Read all timestamps that have been recorded since the last update.
+    pub fn update(&mut self) -> Result<(), Error> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        file.read_to_end(&mut self.partial)
+            .map_err(|err| Error::IoError("reading timestamps".to_string(), err))?;
+
+        // Only parse complete records, the rest is kept for the next update.
+        let Some(end) = self.partial.iter().rposition(|byte| *byte == b'\n') else {
+            return Ok(());
+        };
+        let records: Vec<u8> = self.partial.drain(..=end).collect();
+        self.timestamps.extend(
+            String::from_utf8_lossy(&records)
+                .lines()
+                .filter_map(LineTimestamp::from_record),
+        );
+
+        Ok(())
+    }
+
+    /// The time at which the line that starts at `offset` has been printed.
+    pub fn time_at(&self, offset: u64) -> Option<DateTime<Local>> {
+        self.timestamps
+            .binary_search_by_key(&offset, |timestamp| timestamp.offset)
+            .ok()
+            .map(|index| self.timestamps[index].time)
+    }
+
+    /// Render a chunk of output, which starts at `offset` in the task's output.
+    /// Take a look at [LogReader::offset] for how to get it.
+    ///
+    /// If `show_timestamps` is set, every line is prefixed with the time it has been printed.
+    /// Lines that have been printed before `since` are dropped.
+    /// Lines without a recorded timestamp are always kept as they are.
+    pub fn render(
+        &self,
+        chunk: &[u8],
+        mut offset: u64,
+        show_timestamps: bool,
+        since: Option<DateTime<Local>>,
+    ) -> String {
+        let mut output = String::new();
+        for line in chunk.split_inclusive(|byte| *byte == b'\n') {
+            let time = self.time_at(offset);
+            offset += line.len() as u64;
+
+            if let (Some(time), Some(since)) = (time, since) {
+                if time < since {
+                    continue;
+                }
+            }
+            if let (Some(time), true) = (time, show_timestamps) {
+                output.push_str(&format!("[{}] ", time.format("%Y-%m-%d %H:%M:%S%.3f")));
+            }
+            output.push_str(&String::from_utf8_lossy(line));
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeDelta;
+    use pretty_assertions::assert_eq;
+    use snap::read::FrameDecoder;
+
+    use super::*;
+
+    /// Lines are rendered with their timestamps and can be filtered by time.
+    #[test]
+    fn test_render_timestamps() {
+        let start = Local::now();
+        let later = start + TimeDelta::try_seconds(10).unwrap();
+        let reader = TimestampReader {
+            file: None,
+            partial: Vec::new(),
+            timestamps: vec![
+                LineTimestamp {
+                    offset: 0,
+                    time: start,
+                },
+                LineTimestamp {
+                    offset: 6,
+                    time: later,
+                },
+            ],
+        };
+        let chunk = b"first\nsecond\n";
+
+        assert_eq!(reader.render(chunk, 0, false, None), "first\nsecond\n");
+        assert_eq!(reader.render(chunk, 0, false, Some(later)), "second\n");
+
+        let format = |time: DateTime<Local>| time.format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+        assert_eq!(
+            reader.render(chunk, 0, true, None),
+            format!("[{}] first\n[{}] second\n", format(start), format(later))
+        );
+
+        // A chunk that starts in the middle of a line only gets timestamps for new lines.
+        assert_eq!(
+            reader.render(&chunk[3..], 3, true, None),
+            format!("st\n[{}] second\n", format(later))
+        );
+
+        // Records survive a round trip through the timestamp file format.
+        let record = reader.timestamps[1].to_record();
+        let parsed = LineTimestamp::from_record(record.trim_end()).unwrap();
+        assert_eq!(parsed.offset, 6);
+        assert_eq!(parsed.time.timestamp_millis(), later.timestamp_millis());
+    }
+
+    /// Rotated and truncated logs are read as a single file and keep their offsets.
+    #[test]
+    fn test_rotated_log() -> Result<(), Error> {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("task_logs")).unwrap();
+        let (mut log, _) = create_log_file_handles(0, tempdir.path(), false)?;
+        let path = get_log_path(0, tempdir.path());
+
+        let mut write = |text: &str| log.write_all(text.as_bytes()).unwrap();
+        write("first\n");
+        rotate_log_file(&path, 2)?;
+        write("second\n");
+        rotate_log_file(&path, 2)?;
+        write("third\n");
+
+        let mut reader = LogReader::open(&path)?;
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "first\nsecond\nthird\n");
+
+        // The oldest segment is dropped, once there are too many.
+        rotate_log_file(&path, 2)?;
+        write("fourth\n");
+        let mut reader = LogReader::open(&path)?;
+        assert_eq!(reader.offset(), 6);
+        seek_to_last_lines(&mut reader, 1)?;
+        assert_eq!(reader.offset(), 19);
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "fourth\n");
+
+        // Only the end of the log is kept, while the offset still points into the whole output.
+        write("fifth\n");
+        truncate_log_head(&path, 6)?;
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fifth\n");
+        assert_eq!(read_log_offset(&path), 26);
+
+        Ok(())
+    }
+
+    /// Compressed logs replace the log and its segments and can be read from anywhere.
+    #[test]
+    fn test_compressed_log() -> Result<(), Error> {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("task_logs")).unwrap();
+        let (mut log, _) = create_log_file_handles(0, tempdir.path(), false)?;
+        let path = get_log_path(0, tempdir.path());
+
+        // Write enough output to span several chunks of the compressed log.
+        let lines: Vec<String> = (0..50_000).map(|line| format!("{line}\n")).collect();
+        let expected = lines.concat();
+        log.write_all(lines[..25_000].concat().as_bytes()).unwrap();
+        rotate_log_file(&path, 2)?;
+        log.write_all(lines[25_000..].concat().as_bytes()).unwrap();
+
+        let compressed = compress_log_file(&path)?.expect("The log should be compressed");
+        compressed.finish()?;
+        assert!(!path.exists());
+        assert!(get_log_segment_paths(&path).is_empty());
+        assert!(!get_partial_compressed_log_path(&path).exists());
+
+        let mut reader = LogReader::open(&path)?;
+        assert_eq!(reader.size()?, expected.len() as u64);
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, expected);
+
+        seek_to_last_lines(&mut reader, 2)?;
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "49998\n49999\n");
+
+        reader.seek(SeekFrom::Start(100_000)).unwrap();
+        let mut output = vec![0; 100_000];
+        reader.read_exact(&mut output).unwrap();
+        assert_eq!(output, expected.as_bytes()[100_000..200_000]);
+
+        Ok(())
+    }
+
+    /// A compressed log is discarded, if the log changed while it was compressed.
+    #[test]
+    fn test_discard_compressed_log() -> Result<(), Error> {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("task_logs")).unwrap();
+        let (mut log, _) = create_log_file_handles(0, tempdir.path(), false)?;
+        let path = get_log_path(0, tempdir.path());
+        log.write_all(b"first\n").unwrap();
+
+        let compressed = compress_log_file(&path)?.expect("The log should be compressed");
+        log.write_all(b"second\n").unwrap();
+        assert!(compressed.finish().is_err());
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+        assert!(!get_compressed_log_path(&path).exists());
+        assert!(!get_partial_compressed_log_path(&path).exists());
+
+        Ok(())
+    }
+
+    /// Byte ranges are read by their offset in the task's whole output.
+    #[test]
+    fn test_log_range() -> Result<(), Error> {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("task_logs")).unwrap();
+        let (mut log, _) = create_log_file_handles(0, tempdir.path(), false)?;
+        let path = get_log_path(0, tempdir.path());
+        log.write_all(b"first\nsecond\nthird\n").unwrap();
+        truncate_log_head(&path, 13)?;
+
+        let read_range = |range: Range<u64>| -> Result<(String, Range<u64>, bool), Error> {
+            let (compressed, range, complete) =
+                read_and_compress_log_range(0, tempdir.path(), None, range)?;
+            let mut output = String::new();
+            FrameDecoder::new(compressed.as_slice())
+                .read_to_string(&mut output)
+                .unwrap();
+            Ok((output, range, complete))
+        };
+
+        // The start of the range has already been dropped.
+        assert_eq!(read_range(0..12)?, ("second".to_string(), 6..12, false));
+        assert_eq!(
+            read_range(10..100)?,
+            ("nd\nthird\n".to_string(), 10..19, false)
+        );
+        assert_eq!(
+            read_range(0..100)?,
+            ("second\nthird\n".to_string(), 6..19, true)
+        );
+
+        Ok(())
+    }
+}
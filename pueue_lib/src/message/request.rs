@@ -1,10 +1,14 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    path::PathBuf,
+};
 
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
 use strum::{Display, EnumString, VariantNames};
 
-use crate::message::EditableTask;
+use crate::{Group, Task, message::EditableTask, task::LogStream};
 
 /// Macro to simplify creating [From] implementations for each variant-contained
 /// Request; e.g. `impl_into_request!(AddRequest, Request::Add)` to make it possible
@@ -29,6 +33,10 @@ pub enum Request {
     Remove(Vec<usize>),
     /// Switch two enqueued/stashed tasks.
     Switch(SwitchRequest),
+    /// Move enqueued/stashed tasks to another group or within their group's execution order.
+    Move(MoveRequest),
+    /// Import tasks and groups that have been exported from another daemon.
+    Import(ImportRequest),
     /// Stash a task or schedule it for enqueue.
     Stash(StashRequest),
     /// Take a stashed task and enqueue it.
@@ -70,6 +78,8 @@ pub enum Request {
 
     /// The client requests a continuous stream of a task's log.
     Stream(StreamRequest),
+    /// Search the logs of a set of tasks.
+    Grep(GrepRequest),
 
     /// Reset the daemon
     Reset(ResetRequest),
@@ -77,6 +87,8 @@ pub enum Request {
     Clean(CleanRequest),
     /// Initiate shutdown on the daemon.
     DaemonShutdown(ShutdownRequest),
+    /// Re-read the daemon's configuration file and apply all changed settings.
+    DaemonReload,
 }
 
 /// This enum is used to express a selection of tasks.
@@ -93,6 +105,11 @@ pub enum TaskSelection {
 pub struct AddRequest {
     pub command: String,
     pub path: PathBuf,
+    /// Whether `path` is only the client's current working directory, as the user didn't
+    /// specify one. The daemon then uses the `working_directory` of the task's group instead,
+    /// if one is declared.
+    #[serde(default)]
+    pub default_path: bool,
     pub envs: HashMap<String, String>,
     pub start_immediately: bool,
     pub stashed: bool,
@@ -101,6 +118,19 @@ pub struct AddRequest {
     pub dependencies: Vec<usize>,
     pub priority: Option<i32>,
     pub label: Option<String>,
+    /// Whether the task may be killed and requeued when it's preempted.
+    #[serde(default)]
+    pub restartable: bool,
+    /// Never run this task at the same time as other tasks with the same key.
+    #[serde(default)]
+    pub concurrency_key: Option<String>,
+    /// Cancel all queued and stashed tasks with the same concurrency key, so only the newest one
+    /// is kept.
+    #[serde(default)]
+    pub supersede: bool,
+    /// Record the time at which each line of the task's output has been printed.
+    #[serde(default)]
+    pub timestamps: bool,
 }
 
 /// We use a custom `Debug` implementation for [AddRequest], as the `envs` field just has
@@ -113,6 +143,7 @@ impl std::fmt::Debug for AddRequest {
         f.debug_struct("Task")
             .field("command", &self.command)
             .field("path", &self.path)
+            .field("default_path", &self.default_path)
             .field("envs", &"hidden")
             .field("start_immediately", &self.start_immediately)
             .field("stashed", &self.stashed)
@@ -120,6 +151,10 @@ impl std::fmt::Debug for AddRequest {
             .field("enqueue_at", &self.enqueue_at)
             .field("dependencies", &self.dependencies)
             .field("label", &self.label)
+            .field("restartable", &self.restartable)
+            .field("concurrency_key", &self.concurrency_key)
+            .field("supersede", &self.supersede)
+            .field("timestamps", &self.timestamps)
             .finish()
     }
 }
@@ -132,6 +167,36 @@ pub struct SwitchRequest {
 }
 impl_into_request!(SwitchRequest, Request::Switch);
 
+/// Where tasks should be moved to.
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+pub enum MoveDestination {
+    /// Move the tasks to another group.
+    Group(String),
+    /// Move the tasks in front of all other queued and stashed tasks of their group.
+    Front,
+    /// Move the tasks behind all other queued and stashed tasks of their group.
+    Back,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+pub struct MoveRequest {
+    pub task_ids: Vec<usize>,
+    pub destination: MoveDestination,
+}
+impl_into_request!(MoveRequest, Request::Move);
+
+/// Tasks and groups that have been exported via `pueue export`.
+/// This is also the format of the exported file.
+#[derive(PartialEq, Eq, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImportRequest {
+    pub tasks: BTreeMap<usize, Task>,
+    pub groups: BTreeMap<String, Group>,
+    /// The log output of the exported tasks, if logs have been exported.
+    #[serde(default)]
+    pub logs: BTreeMap<usize, String>,
+}
+impl_into_request!(ImportRequest, Request::Import);
+
 #[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
 pub struct StashRequest {
     pub tasks: TaskSelection,
@@ -239,6 +304,23 @@ pub enum GroupRequest {
         parallel_tasks: Option<usize>,
     },
     Remove(String),
+    /// Rename a group. All tasks of the group are moved to the new name.
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    /// Enable priority preemption with the given margin for a group.
+    /// Preemption is disabled, if no margin is given.
+    Preemption {
+        name: String,
+        margin: Option<i32>,
+    },
+    /// Limit the amount of queued and stashed tasks of a group.
+    /// The limit is removed, if no maximum is given.
+    MaxQueued {
+        name: String,
+        max_queued: Option<usize>,
+    },
     List,
 }
 impl_into_request!(GroupRequest, Request::Group);
@@ -277,15 +359,26 @@ impl_into_request!(ShutdownRequest, Request::DaemonShutdown);
 
 /// Request the live streaming of a set of running tasks.
 ///
-/// **WARNING**:
-/// Even though this type currently accepts a TaskSelection, only
-/// `TaskSelection::TaskIds(vec![])` and `TaskSelection::TaskIds(vec![id])` are accepted.
-/// We already use this format in preparation for <https://github.com/Nukesor/pueue/issues/614>
-/// That way we can stay forwards compatible without having to break the API.
+/// `TaskSelection::TaskIds(vec![])` follows the only running task, if there's exactly one.
+/// Selected tasks are followed once they're started, while the tasks of groups are followed
+/// as long as they're running. This includes tasks that are started later on.
+/// The stream is closed once all followed tasks finished.
 #[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
 pub struct StreamRequest {
     pub tasks: TaskSelection,
     pub lines: Option<usize>,
+    /// Only stream one of the task's output streams.
+    /// This requires the task's streams to be captured separately.
+    #[serde(default)]
+    pub stream: Option<LogStream>,
+    /// Prefix each line with the time it has been printed, if the output is timestamped.
+    #[serde(default)]
+    pub timestamps: bool,
+    /// Resume the stream at these byte offsets of the tasks' output, e.g. after the connection
+    /// has been lost. Take a look at [`super::StreamResponse::offsets`].
+    /// This takes precedence over `lines`.
+    #[serde(default)]
+    pub offsets: BTreeMap<usize, Vec<u64>>,
 }
 impl_into_request!(StreamRequest, Request::Stream);
 
@@ -294,14 +387,77 @@ impl_into_request!(StreamRequest, Request::Stream);
 /// `tasks` specifies the requested tasks.
 /// `send_logs` Determines whether logs should be sent at all.
 /// `lines` Determines whether only a few lines of log should be returned.
+/// `stream` Only return one of the output streams of tasks, whose streams are captured
+/// separately.
+/// `timestamps` Prefix each line with the time it has been printed.
+/// `since` Only return lines that have been printed after this point in time.
+/// The last two only work for tasks with timestamped output.
+/// `bytes` Only return this byte range of the task's output, which allows to page through
+/// huge logs. The offsets are counted from the start of the task's whole output.
+/// `lines`, `timestamps` and `since` are ignored for byte ranges. As a range refers to a single
+/// log file, separately captured stderr is only returned if it's selected via `stream`.
+/// `streamed` Send each task in a separate [`super::Response::Log`] message, followed by a
+/// [`super::Response::Close`], so the logs of all tasks don't have to be held in memory at once.
 #[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
 pub struct LogRequest {
     pub tasks: TaskSelection,
     pub send_logs: bool,
     pub lines: Option<usize>,
+    #[serde(default)]
+    pub stream: Option<LogStream>,
+    #[serde(default)]
+    pub timestamps: bool,
+    #[serde(default)]
+    pub since: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub bytes: Option<Range<u64>>,
+    #[serde(default)]
+    pub streamed: bool,
 }
 impl_into_request!(LogRequest, Request::Log);
 
+/// Search the log output of a set of tasks for lines matching a regular expression.
+///
+/// `context` Amount of lines that're additionally returned before and after each match.
+/// `status` Only search the logs of tasks with this status.
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+pub struct GrepRequest {
+    pub pattern: String,
+    pub tasks: TaskSelection,
+    pub status: Option<TaskStatusFilter>,
+    pub context: usize,
+}
+impl_into_request!(GrepRequest, Request::Grep);
+
+/// The status of tasks, that's used to narrow down a selection of tasks.
+#[derive(
+    PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize, Display, EnumString, VariantNames,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case", ascii_case_insensitive)]
+pub enum TaskStatusFilter {
+    Queued,
+    Stashed,
+    Paused,
+    Running,
+    Success,
+    Failed,
+}
+
+impl TaskStatusFilter {
+    /// Check whether a task has the given status.
+    pub fn matches(&self, task: &Task) -> bool {
+        match self {
+            TaskStatusFilter::Queued => task.is_queued(),
+            TaskStatusFilter::Stashed => task.is_stashed(),
+            TaskStatusFilter::Paused => task.is_paused(),
+            TaskStatusFilter::Running => task.is_running(),
+            TaskStatusFilter::Success => task.is_done() && !task.failed(),
+            TaskStatusFilter::Failed => task.failed(),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
 pub struct ParallelRequest {
     pub parallel_tasks: usize,
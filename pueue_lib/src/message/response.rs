@@ -1,12 +1,12 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, ops::Range};
 
-use chrono::prelude::*;
+use chrono::{TimeDelta, prelude::*};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     message::EditableTask,
     state::{Group, State},
-    task::Task,
+    task::{LogStream, Task, priority_aging_interval},
 };
 
 /// Macro to simplify creating success_messages
@@ -55,7 +55,7 @@ pub enum Response {
     /// The daemon locked the tasks and responds with the tasks' details.
     Edit(Vec<EditableTask>),
 
-    Status(Box<State>),
+    Status(Box<StatusResponse>),
 
     /// The log returned from the daemon for a bunch of [`Task`]s
     /// This is the response to [`super::Request::Log`]
@@ -66,6 +66,10 @@ pub enum Response {
     /// The next chunk of output, that's send to the client.
     Stream(StreamResponse),
 
+    /// The matching log lines of a set of tasks, ordered by task id.
+    /// This is the response to [`super::Request::Grep`]
+    Grep(Vec<GrepTaskResponse>),
+
     Success(String),
     Failure(String),
 
@@ -80,6 +84,24 @@ impl Response {
     }
 }
 
+/// The daemon's current state together with the settings clients need to display it.
+/// This is the response to [`super::Request::Status`]
+#[derive(PartialEq, Eq, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StatusResponse {
+    pub state: State,
+    /// The daemon's `priority_aging_minutes` setting, so clients display the same effective
+    /// priorities the daemon uses for scheduling.
+    pub priority_aging_minutes: Option<u32>,
+}
+
+impl StatusResponse {
+    /// The daemon's interval after which queued tasks gain an additional point of effective
+    /// priority. See [Task::effective_priority].
+    pub fn priority_aging_interval(&self) -> Option<TimeDelta> {
+        priority_aging_interval(self.priority_aging_minutes)
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Debug, Default, Deserialize, Serialize)]
 pub struct AddedTaskResponse {
     pub task_id: usize,
@@ -95,6 +117,16 @@ pub struct TaskLogResponse {
     /// Indicates whether the log output has been truncated or not.
     pub output_complete: bool,
     pub output: Option<Vec<u8>>,
+    /// The compressed stderr of a task, whose output streams are captured separately.
+    /// `output` then only contains the task's stdout.
+    /// This is only sent if no specific stream has been requested.
+    #[serde(default)]
+    pub stderr: Option<Vec<u8>>,
+    /// The byte range of the task's output, that's contained in `output`.
+    /// This is only set, if a byte range has been requested. It's smaller than the requested
+    /// range, if the log ends earlier or the start of the range has already been dropped.
+    #[serde(default)]
+    pub bytes: Option<Range<u64>>,
 }
 impl_into_response!(BTreeMap<usize, TaskLogResponse>, Response::Log);
 
@@ -106,6 +138,8 @@ impl std::fmt::Debug for TaskLogResponse {
             .field("task", &self.task)
             .field("output_complete", &self.output_complete)
             .field("output", &"hidden")
+            .field("stderr", &"hidden")
+            .field("bytes", &self.bytes)
             .finish()
     }
 }
@@ -114,6 +148,9 @@ impl std::fmt::Debug for TaskLogResponse {
 #[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
 pub struct GroupResponse {
     pub groups: BTreeMap<String, Group>,
+    /// The amount of queued and stashed tasks of each group.
+    #[serde(default)]
+    pub queued_tasks: BTreeMap<String, usize>,
 }
 impl_into_response!(GroupResponse, Response::Group);
 
@@ -123,5 +160,36 @@ impl_into_response!(GroupResponse, Response::Group);
 #[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
 pub struct StreamResponse {
     pub logs: BTreeMap<usize, String>,
+    /// The byte offsets in the tasks' output right after the sent chunks.
+    /// There's one offset per followed log file, i.e. stdout comes before stderr, if the task's
+    /// streams are captured separately. Send them via [`super::StreamRequest::offsets`] to
+    /// resume a stream exactly where it left off.
+    #[serde(default)]
+    pub offsets: BTreeMap<usize, Vec<u64>>,
+    /// The labels of the tasks, whose output is sent, if they have one.
+    #[serde(default)]
+    pub labels: BTreeMap<usize, String>,
 }
 impl_into_response!(StreamResponse, Response::Stream);
+
+/// The lines of a task's log, that matched a [`super::GrepRequest`].
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+pub struct GrepTaskResponse {
+    pub task_id: usize,
+    pub label: Option<String>,
+    pub lines: Vec<GrepLine>,
+}
+impl_into_response!(Vec<GrepTaskResponse>, Response::Grep);
+
+/// A single line of a task's log.
+/// Lines that don't match the pattern themselves are context around a match.
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
+pub struct GrepLine {
+    /// The number of the line in the log, starting at 1.
+    pub line_number: usize,
+    pub text: String,
+    pub is_match: bool,
+    /// The stream this line has been printed to.
+    /// This is only set for tasks, whose output streams are captured separately.
+    pub stream: Option<LogStream>,
+}
@@ -46,7 +46,7 @@ use crate::{Error, PROTOCOL_VERSION, internal_prelude::*, message::*};
 /// let response: Response = client.receive_response().await?;
 ///
 /// let _state = match response {
-///     Response::Status(state) => state,
+///     Response::Status(status) => status.state,
 ///     _ => unreachable!(),
 /// };
 /// # Ok(())
@@ -26,3 +26,7 @@ pub(crate) fn default_status_datetime_format() -> String {
 pub(crate) fn default_callback_log_lines() -> usize {
     10
 }
+
+pub(crate) fn default_max_log_segments() -> usize {
+    5
+}
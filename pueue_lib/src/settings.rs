@@ -6,10 +6,15 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use shellexpand::tilde;
 
-use crate::{error::Error, internal_prelude::*, setting_defaults::*};
+use crate::{
+    error::Error,
+    internal_prelude::*,
+    setting_defaults::*,
+    task::{LogStream, priority_aging_interval},
+};
 
 /// The environment variable that can be set to overwrite pueue's config path.
 pub const PUEUE_CONFIG_PATH_ENV: &str = "PUEUE_CONFIG_PATH";
@@ -94,6 +99,123 @@ pub enum EditMode {
     Files,
 }
 
+/// The policy the daemon uses to decide which of several startable tasks is started next.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedulingPolicy {
+    /// Start the task with the highest priority first. Tasks with the same priority are started
+    /// in the order they've been added.
+    #[default]
+    Fifo,
+    /// Prefer tasks of the group that currently uses the smallest share of running tasks
+    /// relative to its weight in `group_weights`.
+    FairShare,
+    /// Take turns between labels, i.e. prefer tasks whose label has the fewest running tasks.
+    /// All tasks without a label are treated as a single label.
+    RoundRobin,
+}
+
+/// How the daemon persists its state.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StateBackend {
+    /// The whole state is written to `state.json` (or `state.json.gz`) on every change.
+    #[default]
+    Json,
+    /// Every task and group is stored as a row in `state.sqlite`.
+    /// Only rows that changed are written on every change.
+    ///
+    /// Existing `state.json` or `state.json.gz` files are migrated on startup.
+    Sqlite,
+    /// Every change is appended to `state.journal`, next to the last snapshot in `state.json`
+    /// (or `state.json.gz`). The journal is replayed on top of the snapshot on startup and
+    /// is regularly compacted into a new snapshot in the background.
+    Journal,
+}
+
+/// What happens to processes of tasks that survived a crash of the daemon.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanPolicy {
+    /// Leave the processes alone and log a warning. The task is marked as killed.
+    #[default]
+    Report,
+    /// Kill the whole process group of the task. The task is marked as killed.
+    Kill,
+    /// Leave the processes alone and mark the task as orphaned.
+    Tag,
+}
+
+/// What happens to a task, whose log file exceeds the `max_log_size`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSizePolicy {
+    /// Drop the oldest output, so only the most recent half of `max_log_size` is kept.
+    #[default]
+    Truncate,
+    /// Move the output into a numbered segment next to the log file and start anew.
+    /// Only the most recent `max_log_segments` segments are kept.
+    Rotate,
+    /// Kill the task.
+    Kill,
+}
+
+/// The declarative configuration of a single group, i.e. a `[daemon.groups.<name>]` section.
+///
+/// Declared groups are created on daemon startup, if they don't exist yet.
+/// All other fields are defaults for the group's tasks.
+#[derive(PartialEq, Eq, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct GroupSettings {
+    /// The amount of parallel tasks of this group.
+    /// This is applied on every daemon startup.
+    pub parallel_tasks: Option<usize>,
+    /// The working directory of tasks that are added without an explicit working directory.
+    pub working_directory: Option<PathBuf>,
+    /// Environment variables that are injected into all tasks of this group.
+    /// Variables that are set on the task itself take precedence.
+    #[serde(default = "Default::default")]
+    pub env_vars: HashMap<String, String>,
+    /// Overrides `daemon.shell_command` for tasks of this group.
+    pub shell_command: Option<Vec<String>>,
+    /// The priority of tasks that are added without an explicit priority.
+    pub default_priority: Option<i32>,
+    /// Overrides `daemon.pause_group_on_failure` for this group.
+    pub pause_on_failure: Option<bool>,
+    /// Overrides `daemon.max_log_size` for tasks of this group.
+    pub max_log_size: Option<u64>,
+    /// Overrides `daemon.log_size_policy` for tasks of this group.
+    pub log_size_policy: Option<LogSizePolicy>,
+}
+
+/// A group in the configuration file.
+/// Older configuration files only contain the amount of parallel tasks of each group.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GroupDeclaration {
+    ParallelTasks(usize),
+    Settings(GroupSettings),
+}
+
+fn deserialize_groups<'de, D>(deserializer: D) -> Result<HashMap<String, GroupSettings>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let groups = HashMap::<String, GroupDeclaration>::deserialize(deserializer)?;
+    Ok(groups
+        .into_iter()
+        .map(|(name, group)| {
+            let settings = match group {
+                GroupDeclaration::ParallelTasks(parallel_tasks) => GroupSettings {
+                    parallel_tasks: Some(parallel_tasks),
+                    ..Default::default()
+                },
+                GroupDeclaration::Settings(settings) => settings,
+            };
+            (name, settings)
+        })
+        .collect())
+}
+
 /// All settings which are used by the client
 #[derive(PartialEq, Eq, Clone, Debug, Deserialize, Serialize)]
 pub struct Client {
@@ -142,10 +264,13 @@ pub struct Daemon {
     /// significantly smaller file. This is particularily useful for I/O starved or embedded
     /// environments, as it trades a bit of CPU power for I/O ops.
     ///
-    /// The state tends to be quite large for many tasks, as the whole environment is copied every
-    /// time. You can expect a ~10 compression ratio.
+    /// Identical environments of tasks are only stored once, but the state can still be quite
+    /// large if tasks are added from many different shells.
     #[serde(default = "Default::default")]
     pub compress_state_file: bool,
+    /// How the state is persisted. Take a look at [StateBackend] for more info.
+    #[serde(default = "Default::default")]
+    pub state_backend: StateBackend,
     /// The callback that's called whenever a task finishes.
     pub callback: Option<String>,
     /// Environment variables that can be will be injected into all executed processes.
@@ -154,6 +279,45 @@ pub struct Daemon {
     /// The amount of log lines from stdout/stderr that are passed to the callback command.
     #[serde(default = "default_callback_log_lines")]
     pub callback_log_lines: usize,
+    /// Only pass the lines of this output stream to the callback command.
+    /// Tasks whose output streams share a single log file pass their whole output instead.
+    pub callback_log_stream: Option<LogStream>,
+    /// Capture the stderr of tasks into a separate file instead of a single log file for both
+    /// streams. The streams can then be viewed separately, but their relative order is lost.
+    #[serde(default = "Default::default")]
+    pub separate_stderr: bool,
+    /// Record the time at which each line of output of all tasks has been printed.
+    /// This can also be enabled for single tasks via `pueue add --timestamps`.
+    /// The output of tasks is then piped through the daemon, which isn't possible for tasks
+    /// that run under a supervisor. Their output isn't timestamped.
+    #[serde(default = "Default::default")]
+    pub timestamp_output: bool,
+    /// The maximum size of a task's log file in bytes.
+    /// Each output stream is limited separately, if stderr is captured separately.
+    /// Take a look at [LogSizePolicy] for what happens once a log file exceeds this size.
+    /// Logs are truncated and rotated while the output is piped through the daemon, so no output
+    /// gets lost. The output of tasks that run under a supervisor isn't piped through the daemon.
+    /// Those tasks are killed instead, once their log exceeds this size.
+    pub max_log_size: Option<u64>,
+    /// What to do with tasks, whose log file exceeds the `max_log_size`.
+    #[serde(default = "Default::default")]
+    pub log_size_policy: LogSizePolicy,
+    /// The amount of rotated segments that are kept per log file by the `rotate` policy.
+    #[serde(default = "default_max_log_segments")]
+    pub max_log_segments: usize,
+    /// Compress the logs of tasks once they're finished.
+    /// Compressed logs are decompressed one chunk at a time whenever they're read.
+    #[serde(default = "Default::default")]
+    pub compress_finished_logs: bool,
+    /// Finished tasks are removed automatically, once they've been finished for this many days.
+    pub retention_days: Option<u32>,
+    /// The amount of days after which failed tasks are removed automatically.
+    /// This allows to keep failed tasks around for longer than successful ones.
+    /// Falls back to `retention_days`, if this isn't set.
+    pub failed_retention_days: Option<u32>,
+    /// The maximum amount of finished tasks that are kept per group.
+    /// The oldest finished tasks of a group are removed automatically, once there are more.
+    pub max_finished_tasks: Option<usize>,
     /// The command that should be used for task and callback execution.
     /// The following are the only officially supported modi for Pueue.
     ///
@@ -171,6 +335,34 @@ pub struct Daemon {
     /// ];
     /// ```
     pub shell_command: Option<Vec<String>>,
+    /// The policy that decides which queued task is started next, if several tasks compete
+    /// for free slots.
+    #[serde(default = "Default::default")]
+    pub scheduling_policy: SchedulingPolicy,
+    /// The weights of groups for the `fair_share` scheduling policy.
+    /// Groups that aren't listed have a weight of `1`.
+    #[serde(default = "Default::default")]
+    pub group_weights: HashMap<String, usize>,
+    /// Priority aging for queued tasks.
+    /// Every time a task has been queued for this many minutes, its effective priority is
+    /// increased by one. Aging is disabled if this isn't set.
+    pub priority_aging_minutes: Option<u32>,
+    /// The maximum amount of tasks that may run at the same time across all groups.
+    /// If this isn't set, only the `parallel_tasks` limits of the individual groups apply.
+    pub max_running_tasks: Option<usize>,
+    /// Groups that are declared in the configuration file, including defaults for their tasks.
+    #[serde(default = "Default::default", deserialize_with = "deserialize_groups")]
+    pub groups: HashMap<String, GroupSettings>,
+    /// What to do with processes of tasks that are still alive after a crash of the daemon.
+    /// Take a look at [OrphanPolicy] for more info.
+    #[serde(default = "Default::default")]
+    pub orphan_policy: OrphanPolicy,
+    /// Run every task under a small supervisor process, which keeps the task's process and
+    /// records its exit status. Running tasks then survive restarts and upgrades of the daemon
+    /// and are re-adopted on startup. Only supported on Unix.
+    /// Their output isn't timestamped and they're killed once their log exceeds `max_log_size`.
+    #[serde(default = "Default::default")]
+    pub supervise_tasks: bool,
     pub worker_id: Option<String>,
     pub nats_host: Option<String>
 }
@@ -222,9 +414,27 @@ impl Default for Daemon {
             pause_all_on_failure: false,
             callback: None,
             callback_log_lines: default_callback_log_lines(),
+            callback_log_stream: None,
+            separate_stderr: false,
+            timestamp_output: false,
+            max_log_size: None,
+            log_size_policy: LogSizePolicy::default(),
+            max_log_segments: default_max_log_segments(),
+            compress_finished_logs: false,
+            retention_days: None,
+            failed_retention_days: None,
+            max_finished_tasks: None,
             compress_state_file: false,
+            state_backend: StateBackend::default(),
             shell_command: None,
             env_vars: HashMap::new(),
+            scheduling_policy: SchedulingPolicy::default(),
+            group_weights: HashMap::new(),
+            priority_aging_minutes: None,
+            max_running_tasks: None,
+            groups: HashMap::new(),
+            orphan_policy: OrphanPolicy::default(),
+            supervise_tasks: false,
             worker_id: None,
             nats_host: None
         }
@@ -361,6 +571,14 @@ impl Shared {
     }
 }
 
+impl Daemon {
+    /// The interval after which queued tasks gain an additional point of effective priority.
+    /// Returns `None` if priority aging is disabled.
+    pub fn priority_aging_interval(&self) -> Option<chrono::TimeDelta> {
+        priority_aging_interval(self.priority_aging_minutes)
+    }
+}
+
 impl Settings {
     /// Try to read existing config files, while using default values for non-existing fields.
     /// If successful, this will return a full config as well as a boolean on whether we found an
@@ -1,10 +1,13 @@
 //! The representation of the pueue daemon's current [State].
 //! Contains all [`Task`]s and [`Group`]s of the daemon.
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::task::Task;
+use crate::task::{Envs, Task};
 
 pub const PUEUE_DEFAULT_GROUP: &str = "default";
 
@@ -24,6 +27,14 @@ pub enum GroupStatus {
 pub struct Group {
     pub status: GroupStatus,
     pub parallel_tasks: usize,
+    /// If this is set, a queued task preempts a running task of this group, as soon as its
+    /// priority exceeds the priority of the running task by at least this margin.
+    #[serde(default)]
+    pub preemption_margin: Option<i32>,
+    /// The maximum amount of queued and stashed tasks in this group.
+    /// New tasks are rejected, once this limit is reached.
+    #[serde(default)]
+    pub max_queued: Option<usize>,
 }
 
 /// This is the full representation of the current state of the Pueue daemon.
@@ -102,6 +113,51 @@ impl State {
             .collect()
     }
 
+    /// Get the amount of tasks in a specific group that haven't been started yet.
+    /// This includes queued and stashed tasks.
+    pub fn queued_tasks_in_group(&self, group: &str) -> usize {
+        self.tasks
+            .values()
+            .filter(|task| task.group == group && task.is_pending())
+            .count()
+    }
+
+    /// Create the environment of a new task.
+    /// If another task has been added with the exact same environment, its base environment
+    /// is shared instead of storing the same environment twice.
+    pub fn intern_envs(&self, envs: HashMap<String, String>) -> Envs {
+        // Tasks from the same shell usually share a single base, so only compare each base once.
+        let mut compared = HashSet::new();
+        for task in self.tasks.values().rev() {
+            let base = task.envs.base();
+            if !compared.insert(Arc::as_ptr(base)) {
+                continue;
+            }
+
+            if **base == envs {
+                return Envs::new(base.clone(), BTreeMap::new());
+            }
+        }
+
+        Envs::from(envs)
+    }
+
+    /// Share the base environment between all tasks with identical base environments.
+    /// This is used for states that have been deserialized with a separate copy of the
+    /// environment for each task.
+    pub fn deduplicate_envs(&mut self) {
+        let mut bases: HashMap<u64, Vec<Arc<HashMap<String, String>>>> = HashMap::new();
+        for task in self.tasks.values_mut() {
+            let candidates = bases.entry(task.envs.base_hash()).or_default();
+            match candidates.iter().find(|base| ***base == **task.envs.base()) {
+                Some(base) => {
+                    task.envs = Envs::new(base.clone(), task.envs.changes().clone());
+                }
+                None => candidates.push(task.envs.base().clone()),
+            }
+        }
+    }
+
     /// This checks, whether some tasks match the expected filter criteria. \
     /// The first result is the list of task_ids that match these statuses. \
     /// The second result is the list of task_ids that don't match these statuses. \
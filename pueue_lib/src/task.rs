@@ -1,8 +1,13 @@
 //! Everything regarding Pueue's [Task]s.
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    sync::Arc,
+};
 
-use chrono::prelude::*;
-use serde::{Deserialize, Serialize};
+use chrono::{TimeDelta, prelude::*};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use strum::Display;
 
 /// This enum represents the status of the internal task handling of Pueue.
@@ -50,6 +55,178 @@ pub enum TaskResult {
     Errored,
     /// A dependency of the task failed.
     DependencyFailed,
+    /// The task has been cancelled before it was started, as it has been superseded by a newer
+    /// task with the same concurrency key.
+    Superseded,
+    /// The daemon crashed while the task was running and its processes have been left running.
+    Orphaned,
+}
+
+/// The process of a running task.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct TaskProcess {
+    /// The process id of the task's main process.
+    pub pid: u32,
+    /// The id of the process group, which contains the task's main process and its children.
+    pub process_group: u32,
+    /// The platform specific start time of the main process.
+    /// It tells the process apart from other processes that reuse its id later on.
+    #[serde(default)]
+    pub start_time: Option<u64>,
+    /// The id of the system boot, in which the process has been started.
+    #[serde(default)]
+    pub boot_id: Option<String>,
+}
+
+/// One of the two output streams of a task.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Display, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// How a running task has been made to give way to a task with a higher priority.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Display, Serialize, Deserialize)]
+pub enum PreemptionAction {
+    /// The task has been paused and will be resumed once there's capacity again.
+    Paused,
+    /// The task has been killed and put back into the queue.
+    Requeued,
+}
+
+/// A record of a single preemption of a task.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct Preemption {
+    /// The id of the queued task that caused the preemption.
+    pub preempted_by: usize,
+    /// The point in time the task has been preempted.
+    pub at: DateTime<Local>,
+    pub action: PreemptionAction,
+    /// The point in time the task has been resumed or requeued.
+    /// This is `None` as long as the preemption is still in effect.
+    pub resumed_at: Option<DateTime<Local>>,
+}
+
+/// The environment variables of a task.
+///
+/// Most tasks are added from the same shell and thereby have the exact same environment.
+/// That's why the environment is split into a base, which is shared between all tasks with the
+/// same environment, and the task specific changes on top of it.
+///
+/// It's (de)serialized as a plain map of all variables.
+#[derive(Clone, Debug, Default)]
+pub struct Envs {
+    base: Arc<HashMap<String, String>>,
+    /// Variables that have been set or removed (`None`) on top of the base.
+    changes: BTreeMap<String, Option<String>>,
+}
+
+impl Envs {
+    pub fn new(
+        base: Arc<HashMap<String, String>>,
+        changes: BTreeMap<String, Option<String>>,
+    ) -> Self {
+        Envs { base, changes }
+    }
+
+    /// The shared base environment.
+    pub fn base(&self) -> &Arc<HashMap<String, String>> {
+        &self.base
+    }
+
+    /// All variables that have been set or removed (`None`) on top of the base.
+    pub fn changes(&self) -> &BTreeMap<String, Option<String>> {
+        &self.changes
+    }
+
+    /// A hash of the base environment, which is independent of the order of the variables.
+    pub fn base_hash(&self) -> u64 {
+        let mut variables: Vec<_> = self.base.iter().collect();
+        variables.sort();
+
+        let mut hasher = DefaultHasher::new();
+        variables.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        match self.changes.get(key) {
+            Some(value) => value.as_ref(),
+            None => self.base.get(key),
+        }
+    }
+
+    /// Set a variable. Returns the previous value, if there was one.
+    pub fn insert(&mut self, key: String, value: String) -> Option<String> {
+        let previous = self.get(&key).cloned();
+        self.changes.insert(key, Some(value));
+        previous
+    }
+
+    /// Remove a variable. Returns the previous value, if there was one.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let previous = self.get(key).cloned()?;
+        if self.base.contains_key(key) {
+            self.changes.insert(key.to_string(), None);
+        } else {
+            self.changes.remove(key);
+        }
+
+        Some(previous)
+    }
+
+    /// Iterate over all variables.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.base
+            .iter()
+            .filter(|(key, _)| !self.changes.contains_key(*key))
+            .chain(
+                self.changes
+                    .iter()
+                    .filter_map(|(key, value)| value.as_ref().map(|value| (key, value))),
+            )
+    }
+
+    pub fn to_map(&self) -> HashMap<String, String> {
+        self.iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+impl From<HashMap<String, String>> for Envs {
+    fn from(base: HashMap<String, String>) -> Self {
+        Envs {
+            base: Arc::new(base),
+            changes: BTreeMap::new(),
+        }
+    }
+}
+
+impl PartialEq for Envs {
+    fn eq(&self, other: &Self) -> bool {
+        if Arc::ptr_eq(&self.base, &other.base) && self.changes == other.changes {
+            return true;
+        }
+
+        self.to_map() == other.to_map()
+    }
+}
+
+impl Eq for Envs {}
+
+impl Serialize for Envs {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for Envs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(Envs::from)
+    }
 }
 
 /// Representation of a task.
@@ -60,12 +237,35 @@ pub struct Task {
     pub original_command: String,
     pub command: String,
     pub path: PathBuf,
-    pub envs: HashMap<String, String>,
+    pub envs: Envs,
     pub group: String,
     pub dependencies: Vec<usize>,
     pub priority: i32,
+    /// The position of the task in its group's execution order, which takes precedence over
+    /// its priority. It's only changed, when a task is moved to the front or back of its group.
+    /// Tasks with a higher position are started first.
+    #[serde(default)]
+    pub queue_position: i64,
     pub label: Option<String>,
     pub status: TaskStatus,
+    /// Whether the task may be killed and requeued, if it's preempted by a task with a higher
+    /// priority. Otherwise, preempted tasks are paused.
+    #[serde(default)]
+    pub restartable: bool,
+    /// All preemptions this task experienced, so users can see why their task stopped.
+    #[serde(default)]
+    pub preemptions: Vec<Preemption>,
+    /// Tasks with the same concurrency key never run at the same time, even if they're in
+    /// different groups.
+    #[serde(default)]
+    pub concurrency_key: Option<String>,
+    /// The process of the task, while it's running.
+    /// This is used to detect processes that survived a crash of the daemon.
+    #[serde(default)]
+    pub process: Option<TaskProcess>,
+    /// Record the time at which each line of the task's output has been printed.
+    #[serde(default)]
+    pub timestamps: bool,
 }
 
 impl Task {
@@ -86,12 +286,18 @@ impl Task {
             original_command: original_command.clone(),
             command: original_command,
             path,
-            envs,
+            envs: envs.into(),
             group,
             dependencies,
             priority,
+            queue_position: 0,
             label,
             status: starting_status.clone(),
+            restartable: false,
+            preemptions: Vec::new(),
+            concurrency_key: None,
+            process: None,
+            timestamps: false,
         }
     }
 
@@ -104,6 +310,27 @@ impl Task {
         }
     }
 
+    /// The priority that's used to determine the execution order of queued tasks.
+    ///
+    /// If an `aging_interval` is given, a queued task gains one additional point of priority
+    /// for every full interval it has been waiting in the queue. This prevents tasks with a low
+    /// priority from starving, if there's a constant stream of tasks with a higher priority.
+    pub fn effective_priority(&self, aging_interval: Option<TimeDelta>) -> i32 {
+        let (Some(interval), TaskStatus::Queued { enqueued_at }) = (aging_interval, &self.status)
+        else {
+            return self.priority;
+        };
+        if interval <= TimeDelta::zero() {
+            return self.priority;
+        }
+
+        let waited = Local::now() - *enqueued_at;
+        let steps = waited.num_seconds() / interval.num_seconds().max(1);
+        let steps = i32::try_from(steps.max(0)).unwrap_or(i32::MAX);
+
+        self.priority.saturating_add(steps)
+    }
+
     /// Whether the task is having a running process managed by the TaskHandler
     pub fn is_running(&self) -> bool {
         matches!(
@@ -117,6 +344,14 @@ impl Task {
         matches!(self.status, TaskStatus::Paused { .. })
     }
 
+    /// Whether the task has been preempted by a task with a higher priority and hasn't been
+    /// resumed or requeued yet.
+    pub fn is_preempted(&self) -> bool {
+        self.preemptions
+            .last()
+            .is_some_and(|preemption| preemption.resumed_at.is_none())
+    }
+
     /// Whether the task's process finished.
     pub fn is_done(&self) -> bool {
         matches!(self.status, TaskStatus::Done { .. })
@@ -148,6 +383,24 @@ impl Task {
                 }
         )
     }
+
+    /// Whether the task is queued or stashed, i.e. it hasn't been started yet.
+    /// Those tasks count towards the `max_queued` limit of their group.
+    pub fn is_pending(&self) -> bool {
+        matches!(
+            self.status,
+            TaskStatus::Queued { .. } | TaskStatus::Stashed { .. }
+        )
+    }
+}
+
+/// Convert the daemon's `priority_aging_minutes` setting into an interval.
+/// Returns `None` if priority aging is disabled.
+pub(crate) fn priority_aging_interval(minutes: Option<u32>) -> Option<TimeDelta> {
+    match minutes {
+        None | Some(0) => None,
+        Some(minutes) => TimeDelta::try_minutes(i64::from(minutes)),
+    }
 }
 
 /// We use a custom `Debug` implementation for [Task], as the `envs` field just has too much
@@ -168,6 +421,12 @@ impl std::fmt::Debug for Task {
             .field("label", &self.label)
             .field("status", &self.status)
             .field("priority", &self.priority)
+            .field("queue_position", &self.queue_position)
+            .field("restartable", &self.restartable)
+            .field("preemptions", &self.preemptions)
+            .field("concurrency_key", &self.concurrency_key)
+            .field("process", &self.process)
+            .field("timestamps", &self.timestamps)
             .finish()
     }
 }